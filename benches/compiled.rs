@@ -0,0 +1,71 @@
+// Times the executables `rorth compile` builds from every program in
+// `benches/`, with and without `-O2`: a warm up run, then SAMPLES timed runs
+// reported as their mean and standard deviation.
+//
+//     cargo bench --bench compiled                 all programs
+//     cargo bench --bench compiled -- arithmetic   programs whose name contains `arithmetic`
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+const SAMPLES: usize = 10;
+
+// builds `program` into the target temporary directory, None if it couldn't
+fn build(rorth: &str, program: &Path, optimize: bool) -> Option<PathBuf> {
+    let name = program.file_stem().unwrap().to_string_lossy().into_owned();
+    let executable = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}-{}", name, if optimize { "O2" } else { "O0" }));
+    let mut command = Command::new(rorth);
+    command.arg("compile").arg(program).arg("-o").arg(&executable);
+    if optimize {
+        command.arg("-O2");
+    }
+    let status = command.stdout(Stdio::null()).status().expect("Could not run rorth");
+    status.success().then_some(executable)
+}
+
+// seconds taken by one run of `executable`
+fn run(executable: &Path) -> f64 {
+    let start = Instant::now();
+    let status = Command::new(executable)
+        .stdout(Stdio::null())
+        .status()
+        .expect("Could not run the executable");
+    assert!(status.success(), "{} failed", executable.display());
+    start.elapsed().as_secs_f64()
+}
+
+// mean and standard deviation of SAMPLES runs, after a warm up run
+fn measure(executable: &Path) -> (f64, f64) {
+    run(executable);
+    let samples: Vec<f64> = (0..SAMPLES).map(|_| run(executable)).collect();
+    let mean = samples.iter().sum::<f64>() / SAMPLES as f64;
+    let deviation = (samples.iter().map(|sample| (sample - mean).powi(2)).sum::<f64>() / SAMPLES as f64).sqrt();
+    (mean, deviation)
+}
+
+fn main() {
+    let rorth = env!("CARGO_BIN_EXE_rorth");
+    let filters: Vec<String> = std::env::args().skip(1).filter(|arg| !arg.starts_with("--")).collect();
+    let mut programs: Vec<_> = std::fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("benches"))
+        .expect("Could not read benches")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "rorth"))
+        .collect();
+    programs.sort();
+    for program in programs {
+        let name = program.file_stem().unwrap().to_string_lossy().into_owned();
+        if !filters.is_empty() && !filters.iter().any(|filter| name.contains(filter.as_str())) {
+            continue;
+        }
+        let (Some(plain), Some(optimized)) = (build(rorth, &program, false), build(rorth, &program, true)) else {
+            println!("{:<16} skipped, could not compile", name);
+            continue;
+        };
+        let (plain_mean, plain_deviation) = measure(&plain);
+        let (optimized_mean, optimized_deviation) = measure(&optimized);
+        println!("{:<16} {:>9.3} ms ± {:>7.3} ms  -O2 {:>9.3} ms ± {:>7.3} ms  ({:+.1}%)", name,
+            plain_mean * 1000.0, plain_deviation * 1000.0, optimized_mean * 1000.0, optimized_deviation * 1000.0,
+            (optimized_mean / plain_mean - 1.0) * 100.0);
+    }
+}
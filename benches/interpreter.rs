@@ -0,0 +1,63 @@
+// Times `rorth interpret` on every program in `benches/`, criterion style: a
+// warm up run, then SAMPLES timed runs reported as their mean and standard
+// deviation, compared with the means of the previous `cargo bench`.
+//
+//     cargo bench                  all programs
+//     cargo bench -- loops         programs whose name contains `loops`
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+const SAMPLES: usize = 10;
+
+// seconds taken by one run of `rorth interpret program`
+fn run(rorth: &str, program: &Path) -> f64 {
+    let start = Instant::now();
+    let status = Command::new(rorth)
+        .arg("interpret")
+        .arg(program)
+        .stdout(Stdio::null())
+        .status()
+        .expect("Could not run rorth");
+    assert!(status.success(), "{} failed", program.display());
+    start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    let rorth = env!("CARGO_BIN_EXE_rorth");
+    let filters: Vec<String> = std::env::args().skip(1).filter(|arg| !arg.starts_with("--")).collect();
+    let baseline_file = Path::new(env!("CARGO_TARGET_TMPDIR")).join("interpreter-baseline.txt");
+    // `name mean` lines
+    let baseline: HashMap<String, f64> = std::fs::read_to_string(&baseline_file).unwrap_or_default().lines()
+        .filter_map(|line| line.split_once(' '))
+        .filter_map(|(name, mean)| Some((name.to_string(), mean.parse().ok()?)))
+        .collect();
+    let mut programs: Vec<_> = std::fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("benches"))
+        .expect("Could not read benches")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "rorth"))
+        .collect();
+    programs.sort();
+    let mut means = baseline.clone();
+    for program in programs {
+        let name = program.file_stem().unwrap().to_string_lossy().into_owned();
+        if !filters.is_empty() && !filters.iter().any(|filter| name.contains(filter.as_str())) {
+            continue;
+        }
+        run(rorth, &program);
+        let samples: Vec<f64> = (0..SAMPLES).map(|_| run(rorth, &program)).collect();
+        let mean = samples.iter().sum::<f64>() / SAMPLES as f64;
+        let deviation = (samples.iter().map(|sample| (sample - mean).powi(2)).sum::<f64>() / SAMPLES as f64).sqrt();
+        let change = match baseline.get(&name) {
+            Some(previous) => format!("{:+.1}% since the last run", (mean / previous - 1.0) * 100.0),
+            None => String::from("no baseline"),
+        };
+        println!("{:<16} {:>9.3} ms ± {:>7.3} ms  ({})", name, mean * 1000.0, deviation * 1000.0, change);
+        means.insert(name, mean);
+    }
+    let mut lines: Vec<String> = means.iter().map(|(name, mean)| format!("{} {}", name, mean)).collect();
+    lines.sort();
+    std::fs::write(&baseline_file, lines.join("\n") + "\n").expect("Could not write the baseline");
+}
@@ -2,18 +2,23 @@
 #include <stdint.h>
 #include <unistd.h>
 
-void dump(uint64_t val) {
-    char buffer[std::numeric_limits<uint64_t>::digits10+1+1];
+void dump(int64_t val) {
+    char buffer[std::numeric_limits<uint64_t>::digits10+1+1+1];
     std::size_t buf_size = 1;
     buffer[sizeof(buffer) - buf_size] = '\n';
+    uint64_t uval = val < 0 ? -(uint64_t)val : val;
     do {
-        buffer[sizeof(buffer) - buf_size - 1] = val % 10 + '0';
+        buffer[sizeof(buffer) - buf_size - 1] = uval % 10 + '0';
         buf_size++;
-        val /= 10;
-    }while(val);
+        uval /= 10;
+    }while(uval);
+    if (val < 0) {
+        buffer[sizeof(buffer) - buf_size - 1] = '-';
+        buf_size++;
+    }
     write(1, &buffer[sizeof(buffer) - buf_size], buf_size);
 }
 
 int main() {
-    dump(18446744073709551615U);
-}
\ No newline at end of file
+    dump(std::numeric_limits<int64_t>::min());
+}
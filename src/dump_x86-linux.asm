@@ -1,10 +1,15 @@
-dump(unsigned long):
+dump(long):
         sub     rsp, 40
         mov     rsi, rdi
+        mov     r11, rdi
+        test    rdi, rdi
+        jns     .L1
+        neg     rsi
+.L1:
         movabs  r10, -3689348814741910323
-        mov     BYTE PTR [rsp+20], 10
-        lea     rcx, [rsp+19]
-        lea     r8, [rsp+21]
+        mov     BYTE PTR [rsp+32], 10
+        lea     rcx, [rsp+31]
+        lea     r8, [rsp+33]
 .L2:
         mov     rax, rsi
         mov     r9, r8
@@ -23,9 +28,15 @@ dump(unsigned long):
         sub     rcx, 1
         cmp     rax, 9
         ja      .L2
+        test    r11, r11
+        jns     .L3
+        mov     BYTE PTR [rcx], 45
+        mov     rdx, rcx
+        add     r9, 1
+.L3:
         sub     rdx, r8
         mov     edi, 1
-        lea     rsi, [rsp+21+rdx]
+        lea     rsi, [rsp+33+rdx]
         mov     rdx, r9
         call    write
         add     rsp, 40
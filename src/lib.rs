@@ -0,0 +1,8344 @@
+use std::{env};
+use std::fs::File;
+use std::io::Write;
+use std::process;
+use std::process::{Command, Stdio};
+use std::os::unix::process::ExitStatusExt;
+use std::io;
+use std::io::BufRead;
+use std::io::IsTerminal;
+use std::collections::{HashMap, HashSet};
+
+const NAME: &str = env!("CARGO_PKG_NAME");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// the discriminants are part of the binary bytecode and cache formats,
+// so new opcodes go at the end
+#[allow(non_camel_case_types)]
+#[derive(PartialEq, Eq, Debug, Clone)]
+enum Opcode {
+    OP_PUSH,
+    OP_ADD,
+    OP_SUB,
+    OP_MUL,
+    OP_DIV,
+    OP_MOD,
+    OP_DIVMOD,
+    OP_NOT,
+    OP_EQ,
+    OP_NE,
+    OP_GT,
+    OP_LT,
+    OP_GE,
+    OP_LE,
+    OP_SHR,
+    OP_SHL,
+    OP_BOR,
+    OP_BAND,
+    OP_DUP,
+    OP_GLOBAL,
+    OP_LOAD,
+    OP_STORE,
+    OP_DUMP,
+    OP_EPRINT,
+    OP_DUMPQ,
+    OP_IF,
+    OP_ELSE,
+    OP_END,
+    OP_WHILE,
+    OP_DO,
+    OP_POW,
+    OP_SQRT,
+    OP_GCD,
+    OP_BSWAP16,
+    OP_BSWAP32,
+    OP_BSWAP64,
+    OP_POPCOUNT,
+    OP_CLZ,
+    OP_CTZ,
+    OP_SWAP,
+    OP_ROT,
+    OP_2DUP,
+    OP_2DROP,
+    OP_2SWAP,
+    OP_2OVER,
+    OP_CALL,
+    OP_PICK,
+    OP_ROLL,
+    OP_DEPTH,
+    OP_BXOR,
+    OP_ROTL,
+    OP_ROTR,
+    OP_STRING,
+    OP_CONCAT,
+    OP_SLICE,
+    OP_STRLEN,
+    OP_PUTS,
+    OP_NEG,
+    OP_ABS,
+    OP_INC,
+    OP_DEC,
+    OP_AND,
+    OP_OR,
+    OP_BNOT,
+}
+
+impl Opcode {
+    // every opcode, in declaration order, so that `Opcode::ALL[op as usize] == op`
+    const ALL: &'static [Opcode] = &[
+        Opcode::OP_PUSH, Opcode::OP_ADD, Opcode::OP_SUB, Opcode::OP_MUL, Opcode::OP_DIV,
+        Opcode::OP_MOD, Opcode::OP_DIVMOD, Opcode::OP_NOT, Opcode::OP_EQ, Opcode::OP_NE,
+        Opcode::OP_GT, Opcode::OP_LT, Opcode::OP_GE, Opcode::OP_LE, Opcode::OP_SHR,
+        Opcode::OP_SHL, Opcode::OP_BOR, Opcode::OP_BAND, Opcode::OP_DUP, Opcode::OP_GLOBAL,
+        Opcode::OP_LOAD, Opcode::OP_STORE, Opcode::OP_DUMP, Opcode::OP_EPRINT, Opcode::OP_DUMPQ,
+        Opcode::OP_IF, Opcode::OP_ELSE, Opcode::OP_END, Opcode::OP_WHILE, Opcode::OP_DO,
+        Opcode::OP_POW, Opcode::OP_SQRT, Opcode::OP_GCD, Opcode::OP_BSWAP16, Opcode::OP_BSWAP32,
+        Opcode::OP_BSWAP64, Opcode::OP_POPCOUNT, Opcode::OP_CLZ, Opcode::OP_CTZ,
+        Opcode::OP_SWAP, Opcode::OP_ROT, Opcode::OP_2DUP, Opcode::OP_2DROP, Opcode::OP_2SWAP,
+        Opcode::OP_2OVER, Opcode::OP_CALL, Opcode::OP_PICK, Opcode::OP_ROLL,
+        Opcode::OP_DEPTH, Opcode::OP_BXOR, Opcode::OP_ROTL, Opcode::OP_ROTR,
+        Opcode::OP_STRING, Opcode::OP_CONCAT, Opcode::OP_SLICE, Opcode::OP_STRLEN, Opcode::OP_PUTS,
+        Opcode::OP_NEG, Opcode::OP_ABS, Opcode::OP_INC, Opcode::OP_DEC,
+        Opcode::OP_AND, Opcode::OP_OR, Opcode::OP_BNOT,
+    ];
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum EmitKind {
+    Bytecode(BytecodeFormat),
+    // objdump disassembly of the executable annotated with source lines
+    Listing,
+    // JSON mapping the labels of the generated assembly to source locations
+    SourceMap,
+    // addresses and sizes of everything reserved in `.bss`
+    Layout,
+    // JSON of the instructions with their jumps and locations, and the symbols
+    JsonIr,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum BytecodeFormat {
+    Text,
+    Json,
+    Binary,
+}
+
+#[derive(Debug, Clone)]
+struct CompileOptions {
+    // collect the output of `dump` in a buffer, written at exit or when nearly full
+    buffer_output: bool,
+    // trap with the location of the failing instruction on underflow, division by zero or a bad address
+    runtime_checks: bool,
+    // object files and archives passed on to `ld`
+    link_objects: Vec<String>,
+    // path of the annotated listing to write after assembling, if any
+    listing: Option<String>,
+    // path of the source map to write along the assembly, if any
+    source_map: Option<String>,
+    // path of the `.bss` layout to write along the assembly, if any
+    layout: Option<String>,
+    // name the labels of if/else/while blocks after them, or only number instructions
+    mangle: Mangle,
+    // assembler the generated code is written for
+    asm_syntax: AsmSyntax,
+    // report every phase of the build and the tools it runs
+    verbose: bool,
+    // command running the executable with `-r`, for targets the host can't run
+    run_with: Option<String>,
+    // bytes of a data stack mapped at startup, with a guard page faulting on
+    // overflow, instead of the stack of the process
+    data_stack: Option<usize>,
+    // move pops up to the push of their value, `-O2`
+    schedule: bool,
+    // hash of the source files, recorded in the metadata of the executable
+    source_hash: Option<u64>,
+    // name of the backend generating the executable, None for the native one
+    backend: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Mangle {
+    Readable,
+    Plain,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions { buffer_output: true, runtime_checks: false, link_objects: Vec::new(), listing: None, source_map: None, layout: None,
+            mangle: Mangle::Readable, asm_syntax: AsmSyntax::Nasm,
+            verbose: false, run_with: None, data_stack: None, schedule: false, source_hash: None, backend: None }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+struct Loc {
+    file: String,
+    row: usize,
+    col: usize
+}
+
+#[derive(Debug, Clone)]
+struct Instruction {
+    opcode: Opcode,
+    operands: Vec<i64>,
+    ip: usize,
+    loc: Loc
+}
+
+impl Instruction {
+    fn new(opcode: Opcode, operands: Vec<i64>, ip: usize) -> Self {
+        Instruction { opcode, operands, ip, loc: Loc::default() }
+    }
+}
+
+#[derive(Debug)]
+struct Token {
+    tok: String,
+    file: String,
+    row: usize,
+    col: usize,
+    // warnings silenced by a `// rorth: allow(...)` pragma right before it
+    allow: Vec<String>
+}
+
+impl Token {
+    fn new(tok: String, file: &str, row: usize, col: usize) -> Self {
+        Token { tok, file: file.to_string(), row, col, allow: Vec::new() }
+    }
+
+    fn loc(&self) -> Loc {
+        Loc { file: self.file.clone(), row: self.row, col: self.col }
+    }
+}
+
+fn usage() {
+    println!("{} v{}", NAME.to_uppercase(), VERSION);
+    println!("A Forth-like programming language written in Rust");
+    println!("\nUSAGE:");
+    println!("    {} <SUBCOMMAND> [OPTIONS]", env::current_exe().unwrap().file_name().unwrap().to_str().unwrap());
+    println!("\nOPTIONS:");
+    println!("    -h, --help                            Print this message");
+    println!("    -b, --bytecode                        Dump bytecode to stdout and to <FILE>.bytecode");
+    println!("    --emit bytecode[:FORMAT][=PATH]       Write bytecode to PATH (default: <FILE>.bytecode)");
+    println!("                                          FORMAT is one of text (default), json, binary");
+    println!("    --emit listing[=PATH]                 Write the disassembly annotated with source lines to PATH");
+    println!("                                          (default: <OUT_FILE>.lst), compile only");
+    println!("    --emit source-map[=PATH]              Write a JSON map from assembly labels to source locations to PATH");
+    println!("                                          (default: <OUT_FILE>.asm.map), compile only");
+    println!("    --emit layout[=PATH]                  Write the address, size and label of every .bss region to PATH");
+    println!("                                          (default: <OUT_FILE>.layout), compile only");
+    println!("    --emit json-ir[=PATH]                 Write the instructions, their locations and the symbols as JSON to PATH");
+    println!("                                          (default: <FILE>.ir.json)");
+    println!("    -D <NAME>[=VALUE]                     Makes NAME a constant pushing VALUE (default: 1)");
+    println!("    -v, --verbose                         Report the source files and every phase of the build on stderr");
+    println!("    --dump-ir[=PHASE]                     Print the bytecode with jump targets and source tokens, colored on a terminal,");
+    println!("                                          as it is after PHASE: {} (default: final)", IR_PHASES.join(", "));
+    println!("    --profile-data <PROFILE>              Show the execution counts of PROFILE in `--dump-ir`, marking hot loops");
+    println!("    --dump-radix <2|10|16>                Print numbers with `dump` in this radix until a `base!` (default: 10)");
+    println!("    --cache <DIR>                         Reuse the parsed program stored in DIR if the sources are unchanged");
+    println!("    --progress                            Report every phase as it finishes, with token or instruction counts");
+    println!("                                          and throughput, on stderr");
+    println!("    --warn-size <N>                       Warn about the memory used by programs over N instructions");
+    println!("                                          (default: {})", DEFAULT_WARN_SIZE);
+    println!("    --warn-stack-depth <N>                Warn if the stack can get deeper than N values, or if its depth");
+    println!("                                          can't be bounded without running the program");
+    println!("    --line-comment <PREFIX>               Also start a comment running to the end of the line with PREFIX,");
+    println!("                                          e.g. `#` or `\\`, can be given more than once (`//` always does)");
+    println!("\nSUBCOMMANDS:");
+    println!("    interpret <FILE>... [-d]              Interprets source files FILE, concatenated in order");
+    println!("        -d, --debug                       Debug mode");
+    println!("        --profile <PROFILE>               Write how many times every instruction ran to PROFILE");
+    println!("        --record <TRACE>                  Record every step of the execution to TRACE");
+    println!("        --replay <TRACE>                  Debug mode, replaying the steps recorded in TRACE");
+    println!("        --mem-stats                       Print peak stack depth and instructions executed at exit");
+    println!("        --checkpoint <FILE>               Save the interpreter state to FILE and stop, see --checkpoint-after");
+    println!("        --checkpoint-after <STEPS>        Number of instructions to run before saving the checkpoint");
+    println!("        --resume <FILE>                   Resume from the interpreter state saved in FILE");
+    println!("        --mem-check                       Warn about reads of globals that were never written");
+    println!("        --bits <32|64>                    Size of a word in bits (default: 64)");
+    println!("        --explain-bytecode                Explain every step and show the stack after it, for learning");
+    println!("        --fuse-loops                      Jump from the end of a while straight to its condition");
+    println!("        --strict                          Fail if values are left on the stack when the program ends");
+    println!("        --strings                         Allow garbage collected string values, not supported by `compile`");
+    println!("        --max-errors <N>                  Stop reporting errors after the first N (default: 20)");
+    println!("    compile <FILE>... [-r] [-o OUT_FILE]  Compiles source files FILE into native code");
+    println!("        -r, --run                         Runs program after compiling");
+    println!("        --run-with <CMD>                  Runs the program with CMD, e.g. an emulator, instead of directly");
+    println!("        -o, --output                      Name of the executable (default: out)");
+    println!("        --no-buffer                       Write the output of the program immediately, without buffering");
+    println!("        --link <OBJ>                      Link the object file or archive OBJ into the executable");
+    println!("        --runtime-checks                  Trap on stack underflow and bad `@`/`!`");
+    println!("        --data-stack <SIZE>               Keep the stack in SIZE bytes mapped at startup, faulting on overflow");
+    println!("        -O2                               Reorder instructions so values skip the stack where possible");
+    println!("        --mangle <readable|plain>         Also label blocks like `.if_7_else`, or only `.addr_N` (default: readable)");
+    println!("        --asm-syntax <nasm|gas>           Generate assembly for nasm or GNU as (default: nasm)");
+    println!("        --backend <NAME>                  Generate code with the executable `{}NAME` in PATH, which is", BACKEND_PREFIX);
+    println!("                                          given the program as JSON on stdin (default: {})", NATIVE_BACKEND);
+    println!("        --timings                         Print the time spent in each phase of the build");
+    println!("        -- <ARGS>...                      Arguments passed to the program when running it");
+    println!("    highlight <FILE>...                   Prints source files FILE as syntax highlighted HTML");
+    println!("    lint <FILE>...                        Warns about code that does nothing or is likely a mistake, a");
+    println!("                                          `// rorth: allow(NAME)` comment silences NAME on the next line");
+    println!("    verify-ir <FILE>...                   Checks the jumps, operands and blocks of every instruction of the binary");
+    println!("                                          bytecode or source files FILE, failing if any is malformed");
+    println!("    analyze <FILE>...                     Estimates the cost of every loop, assuming each runs {} times", ASSUMED_TRIPS);
+    println!("    minimize <FILE> [-o OUT] -- <CMD>...  Removes code from FILE while CMD, run with the path of the");
+    println!("                                          reduced program appended, exits with 0 (default OUT: <FILE>.min)");
+    println!("    fuzz-gen                              Prints a random valid program");
+    println!("        --seed <N>                        Seed of the generator (default: 0)");
+    println!("        --size <N>                        Number of statements at the top level (default: {})", FuzzOptions::default().size);
+    println!("        --depth <N>                       Maximum nesting of blocks (default: {})", FuzzOptions::default().depth);
+    println!("        --features <LIST>                 Comma separated, from {} (default: all)", FUZZ_FEATURES.join(","));
+    println!("    init <DIR>                            Creates a new project in directory DIR");
+    println!("    inspect <BIN>                         Prints the version, target, optimization level and source hash");
+    println!("                                          recorded in the executable BIN by `compile`");
+    println!("    serve [ADDR]                          Serves a playground web page (default: {})", SERVE_ADDR);
+    println!("    batch                                 Interprets the line delimited JSON requests on stdin, e.g.");
+    println!("                                          {{\"id\": 1, \"source\": \"1 2 + dump\", \"max_steps\": 100}}, and prints a");
+    println!("                                          line of JSON with the output and exit code of each. Limits");
+    println!("                                          default to {} steps and {} bytes of output. A `stdin`", BATCH_MAX_STEPS, BATCH_MAX_OUTPUT);
+    println!("                                          string is accepted but no word reads it yet");
+    println!("\nDEFAULTS:");
+    println!("    Options are first read from `flags = [..]` under `[defaults]` in the nearest rorth.toml, then from");
+    println!("    the RORTH_FLAGS environment variable, options on the command line take precedence over both");
+}
+
+// the `rorth` command line, the binary only calls this
+pub fn run_cli() {
+
+    let mut comp : bool = false;
+    let mut interp : bool = false;
+    let mut highlight : bool = false;
+    let mut serve : bool = false;
+    let mut batch_mode : bool = false;
+    let mut init_dir : Option<String> = None;
+    let mut fuzz_gen : bool = false;
+    let mut minimize : bool = false;
+    let mut analyze : bool = false;
+    let mut lint : bool = false;
+    let mut verify_ir : bool = false;
+    let mut fuzz_options = FuzzOptions::default();
+    let mut fuzz_option_next : Option<String> = None;
+    let mut init_dir_next : bool = false;
+    let mut inspect_file : Option<String> = None;
+    let mut inspect_file_next : bool = false;
+    let mut show_timings : bool = false;
+    let mut show_progress : bool = false;
+    let mut warn_size : usize = DEFAULT_WARN_SIZE;
+    let mut warn_size_next : bool = false;
+    let mut warn_stack_depth : Option<usize> = None;
+    let mut warn_stack_depth_next : bool = false;
+    let mut lex_options = LexOptions::default();
+    let mut line_comment_next : bool = false;
+    let mut mem_check : bool = false;
+    let mut mem_stats : bool = false;
+    let mut link_object_next : bool = false;
+    let mut cache_dir : Option<String> = None;
+    let mut cache_dir_next : bool = false;
+    let mut serve_addr : String = String::from(SERVE_ADDR);
+    let mut serve_addr_next : bool = false;
+    let mut run_prog : bool = false;
+    let mut dump_bc : bool = false;
+    let mut dump_ir : Option<String> = None;
+    let mut defines : Vec<(String, i64)> = Vec::new();
+    let mut define_next : bool = false;
+    let mut emit_bc : Option<(BytecodeFormat, Option<String>)> = None;
+    let mut emit_next : bool = false;
+    let mut emit_listing : Option<Option<String>> = None;
+    let mut emit_source_map : Option<Option<String>> = None;
+    let mut emit_layout : Option<Option<String>> = None;
+    let mut emit_json_ir : Option<Option<String>> = None;
+    let mut mangle_next : bool = false;
+    let mut asm_syntax_next : bool = false;
+    let mut backend_next : bool = false;
+    let mut run_with_next : bool = false;
+    let mut debug_mode : bool = false;
+    let mut compile_options = CompileOptions::default();
+    let mut record_file : Option<String> = None;
+    let mut record_file_next : bool = false;
+    let mut profile_file : Option<String> = None;
+    let mut profile_file_next : bool = false;
+    let mut profile_data : Option<String> = None;
+    let mut profile_data_next : bool = false;
+    let mut replay_file : Option<String> = None;
+    let mut replay_file_next : bool = false;
+    let mut checkpoint_file : Option<String> = None;
+    let mut checkpoint_file_next : bool = false;
+    let mut checkpoint_after : Option<u64> = None;
+    let mut checkpoint_after_next : bool = false;
+    let mut resume_file : Option<String> = None;
+    let mut resume_file_next : bool = false;
+    let mut bits : u32 = 64;
+    let mut strict : bool = false;
+    let mut strings : bool = false;
+    let mut max_errors : usize = DEFAULT_MAX_ERRORS;
+    let mut max_errors_next : bool = false;
+    let mut fuse_loops : bool = false;
+    let mut explain : bool = false;
+    let mut bits_next : bool = false;
+    let mut data_stack_next : bool = false;
+    let mut dump_radix : Option<i64> = None;
+    let mut dump_radix_next : bool = false;
+    let mut exec_file: String = String::from("out");
+    let mut source_files : Vec<String> = Vec::new();
+    let mut source_file_next : bool = false;
+    let mut exec_file_next : bool = false;
+    let mut exec_file_set : bool = false;
+    let mut prog_args : Vec<String> = Vec::new();
+    let mut prog_args_next : bool = false;
+
+    let defaults = default_flags().unwrap_or_else(|err| {
+        eprintln!("[ERROR] {}", err);
+        process::exit(1);
+    });
+    let args : Vec<String> = env::args().take(1).chain(defaults).chain(env::args().skip(1)).collect();
+    for arg in args {
+        if prog_args_next {
+            prog_args.push(arg);
+            continue;
+        }
+        if arg == "--" {
+            prog_args_next = true;
+            continue;
+        }
+        if arg == "-h" || arg == "--help" {
+            usage();
+            process::exit(0);
+        }
+        if arg == "compile" {
+            comp = true;
+            source_file_next = true;
+            continue;
+        }
+        if arg == "interpret" {
+            interp = true;
+            source_file_next = true;
+            continue;
+        }
+        if arg == "highlight" {
+            highlight = true;
+            source_file_next = true;
+            continue;
+        }
+        if arg == "lint" {
+            lint = true;
+            source_file_next = true;
+            continue;
+        }
+        if arg == "verify-ir" {
+            verify_ir = true;
+            source_file_next = true;
+            continue;
+        }
+        if arg == "analyze" {
+            analyze = true;
+            source_file_next = true;
+            continue;
+        }
+        if arg == "minimize" {
+            minimize = true;
+            source_file_next = true;
+            continue;
+        }
+        if arg == "fuzz-gen" {
+            fuzz_gen = true;
+            continue;
+        }
+        if fuzz_gen && ["--seed", "--size", "--depth", "--features"].contains(&arg.as_str()) {
+            fuzz_option_next = Some(arg);
+            continue;
+        }
+        if let Some(option) = fuzz_option_next.take() {
+            if let Err(err) = fuzz_options.set(&option, &arg) {
+                usage();
+                println!("\n[ERROR] {}", err);
+                process::exit(1);
+            }
+            continue;
+        }
+        if arg == "init" {
+            init_dir_next = true;
+            continue;
+        }
+        if init_dir_next {
+            init_dir = Some(arg);
+            init_dir_next = false;
+            continue;
+        }
+        if arg == "inspect" {
+            inspect_file_next = true;
+            continue;
+        }
+        if inspect_file_next {
+            inspect_file = Some(arg);
+            inspect_file_next = false;
+            continue;
+        }
+        if arg == "batch" {
+            batch_mode = true;
+            continue;
+        }
+        if arg == "serve" {
+            serve = true;
+            serve_addr_next = true;
+            continue;
+        }
+        if serve_addr_next {
+            serve_addr = arg;
+            serve_addr_next = false;
+            continue;
+        }
+        if arg == "-r" || arg == "--run" {
+            run_prog = true;
+            continue;
+        }
+        if arg == "-b" || arg == "--bytecode" {
+            dump_bc = true;
+            continue;
+        }
+        if dump_radix_next {
+            dump_radix = match parse_integer(&arg) {
+                Ok(radix) if DUMP_RADIXES.contains(&radix) => Some(radix),
+                _ => {
+                    usage();
+                    println!("\n[ERROR] Unsupported radix {}, expected 2, 10 or 16.", arg);
+                    process::exit(1);
+                },
+            };
+            dump_radix_next = false;
+            continue;
+        }
+        if arg == "--dump-radix" {
+            dump_radix_next = true;
+            continue;
+        }
+        if data_stack_next {
+            compile_options.data_stack = match arg.parse::<usize>() {
+                Ok(size) if size > 0 => Some(size.next_multiple_of(PAGE_SIZE)),
+                _ => {
+                    usage();
+                    println!("\n[ERROR] Invalid data stack size {}, expected a positive number of bytes.", arg);
+                    process::exit(1);
+                },
+            };
+            data_stack_next = false;
+            continue;
+        }
+        if arg == "-O2" {
+            compile_options.schedule = true;
+            continue;
+        }
+        if arg == "--data-stack" {
+            data_stack_next = true;
+            continue;
+        }
+        if run_with_next {
+            compile_options.run_with = Some(arg);
+            run_with_next = false;
+            continue;
+        }
+        if arg == "--run-with" {
+            run_with_next = true;
+            continue;
+        }
+        if define_next || (arg.starts_with("-D") && arg.len() > 2) {
+            let spec = if define_next { arg.as_str() } else { &arg[2..] };
+            match parse_define(spec) {
+                Ok((name, value)) => {
+                    // a later define of the same name replaces the earlier one
+                    defines.retain(|(defined, _)| *defined != name);
+                    defines.push((name, value));
+                },
+                Err(err) => {
+                    usage();
+                    println!("\n[ERROR] {}", err);
+                    process::exit(1);
+                },
+            }
+            define_next = false;
+            continue;
+        }
+        if arg == "-D" {
+            define_next = true;
+            continue;
+        }
+        if arg == "-v" || arg == "--verbose" {
+            compile_options.verbose = true;
+            continue;
+        }
+        if arg == "--dump-ir" || arg.starts_with("--dump-ir=") {
+            let phase = arg.strip_prefix("--dump-ir=").unwrap_or("final");
+            if !IR_PHASES.contains(&phase) {
+                usage();
+                println!("\n[ERROR] Unknown `--dump-ir` phase `{}`, expected one of {}.", phase, IR_PHASES.join(", "));
+                process::exit(1);
+            }
+            dump_ir = Some(phase.to_string());
+            continue;
+        }
+        if arg == "--emit" {
+            emit_next = true;
+            continue;
+        }
+        if arg == "-o" || arg == "--output" {
+            exec_file_next = true;
+            continue;
+        }
+        if arg == "--cache" {
+            cache_dir_next = true;
+            continue;
+        }
+        if cache_dir_next {
+            cache_dir = Some(arg);
+            cache_dir_next = false;
+            continue;
+        }
+        if arg == "--timings" {
+            show_timings = true;
+            continue;
+        }
+        if arg == "--progress" {
+            show_progress = true;
+            continue;
+        }
+        if arg == "--warn-size" {
+            warn_size_next = true;
+            continue;
+        }
+        if arg == "--warn-stack-depth" {
+            warn_stack_depth_next = true;
+            continue;
+        }
+        if arg == "--line-comment" {
+            line_comment_next = true;
+            continue;
+        }
+        if line_comment_next {
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                usage();
+                println!("\n[ERROR] Invalid comment prefix `{}`, expected a non empty word.", arg);
+                process::exit(1);
+            }
+            if !lex_options.line_comments.contains(&arg) {
+                lex_options.line_comments.push(arg);
+            }
+            line_comment_next = false;
+            continue;
+        }
+        if warn_stack_depth_next {
+            warn_stack_depth = Some(arg.parse::<usize>().unwrap_or_else(|_| {
+                usage();
+                println!("\n[ERROR] Invalid stack depth {}, expected a number of values.", arg);
+                process::exit(1);
+            }));
+            warn_stack_depth_next = false;
+            continue;
+        }
+        if warn_size_next {
+            warn_size = arg.parse::<usize>().unwrap_or_else(|_| {
+                usage();
+                println!("\n[ERROR] Invalid size {}, expected a number of instructions.", arg);
+                process::exit(1);
+            });
+            warn_size_next = false;
+            continue;
+        }
+        if arg == "--link" {
+            link_object_next = true;
+            continue;
+        }
+        if mangle_next {
+            compile_options.mangle = match arg.as_str() {
+                "readable" => Mangle::Readable,
+                "plain" => Mangle::Plain,
+                _ => {
+                    usage();
+                    println!("\n[ERROR] Unknown `--mangle` scheme `{}`, expected `readable` or `plain`.", arg);
+                    process::exit(1);
+                },
+            };
+            mangle_next = false;
+            continue;
+        }
+        if asm_syntax_next {
+            compile_options.asm_syntax = match arg.as_str() {
+                "nasm" => AsmSyntax::Nasm,
+                "gas" => AsmSyntax::Gas,
+                _ => {
+                    usage();
+                    println!("\n[ERROR] Unknown assembler syntax `{}`, expected `nasm` or `gas`.", arg);
+                    process::exit(1);
+                },
+            };
+            asm_syntax_next = false;
+            continue;
+        }
+        if backend_next {
+            compile_options.backend = Some(arg);
+            backend_next = false;
+            continue;
+        }
+        if arg == "--backend" {
+            backend_next = true;
+            continue;
+        }
+        if arg == "--asm-syntax" {
+            asm_syntax_next = true;
+            continue;
+        }
+        if arg == "--mangle" {
+            mangle_next = true;
+            continue;
+        }
+        if link_object_next {
+            compile_options.link_objects.push(arg);
+            link_object_next = false;
+            continue;
+        }
+        if arg == "--runtime-checks" {
+            compile_options.runtime_checks = true;
+            continue;
+        }
+        if arg == "--no-buffer" {
+            compile_options.buffer_output = false;
+            continue;
+        }
+        if arg == "-d" || arg == "--debug" {
+            debug_mode = true;
+            continue;
+        }
+        if arg == "--mem-stats" {
+            mem_stats = true;
+            continue;
+        }
+        if arg == "--checkpoint" {
+            checkpoint_file_next = true;
+            continue;
+        }
+        if arg == "--checkpoint-after" {
+            checkpoint_after_next = true;
+            continue;
+        }
+        if arg == "--resume" {
+            resume_file_next = true;
+            continue;
+        }
+        if checkpoint_file_next {
+            checkpoint_file = Some(arg);
+            checkpoint_file_next = false;
+            continue;
+        }
+        if checkpoint_after_next {
+            checkpoint_after = Some(arg.parse::<u64>().unwrap_or_else(|_| {
+                usage();
+                println!("\n[ERROR] Invalid number of steps {}, expected a number.", arg);
+                process::exit(1);
+            }));
+            checkpoint_after_next = false;
+            continue;
+        }
+        if resume_file_next {
+            resume_file = Some(arg);
+            resume_file_next = false;
+            continue;
+        }
+        if arg == "--explain-bytecode" {
+            explain = true;
+            continue;
+        }
+        if arg == "--fuse-loops" {
+            fuse_loops = true;
+            continue;
+        }
+        if arg == "--strict" {
+            strict = true;
+            continue;
+        }
+        if arg == "--strings" {
+            strings = true;
+            continue;
+        }
+        if arg == "--bits" {
+            bits_next = true;
+            continue;
+        }
+        if arg == "--max-errors" {
+            max_errors_next = true;
+            continue;
+        }
+        if arg == "--mem-check" {
+            mem_check = true;
+            continue;
+        }
+        if arg == "--record" {
+            record_file_next = true;
+            continue;
+        }
+        if arg == "--replay" {
+            debug_mode = true;
+            replay_file_next = true;
+            continue;
+        }
+        if bits_next {
+            bits = match arg.as_str() {
+                "32" => 32,
+                "64" => 64,
+                _ => {
+                    usage();
+                    println!("\n[ERROR] Unsupported word size {}, expected 32 or 64.", arg);
+                    process::exit(1);
+                },
+            };
+            bits_next = false;
+            continue;
+        }
+        if max_errors_next {
+            max_errors = arg.parse::<usize>().unwrap_or_else(|_| {
+                usage();
+                println!("\n[ERROR] Invalid error limit {}, expected a number.", arg);
+                process::exit(1);
+            });
+            max_errors_next = false;
+            continue;
+        }
+        if profile_file_next {
+            profile_file = Some(arg);
+            profile_file_next = false;
+            continue;
+        }
+        if profile_data_next {
+            profile_data = Some(arg);
+            profile_data_next = false;
+            continue;
+        }
+        if arg == "--profile" {
+            profile_file_next = true;
+            continue;
+        }
+        if arg == "--profile-data" {
+            profile_data_next = true;
+            continue;
+        }
+        if record_file_next {
+            record_file = Some(arg);
+            record_file_next = false;
+            continue;
+        }
+        if replay_file_next {
+            replay_file = Some(arg);
+            replay_file_next = false;
+            continue;
+        }
+        if emit_next {
+            match parse_emit_spec(&arg) {
+                Ok((EmitKind::Bytecode(format), path)) => emit_bc = Some((format, path)),
+                Ok((EmitKind::Listing, path)) => emit_listing = Some(path),
+                Ok((EmitKind::SourceMap, path)) => emit_source_map = Some(path),
+                Ok((EmitKind::Layout, path)) => emit_layout = Some(path),
+                Ok((EmitKind::JsonIr, path)) => emit_json_ir = Some(path),
+                Err(err) => {
+                    usage();
+                    println!("\n[ERROR] {}", err);
+                    process::exit(1);
+                }
+            }
+            emit_next = false;
+            continue;
+        }
+        if exec_file_next {
+            exec_file = arg;
+            exec_file_set = true;
+            exec_file_next = false;
+            continue;
+        }
+        if source_file_next {
+            source_files.push(arg);
+            continue;
+        }
+    }
+
+    if serve {
+        serve_playground(&serve_addr);
+        process::exit(0);
+    }
+
+    if batch_mode {
+        batch(io::stdin().lock(), &mut io::stdout().lock());
+        process::exit(0);
+    }
+
+    if fuzz_gen {
+        print!("{}", fuzz_program(&fuzz_options));
+        process::exit(0);
+    }
+
+    if init_dir_next {
+        usage();
+        println!("\n[ERROR] Missing project directory.");
+        process::exit(1);
+    }
+
+    if let Some(init_dir) = init_dir {
+        if let Err(err) = init_project(&init_dir) {
+            eprintln!("[ERROR] {}", err);
+            process::exit(1);
+        }
+        println!("[INFO] Created project {}", init_dir);
+        process::exit(0);
+    }
+
+    if inspect_file_next {
+        usage();
+        println!("\n[ERROR] Missing executable.");
+        process::exit(1);
+    }
+
+    if let Some(inspect_file) = inspect_file {
+        let bytes = std::fs::read(&inspect_file).unwrap_or_else(|_| panic!("Could not read file {}", inspect_file));
+        match read_metadata(&bytes) {
+            Ok(metadata) => print!("{}", metadata),
+            Err(err) => {
+                eprintln!("[ERROR] {}: {}", inspect_file, err);
+                process::exit(1);
+            },
+        }
+        process::exit(0);
+    }
+
+    if source_files.is_empty() {
+        usage();
+        println!("\n[ERROR] Missing source file.");
+        process::exit(1);
+    }
+
+    if [interp, comp, highlight, minimize, analyze, lint, verify_ir].iter().filter(|&&subcommand| subcommand).count() > 1 {
+        usage();
+        println!("\n[ERROR] `compile`, `interpret`, `highlight`, `minimize`, `analyze`, `lint` and `verify-ir` subcommands are mutually exclusive.");
+        process::exit(1);
+    }
+
+    if minimize {
+        if prog_args.is_empty() {
+            usage();
+            println!("\n[ERROR] Missing predicate command after `--`.");
+            process::exit(1);
+        }
+        let out_file = if exec_file_set { exec_file.clone() } else { source_files[0].clone() + ".min" };
+        let minimized = minimize_file(&source_files[0], &prog_args).unwrap_or_else(|err| {
+            eprintln!("[ERROR] {}", err);
+            process::exit(1);
+        });
+        std::fs::write(&out_file, minimized).unwrap_or_else(|_| panic!("Could not write file {}", out_file));
+        println!("[INFO] Minimized program written to {}", out_file);
+        process::exit(0);
+    }
+
+    if explain && !interp {
+        usage();
+        println!("\n[ERROR] `--explain-bytecode` is only supported by the `interpret` subcommand.");
+        process::exit(1);
+    }
+
+    if profile_file.is_some() && (!interp || debug_mode || record_file.is_some() || mem_check || mem_stats || bits == 32 || explain) {
+        usage();
+        println!("\n[ERROR] `--profile` is only supported by the `interpret` subcommand, without other interpreter options.");
+        process::exit(1);
+    }
+
+    if profile_data.is_some() && dump_ir.is_none() {
+        usage();
+        println!("\n[ERROR] `--profile-data` is only supported together with `--dump-ir`.");
+        process::exit(1);
+    }
+
+    if fuse_loops && (!interp || debug_mode || record_file.is_some() || mem_check || mem_stats || bits == 32 || strict) {
+        usage();
+        println!("\n[ERROR] `--fuse-loops` is only supported by the `interpret` subcommand, without other interpreter options.");
+        process::exit(1);
+    }
+
+    if strict && (!interp || debug_mode || record_file.is_some() || mem_check || mem_stats || bits == 32) {
+        usage();
+        println!("\n[ERROR] `--strict` is only supported by the `interpret` subcommand, without other interpreter options.");
+        process::exit(1);
+    }
+
+    if checkpoint_file.is_some() != checkpoint_after.is_some() {
+        usage();
+        println!("\n[ERROR] `--checkpoint` and `--checkpoint-after` must be given together.");
+        process::exit(1);
+    }
+
+    if strings && (!interp || debug_mode || record_file.is_some() || mem_check || mem_stats || strict || bits == 32
+        || checkpoint_file.is_some() || resume_file.is_some()) {
+        usage();
+        println!("\n[ERROR] `--strings` is only supported by the `interpret` subcommand, without other interpreter options.");
+        process::exit(1);
+    }
+
+    let resumable = checkpoint_file.is_some() || resume_file.is_some();
+    if resumable && (!interp || debug_mode || record_file.is_some() || mem_check || mem_stats || strict || bits == 32) {
+        usage();
+        println!("\n[ERROR] `--checkpoint` and `--resume` are only supported by the `interpret` subcommand, without other interpreter options.");
+        process::exit(1);
+    }
+
+    if bits == 32 && (!interp || debug_mode || record_file.is_some() || mem_check || mem_stats) {
+        usage();
+        println!("\n[ERROR] `--bits 32` is only supported by the `interpret` subcommand, without other interpreter options.");
+        process::exit(1);
+    }
+
+    if let Some(path) = emit_listing {
+        if !comp {
+            usage();
+            println!("\n[ERROR] `--emit listing` is only supported by the `compile` subcommand.");
+            process::exit(1);
+        }
+        compile_options.listing = Some(path.unwrap_or(exec_file.clone() + ".lst"));
+    }
+
+    if let Some(path) = emit_source_map {
+        if !comp {
+            usage();
+            println!("\n[ERROR] `--emit source-map` is only supported by the `compile` subcommand.");
+            process::exit(1);
+        }
+        compile_options.source_map = Some(path.unwrap_or(exec_file.clone() + ".asm.map"));
+    }
+
+    if let Some(path) = emit_layout {
+        if !comp {
+            usage();
+            println!("\n[ERROR] `--emit layout` is only supported by the `compile` subcommand.");
+            process::exit(1);
+        }
+        compile_options.layout = Some(path.unwrap_or(exec_file.clone() + ".layout"));
+    }
+
+    if highlight {
+        for source_file in &source_files {
+            let source : String = std::fs::read_to_string(source_file)
+                .unwrap_or_else(|_| panic!("Could not read file {}", source_file));
+            print!("{}", highlight_html(&source, &lex_options.line_comments));
+        }
+        process::exit(0);
+    }
+
+    if verify_ir {
+        let mut failed = false;
+        for source_file in &source_files {
+            let problems = verify_file(source_file, &defines, &lex_options);
+            for problem in &problems {
+                println!("[ERROR] {}: {}", source_file, problem);
+            }
+            failed |= !problems.is_empty();
+        }
+        process::exit(if failed { 1 } else { 0 });
+    }
+
+    if lint {
+        let warnings = lint_tokens(&lex_files(&source_files, &lex_options, &mut Timings::default()));
+        for warning in &warnings {
+            println!("{}", warning);
+        }
+        process::exit(if warnings.is_empty() { 0 } else { 1 });
+    }
+
+    info(compile_options.verbose, &format!("source files: {:?}", source_files));
+    compile_options.source_hash = source_hash(&source_files);
+
+    let mut timings = Timings { progress: show_progress, ..Timings::default() };
+    let mut program = match &cache_dir {
+        Some(cache_dir) => load_program_cached(&source_files, &defines, &lex_options, cache_dir, &mut timings),
+        None => load_program(&source_files, &defines, &lex_options, &mut timings),
+    };
+    if program.len() > warn_size {
+        eprintln!("[WARNING] The program has {} instructions, more than --warn-size {}, and takes about {} MiB in memory",
+            program.len(), warn_size, program_memory(&program) >> 20);
+    }
+    if let Some(limit) = warn_stack_depth {
+        match max_stack_depth(&program) {
+            Ok((depth, ip)) if depth > limit => {
+                let loc = &program[ip].loc;
+                eprintln!("[WARNING] {}:{}:{}: @ip {}: The stack can be {} value{} deep here, more than --warn-stack-depth {}",
+                    loc.file, loc.row+1, loc.col+1, ip, depth, if depth == 1 { "" } else { "s" }, limit);
+            },
+            Ok(_) => {},
+            Err(warning) => eprintln!("{}", warning),
+        }
+    }
+    if let Some(radix) = dump_radix {
+        set_dump_radix(&mut program, radix);
+    }
+
+    let dump_phase = |phase: &str, program: &[Instruction]| {
+        if dump_ir.as_deref() == Some(phase) {
+            let counts = profile_data.as_ref().map(|path| read_profile(path, program.len()).unwrap_or_else(|err| {
+                eprintln!("[ERROR] {}", err);
+                process::exit(1);
+            }));
+            let color = io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none();
+            print!("{}", bytecode_view(program, counts.as_deref(), color));
+        }
+    };
+    dump_phase("after-parse", &program);
+    dump_phase("final", &program);
+
+    if analyze {
+        print!("{}", regions_to_table(&program, &analyze_regions(&program)));
+        process::exit(0);
+    }
+
+    if dump_bc {
+        _dump_bytecode(&program);
+        emit_bc.get_or_insert((BytecodeFormat::Text, None));
+    }
+
+    if let Some((format, path)) = emit_bc {
+        let path = path.unwrap_or(source_files[0].clone() + ".bytecode");
+        emit_bytecode(&program, &path, format);
+    }
+
+    if let Some(path) = emit_json_ir {
+        let path = path.unwrap_or(source_files[0].clone() + ".ir.json");
+        let (tokens, constants) = expand_structs_with_constants(lex_files(&source_files, &lex_options, &mut Timings::default()), &defines)
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            });
+        std::fs::write(&path, ir_to_json(&program, &tokens, &constants))
+            .unwrap_or_else(|_| panic!("Could not write file {}", path));
+    }
+
+    if interp {
+        if debug_mode {
+            let history = match replay_file {
+                Some(replay_file) => read_trace(&replay_file, &program).unwrap_or_else(|err| {
+                    eprintln!("[ERROR] {}", err);
+                    process::exit(1);
+                }),
+                None => Vec::new(),
+            };
+            debug(&program, history);
+        } else if strings {
+            interpret_strings(&program, &mut io::stdout());
+        } else if resumable {
+            let mut state = match &resume_file {
+                Some(resume_file) => Checkpoint::read(resume_file, &program).unwrap_or_else(|err| {
+                    eprintln!("[ERROR] {}", err);
+                    process::exit(1);
+                }),
+                None => Checkpoint::new(&program),
+            };
+            let finished = interpret_resumable(&program, &mut state, checkpoint_after, &mut io::stdout());
+            if let (false, Some(checkpoint_file)) = (finished, checkpoint_file) {
+                std::fs::write(&checkpoint_file, state.to_bytes(&program))
+                    .unwrap_or_else(|_| panic!("Could not write file {}", checkpoint_file));
+                eprintln!("[INFO] Saved the state at ip {} to {}", state.ip, checkpoint_file);
+            }
+        } else if let Some(record_file) = record_file {
+            let mut trace = File::create(&record_file)
+                .unwrap_or_else(|_| panic!("Could not open file {}", record_file));
+            interpret_recording(&program, &mut io::stdout(), &mut trace);
+        } else if mem_check {
+            let warnings = interpret_checking_memory(&program, &mut io::stdout());
+            for warning in &warnings {
+                eprintln!("{}", warning);
+            }
+            eprintln!("[INFO] Memory check: {} read(s) of unwritten globals", warnings.len());
+        } else if mem_stats {
+            let stats = interpret_with_stats(&program, &mut io::stdout());
+            eprint!("{}", stats.to_summary());
+        } else if let Some(profile_file) = profile_file {
+            let counts = interpret_profiling(&program, &mut io::stdout());
+            std::fs::write(&profile_file, profile_to_string(&counts))
+                .unwrap_or_else(|_| panic!("Could not write file {}", profile_file));
+        } else if explain {
+            interpret_explaining(&program, &mut io::stdout(), &mut io::stderr());
+        } else if bits == 32 {
+            interpret_32(&program, &mut io::stdout());
+        } else if fuse_loops {
+            interpret_fused(&program, &mut io::stdout());
+        } else if strict {
+            let errors = interpret_strict(&program, &mut io::stdout());
+            for error in report_errors(&errors, max_errors) {
+                eprintln!("{}", error);
+            }
+            if !errors.is_empty() {
+                process::exit(1);
+            }
+        } else {
+            interpret(&program, &mut io::stdout());
+        }
+    }
+    if comp {
+        let exit_code = compile(&program, &exec_file, run_prog, &prog_args, &compile_options, &mut timings);
+        if show_timings {
+            eprint!("{}", timings.to_table());
+        }
+        process::exit(exit_code);
+    }
+}
+
+fn load_program(source_files: &[String], defines: &[(String, i64)], lex_options: &LexOptions, timings: &mut Timings) -> Vec<Instruction> {
+    let tokens = lex_files(source_files, lex_options, timings);
+    timings.begin("check");
+    let start = std::time::Instant::now();
+    for warning in unused_variables(&tokens) {
+        eprintln!("{}", warning);
+    }
+    timings.record_count("check", start.elapsed(), tokens.len(), "tokens");
+    timings.begin("parse");
+    let start = std::time::Instant::now();
+    let program = parser(&expand_structs(tokens, defines));
+    timings.record_count("parse", start.elapsed(), program.len(), "instructions");
+    program
+}
+
+// numbers above this are reported by `lint` as magic numbers
+const MAGIC_NUMBER_LIMIT: i64 = 255;
+// blocks nested deeper than this are reported by `lint`
+const MAX_NESTING: usize = 4;
+
+// style warnings, each named for `// rorth: allow(NAME)` on the line before
+fn lint_tokens(tokens: &[Token]) -> Vec<String> {
+    // a pragma applies to the whole line following it
+    let allowed : HashSet<(&str, usize, &str)> = tokens.iter()
+        .flat_map(|tok| tok.allow.iter().map(|name| (tok.file.as_str(), tok.row, name.as_str())))
+        .collect();
+    let mut warnings : Vec<(&Token, String, &str)> = Vec::new();
+    let mut depth : usize = 0;
+    // the `end` of a struct body doesn't close a block
+    let mut in_struct = false;
+    for (i, tok) in tokens.iter().enumerate() {
+        let next = tokens.get(i + 1).map_or("", |next| next.tok.as_str());
+        let literal = parse_integer(&tok.tok).ok();
+        match (tok.tok.as_str(), next) {
+            (first @ ("swap" | "2swap"), second) if first == second =>
+                warnings.push((tok, format!("`{} {}` does nothing", first, second), "noop")),
+            ("2dup", "2drop") => warnings.push((tok, String::from("`2dup 2drop` does nothing"), "noop")),
+            ("dup", op @ ("=" | "!=" | "<" | ">" | "<=" | ">=")) => {
+                let result = ["=", "<=", ">="].contains(&op) as i64;
+                warnings.push((tok, format!("`dup {}` compares a value with itself, always {}", op, result), "self-compare"));
+            },
+            ("while", _) if parse_integer(next).is_ok() && tokens.get(i + 2).is_some_and(|tok| tok.tok == "do") =>
+                warnings.push((tok, format!("`while` condition is the constant {}", next), "constant-condition")),
+            (_, "if") if literal.is_some() =>
+                warnings.push((tok, format!("`if` condition is the constant {}", tok.tok), "constant-condition")),
+            _ => {},
+        }
+        if literal.is_some_and(|value| value.unsigned_abs() > MAGIC_NUMBER_LIMIT as u64) {
+            warnings.push((tok, format!("Magic number {}, give it a name with `-D` or a `var`", tok.tok), "magic-number"));
+        }
+        match tok.tok.as_str() {
+            "if" | "while" => {
+                depth += 1;
+                if depth == MAX_NESTING + 1 {
+                    warnings.push((tok, format!("Blocks nested deeper than {} levels", MAX_NESTING), "deep-nesting"));
+                }
+            },
+            "struct" => in_struct = true,
+            "end" if in_struct => in_struct = false,
+            "end" if depth == 0 => warnings.push((tok, String::from("`end` without a matching `if` or `while`"), "unbalanced")),
+            "end" => depth -= 1,
+            _ => {},
+        }
+    }
+    let mut lines : Vec<String> = warnings.into_iter()
+        .filter(|(tok, _, name)| !allowed.contains(&(tok.file.as_str(), tok.row, *name)))
+        .map(|(tok, message, name)| format!("[WARNING] {}:{}:{}: {} [{}]", tok.file, tok.row+1, tok.col+1, message, name))
+        .collect();
+    lines.extend(unused_variables(tokens));
+    lines
+}
+
+// parses the argument of `-D`, `NAME=VALUE` or `NAME` for a value of 1
+fn parse_define(spec: &str) -> Result<(String, i64), String> {
+    let (name, value) = spec.split_once('=').unwrap_or((spec, "1"));
+    if name.is_empty() || parse_integer(name).is_ok() || is_reserved_name(name) {
+        return Err(format!("Invalid name `{}` for a define", name));
+    }
+    let value = parse_integer(value).map_err(|err| format!("Invalid value for define `{}`: {}", name, err))?;
+    Ok((name.to_string(), value))
+}
+
+// bump when the parser makes different instructions of the same words,
+// changes to the opcodes and words themselves are caught by `language_hash`
+const CACHE_VERSION: u32 = 2;
+
+// 64-bit FNV-1a, stable across toolchains unlike the std hashers
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+// hashes the opcodes in declaration order and the words the parser knows
+fn language_hash() -> u64 {
+    let mut hash = 0xcbf29ce484222325;
+    for opcode in Opcode::ALL {
+        hash = fnv1a(hash, format!("{:?}\0", opcode).as_bytes());
+    }
+    for word in KEYWORDS.iter().chain(BUILTIN_WORDS) {
+        hash = fnv1a(hash, format!("{}\0", word).as_bytes());
+    }
+    hash
+}
+
+// hashes the names and contents of the source files, together with the
+// defines, the comment prefixes, the language and the compiler and cache
+// versions, or None if any of the files can't be read
+fn cache_key(source_files: &[String], defines: &[(String, i64)], line_comments: &[String]) -> Option<u64> {
+    let mut hash = fnv1a(0xcbf29ce484222325, format!("{} {} {} {:x}\0", NAME, VERSION, CACHE_VERSION, language_hash()).as_bytes());
+    for (name, value) in defines {
+        hash = fnv1a(hash, format!("{}={}\0", name, value).as_bytes());
+    }
+    for prefix in line_comments {
+        hash = fnv1a(hash, format!("comment {}\0", prefix).as_bytes());
+    }
+    for source_file in source_files {
+        hash = fnv1a(hash, source_file.as_bytes());
+        hash = fnv1a(hash, b"\0");
+        hash = fnv1a(hash, &std::fs::read(source_file).ok()?);
+        hash = fnv1a(hash, b"\0");
+    }
+    Some(hash)
+}
+
+// hashes the contents of the source files, or None if any of them can't be read
+fn source_hash(source_files: &[String]) -> Option<u64> {
+    let mut hash = 0xcbf29ce484222325;
+    for source_file in source_files {
+        hash = fnv1a(hash, &std::fs::read(source_file).ok()?);
+        hash = fnv1a(hash, b"\0");
+    }
+    Some(hash)
+}
+
+// looks the program up in `cache_dir` by the content hash of its sources,
+// lexing and parsing them and storing the result on a miss
+fn load_program_cached(source_files: &[String], defines: &[(String, i64)], lex_options: &LexOptions, cache_dir: &str, timings: &mut Timings) -> Vec<Instruction> {
+    let cache_file = match cache_key(source_files, defines, &lex_options.line_comments) {
+        Some(key) => std::path::Path::new(cache_dir).join(format!("{:016x}.cache", key)),
+        None => return load_program(source_files, defines, lex_options, timings),
+    };
+    let cached = timings.time("cache", || std::fs::read_to_string(&cache_file).ok()
+        .and_then(|text| program_from_cache(&text)));
+    if let Some(program) = cached {
+        return program;
+    }
+    let program = load_program(source_files, defines, lex_options, timings);
+    let stored = std::fs::create_dir_all(cache_dir)
+        .and_then(|_| std::fs::write(&cache_file, program_to_cache(&program)));
+    if let Err(err) = stored {
+        eprintln!("[WARNING] Could not write cache file {}: {}", cache_file.display(), err);
+    }
+    program
+}
+
+// one instruction per line: `ip opcode row col operand_count operands... file`
+fn program_to_cache(program: &[Instruction]) -> String {
+    let mut text = String::new();
+    for ins in program {
+        text += &format!("{} {} {} {} {}", ins.ip, ins.opcode.clone() as u8, ins.loc.row, ins.loc.col, ins.operands.len());
+        for operand in &ins.operands {
+            text += &format!(" {}", operand);
+        }
+        text += &format!(" {}\n", ins.loc.file);
+    }
+    text
+}
+
+fn program_from_cache(text: &str) -> Option<Vec<Instruction>> {
+    let mut program : Vec<Instruction> = Vec::new();
+    for line in text.lines() {
+        let mut fields = line.splitn(6, ' ');
+        let ip : usize = fields.next()?.parse().ok()?;
+        let opcode = Opcode::ALL.get(fields.next()?.parse::<usize>().ok()?)?.clone();
+        let row : usize = fields.next()?.parse().ok()?;
+        let col : usize = fields.next()?.parse().ok()?;
+        let operand_count : usize = fields.next()?.parse().ok()?;
+        let mut rest = fields.next().unwrap_or("").splitn(operand_count + 1, ' ');
+        let mut operands : Vec<i64> = Vec::new();
+        for _ in 0..operand_count {
+            operands.push(rest.next()?.parse().ok()?);
+        }
+        let file = rest.next()?.to_string();
+        program.push(Instruction { opcode, operands, ip, loc: Loc { file, row, col } });
+    }
+    Some(program)
+}
+
+// debug function
+fn _dump_tokens(tokens : &[Token]) {
+    println!("Tokens:");
+    for (i, tok) in tokens.iter().enumerate() {
+        println!("\t{} {:?}", i, tok);
+    }
+}
+
+// debug function
+fn _dump_bytecode(program : &[Instruction]) {
+    println!("Bytecode:\n[ip | opcode  | operands]");
+    for (i, ins) in program.iter().enumerate() {
+        println!("{:>3}   {:?}\t{:>?}", i, ins.opcode, ins.operands);
+    }
+}
+
+// parses the argument of `--emit`, of the form `bytecode[:FORMAT][=PATH]`
+fn parse_emit_spec(spec: &str) -> Result<(EmitKind, Option<String>), String> {
+    let (kind, path) = match spec.split_once('=') {
+        Some((kind, path)) => (kind, Some(path.to_string())),
+        None => (spec, None),
+    };
+    if kind == "listing" {
+        return Ok((EmitKind::Listing, path));
+    }
+    if kind == "source-map" {
+        return Ok((EmitKind::SourceMap, path));
+    }
+    if kind == "layout" {
+        return Ok((EmitKind::Layout, path));
+    }
+    if kind == "json-ir" {
+        return Ok((EmitKind::JsonIr, path));
+    }
+    let (kind, format) = kind.split_once(':').unwrap_or((kind, "text"));
+    if kind != "bytecode" {
+        return Err(format!("Unknown `--emit` kind `{}`, expected `bytecode`, `listing`, `source-map`, `layout` or `json-ir`", kind));
+    }
+    let format = match format {
+        "text" => BytecodeFormat::Text,
+        "json" => BytecodeFormat::Json,
+        "binary" => BytecodeFormat::Binary,
+        _ => return Err(format!("Unknown bytecode format `{}`, expected `text`, `json` or `binary`", format)),
+    };
+    Ok((EmitKind::Bytecode(format), path))
+}
+
+fn emit_bytecode(program : &[Instruction], path: &str, format: BytecodeFormat) {
+    let contents = match format {
+        BytecodeFormat::Text => _dump_bytecode_to_string(program).into_bytes(),
+        BytecodeFormat::Json => bytecode_to_json(program).into_bytes(),
+        BytecodeFormat::Binary => bytecode_to_binary(program),
+    };
+    std::fs::write(path, contents)
+        .unwrap_or_else(|_| panic!("Could not write file {}", path));
+}
+
+// version of the bytecode formats, also given by the json-ir export, bumped
+// whenever an opcode changes its number or meaning
+// 2: opcodes were inserted before the ones of version 1
+// 3: the operand count of an instruction is a u32, long strings don't fit a u8
+const BYTECODE_VERSION: u32 = 3;
+// the opcodes of version 1, by number, which binary bytecode of that version
+// is still read with
+const BYTECODE_V1_OPCODES: &[Opcode] = &[
+    Opcode::OP_PUSH, Opcode::OP_ADD, Opcode::OP_SUB, Opcode::OP_MUL, Opcode::OP_DIV,
+    Opcode::OP_NOT, Opcode::OP_EQ, Opcode::OP_NE, Opcode::OP_GT, Opcode::OP_LT,
+    Opcode::OP_GE, Opcode::OP_LE, Opcode::OP_SHR, Opcode::OP_SHL, Opcode::OP_BOR,
+    Opcode::OP_BAND, Opcode::OP_DUP, Opcode::OP_DUMP, Opcode::OP_IF, Opcode::OP_ELSE,
+    Opcode::OP_END, Opcode::OP_WHILE, Opcode::OP_DO,
+];
+const BYTECODE_MAGIC: &[u8; 8] = b"RORTHBC\0";
+
+/**
+ * {"version": 3, "instructions": [{"ip": 0, "opcode": "OP_PUSH", "operands": [34]}, ...]}
+ */
+fn bytecode_to_json(program : &[Instruction]) -> String {
+    let mut json = format!("{{\"version\": {}, \"instructions\": [", BYTECODE_VERSION);
+    for (i, ins) in program.iter().enumerate() {
+        if i > 0 {
+            json += ", ";
+        }
+        let operands : Vec<String> = ins.operands.iter().map(|op| op.to_string()).collect();
+        json += format!("{{\"ip\": {}, \"opcode\": \"{:?}\", \"operands\": [{}]}}",
+            ins.ip, ins.opcode, operands.join(", ")).as_str();
+    }
+    json += "]}\n";
+    json
+}
+
+fn instruction_to_json(ins : &Instruction) -> String {
+    let operands : Vec<String> = ins.operands.iter().map(|op| op.to_string()).collect();
+    let jump = match flow(ins) {
+        Flow::Next => String::from("null"),
+        Flow::Jump(target) | Flow::JumpIfZero(target) => target.to_string(),
+    };
+    format!("{{\"ip\": {}, \"opcode\": \"{:?}\", \"operands\": [{}], \"jump\": {}, \"span\": {}}}",
+        ins.ip, ins.opcode, operands.join(", "), jump, span_to_json(&ins.loc))
+}
+
+fn span_to_json(loc : &Loc) -> String {
+    format!("{{\"file\": \"{}\", \"row\": {}, \"col\": {}}}", json_escape(&loc.file), loc.row+1, loc.col+1)
+}
+
+/**
+ * {"version": 3,
+ *  "instructions": [{"ip": 0, "opcode": "OP_PUSH", "operands": [34], "jump": null, "span": {"file": ..., "row": 1, "col": 1}}, ...],
+ *  "globals": [{"name": "n", "index": 0, "address": 268435456, "span": {...}}, ...],
+ *  "constants": [{"name": "P.x", "value": 0}, ...]}
+ * `tokens` are the ones the program was parsed from, after struct expansion,
+ * rows and cols are 1 based, constants are sorted by name
+ */
+fn ir_to_json(program : &[Instruction], tokens : &[Token], constants : &HashMap<String, i64>) -> String {
+    let instructions : Vec<String> = program.iter().map(instruction_to_json).collect();
+    // numbered in order of declaration, as the parser does
+    let globals : Vec<String> = tokens.windows(2).filter(|pair| pair[0].tok == "var").enumerate().map(|(index, pair)| {
+        format!("{{\"name\": \"{}\", \"index\": {}, \"address\": {}, \"span\": {}}}",
+            json_escape(&pair[1].tok), index, global_address(index), span_to_json(&pair[1].loc()))
+    }).collect();
+    let mut constants : Vec<(&String, &i64)> = constants.iter().collect();
+    constants.sort();
+    let constants : Vec<String> = constants.into_iter()
+        .map(|(name, value)| format!("{{\"name\": \"{}\", \"value\": {}}}", json_escape(name), value))
+        .collect();
+    format!("{{\"version\": {},\n \"instructions\": [{}],\n \"globals\": [{}],\n \"constants\": [{}]}}\n",
+        BYTECODE_VERSION, instructions.join(", "), globals.join(", "), constants.join(", "))
+}
+
+/**
+ * magic (8 bytes) | version (u32) | instruction count (u64)
+ * then per instruction: opcode (u8) | ip (u64) | operand count (u32) | operands (i64 each)
+ * all integers are little endian, before version 3 the operand count was a u8
+ */
+fn bytecode_to_binary(program : &[Instruction]) -> Vec<u8> {
+    let mut bytes : Vec<u8> = Vec::new();
+    bytes.extend_from_slice(BYTECODE_MAGIC);
+    bytes.extend_from_slice(&BYTECODE_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(program.len() as u64).to_le_bytes());
+    for ins in program {
+        bytes.push(ins.opcode.clone() as u8);
+        bytes.extend_from_slice(&(ins.ip as u64).to_le_bytes());
+        bytes.extend_from_slice(&(ins.operands.len() as u32).to_le_bytes());
+        for operand in &ins.operands {
+            bytes.extend_from_slice(&operand.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+// reads back `bytecode_to_binary`, all the instructions are located at the
+// start of `filename`
+fn bytecode_from_binary(bytes : &[u8], filename : &str) -> Result<Vec<Instruction>, String> {
+    let mut offset = 0;
+    let mut take = |size: usize| -> Result<&[u8], String> {
+        let field = bytes.get(offset..offset + size).ok_or_else(|| format!("Truncated bytecode at byte {}", offset))?;
+        offset += size;
+        Ok(field)
+    };
+    if take(8)? != BYTECODE_MAGIC {
+        return Err(String::from("Not a binary bytecode file"));
+    }
+    let version = u32::from_le_bytes(take(4)?.try_into().unwrap());
+    let opcodes = match version {
+        1 => BYTECODE_V1_OPCODES,
+        2 | BYTECODE_VERSION => Opcode::ALL,
+        _ => return Err(format!("Unsupported bytecode version {}, expected {} or older", version, BYTECODE_VERSION)),
+    };
+    let count = u64::from_le_bytes(take(8)?.try_into().unwrap());
+    let mut program : Vec<Instruction> = Vec::new();
+    for _ in 0..count {
+        let opcode = take(1)?[0];
+        let opcode = opcodes.get(opcode as usize)
+            .ok_or_else(|| format!("@ip {}: Unknown opcode {}", program.len(), opcode))?.clone();
+        let ip = u64::from_le_bytes(take(8)?.try_into().unwrap()) as usize;
+        let operand_count = match version {
+            1 | 2 => take(1)?[0] as u32,
+            _ => u32::from_le_bytes(take(4)?.try_into().unwrap()),
+        };
+        let mut operands : Vec<i64> = Vec::new();
+        for _ in 0..operand_count {
+            operands.push(i64::from_le_bytes(take(8)?.try_into().unwrap()));
+        }
+        program.push(Instruction { opcode, operands, ip, loc: Loc { file: filename.to_string(), row: 0, col: 0 } });
+    }
+    if offset != bytes.len() {
+        return Err(format!("{} bytes after the last instruction", bytes.len() - offset));
+    }
+    Ok(program)
+}
+
+// whether `ins` has the operands its opcode needs: the jump target of `if`,
+// `else` and `do`, the while of a loop's `end`, the radix of a `dump`, the
+// length and packed bytes of a string and the stack effect and name of a call
+fn operands_are_valid(ins : &Instruction) -> bool {
+    let operands = &ins.operands;
+    match ins.opcode {
+        Opcode::OP_PUSH | Opcode::OP_IF | Opcode::OP_ELSE | Opcode::OP_DO => operands.len() == 1,
+        Opcode::OP_GLOBAL => operands.len() == 1 && operands[0] >= 0,
+        Opcode::OP_END => operands.len() <= 1,
+        Opcode::OP_DUMP => operands.iter().all(|radix| DUMP_RADIXES.contains(radix)) && operands.len() <= 1,
+        Opcode::OP_STRING => operands.first()
+            .is_some_and(|&len| len >= 0 && operands.len() as i64 == 1 + (len + 7) / 8),
+        Opcode::OP_CALL => operands.len() >= 3 && operands[0] >= 0 && operands[1] >= 0,
+        _ => operands.is_empty(),
+    }
+}
+
+// the structural problems of a binary bytecode file or of the program parsed
+// from a source file, a file that is neither is a problem too
+fn verify_file(source_file: &str, defines: &[(String, i64)], lex_options: &LexOptions) -> Vec<String> {
+    let bytes = match std::fs::read(source_file) {
+        Ok(bytes) => bytes,
+        Err(err) => return vec![format!("Could not read file: {}", err)],
+    };
+    let program = if bytes.starts_with(BYTECODE_MAGIC) {
+        bytecode_from_binary(&bytes, source_file)
+    } else if std::str::from_utf8(&bytes).is_err() {
+        Err(String::from("Neither binary bytecode nor UTF-8 source"))
+    } else {
+        Ok(load_program(&[source_file.to_string()], defines, lex_options, &mut Timings::default()))
+    };
+    match program {
+        Ok(program) => verify_program(&program),
+        Err(err) => vec![err],
+    }
+}
+
+// the structural problems of a program that wasn't necessarily made by the
+// parser: instructions numbered out of order, malformed operands, jumps that
+// don't land where their block says, and blocks that are never closed or
+// `end`s that close none
+fn verify_program(program : &[Instruction]) -> Vec<String> {
+    let mut problems : Vec<String> = Vec::new();
+    // the ip of the `if` or `while` of every open block, and of its last
+    // `if`, `else`, `while` or `do`
+    let mut blocks : Vec<(usize, usize)> = Vec::new();
+    let target = |ip: usize| program[ip].operands.first().copied();
+    let show = |target: Option<i64>| target.map_or(String::from("nowhere"), |target| target.to_string());
+    for (ip, ins) in program.iter().enumerate() {
+        if ins.ip != ip {
+            problems.push(format!("@ip {}: Instruction is numbered {}", ip, ins.ip));
+        }
+        if !operands_are_valid(ins) {
+            problems.push(format!("@ip {}: Malformed operands {:?} for {:?}", ip, ins.operands, ins.opcode));
+        }
+        match (&ins.opcode, blocks.last().copied()) {
+            (Opcode::OP_IF | Opcode::OP_WHILE, _) => blocks.push((ip, ip)),
+            (Opcode::OP_ELSE, Some((start, last))) if program[last].opcode == Opcode::OP_IF => {
+                if target(last) != Some(ip as i64) {
+                    problems.push(format!("@ip {}: `if` jumps to {} instead of its `else` at {}", last, show(target(last)), ip));
+                }
+                *blocks.last_mut().unwrap() = (start, ip);
+            },
+            (Opcode::OP_DO, Some((start, last))) if program[last].opcode == Opcode::OP_WHILE => {
+                *blocks.last_mut().unwrap() = (start, ip);
+            },
+            (Opcode::OP_END, Some((start, last))) if program[last].opcode == Opcode::OP_WHILE => {
+                blocks.pop();
+                problems.push(format!("@ip {}: `while` has no `do` before its `end` at {}", start, ip));
+            },
+            (Opcode::OP_END, Some((start, last))) => {
+                blocks.pop();
+                if target(last) != Some(ip as i64) {
+                    problems.push(format!("@ip {}: `{}` jumps to {} instead of its `end` at {}",
+                        last, block_keyword(&program[last].opcode), show(target(last)), ip));
+                }
+                // only the end of a loop jumps, back to its while
+                let expected = Some(start as i64).filter(|_| program[start].opcode == Opcode::OP_WHILE);
+                if ins.operands.first().copied() != expected {
+                    problems.push(format!("@ip {}: `end` jumps to {} instead of {}", ip, show(ins.operands.first().copied()), show(expected)));
+                }
+            },
+            (Opcode::OP_ELSE | Opcode::OP_DO | Opcode::OP_END, _) => {
+                problems.push(format!("@ip {}: `{}` is in no block it can belong to", ip, block_keyword(&ins.opcode)));
+            },
+            _ => {},
+        }
+    }
+    for (start, _) in blocks {
+        problems.push(format!("@ip {}: `{}` has no `end`", start, block_keyword(&program[start].opcode)));
+    }
+    problems
+}
+
+// points of the pipeline where `--dump-ir` can show the program, optimization
+// passes go between them, each followed by its own `after-` phase
+const IR_PHASES: &[&str] = &["after-parse", "final"];
+
+// the bytecode as aligned columns, with the target of every jump and the
+// location and source token of every instruction, and how many times it ran
+// when given the counts of a profile
+fn bytecode_view(program : &[Instruction], counts : Option<&[u64]>, color : bool) -> String {
+    let paint = |text: String, code: &str| if color { format!("\x1b[{}m{}\x1b[0m", code, text) } else { text };
+    let hot = hot_back_edges(program, counts.unwrap_or_default());
+    let mut sources : HashMap<String, Vec<Vec<char>>> = HashMap::new();
+    let rows : Vec<[String; 5]> = program.iter().map(|ins| {
+        let operands : Vec<String> = ins.operands.iter().map(|operand| operand.to_string()).collect();
+        let jump = match flow(ins) {
+            Flow::Next => String::new(),
+            Flow::Jump(target) if hot.contains(&ins.ip) => format!("-> {} hot", target),
+            Flow::Jump(target) | Flow::JumpIfZero(target) => format!("-> {}", target),
+        };
+        // lines with tabs expanded, so that columns index them
+        let lines = sources.entry(ins.loc.file.clone()).or_insert_with(|| {
+            std::fs::read_to_string(&ins.loc.file).unwrap_or_default().lines().map(|line| {
+                let mut expanded : Vec<char> = Vec::new();
+                for c in line.chars() {
+                    if c == '\t' {
+                        expanded.resize((expanded.len() / TAB_WIDTH + 1) * TAB_WIDTH, ' ');
+                    } else {
+                        expanded.push(c);
+                    }
+                }
+                expanded
+            }).collect()
+        });
+        let token : String = lines.get(ins.loc.row).map_or(String::new(), |line| {
+            line.iter().skip(ins.loc.col).take_while(|c| !c.is_whitespace()).collect()
+        });
+        let loc = format!("{}:{}:{}", ins.loc.file, ins.loc.row+1, ins.loc.col+1);
+        [format!("{:?}", ins.opcode), operands.join(" "), jump, loc, token]
+    }).collect();
+    let width = |column: usize, header: &str| rows.iter().map(|row| row[column].len()).chain([header.len()]).max().unwrap();
+    let widths = [width(0, "opcode"), width(1, "operands"), width(2, "jump"), width(3, "location")];
+    let counts = counts.map(|counts| {
+        let counts : Vec<String> = counts.iter().map(|count| count.to_string()).collect();
+        let width = counts.iter().map(String::len).chain(["count".len()]).max().unwrap();
+        (counts, width)
+    });
+    let count = |text: &str| counts.as_ref().map_or(String::new(), |(_, width)| format!("{:>w$}  ", text, w = width));
+    let mut view = format!("{:>4}  {}{:<w0$}  {:<w1$}  {:<w2$}  {:<w3$}  source\n", "ip", count("count"), "opcode", "operands", "jump", "location",
+        w0 = widths[0], w1 = widths[1], w2 = widths[2], w3 = widths[3]);
+    for (ip, [opcode, operands, jump, loc, token]) in rows.into_iter().enumerate() {
+        let jump_color = if hot.contains(&ip) { "31" } else { "33" };
+        view += &format!("{:>4}  {}{}  {:<w1$}  {}  {}  {}", ip,
+            count(counts.as_ref().map_or("", |(counts, _)| &counts[ip])),
+            paint(format!("{:<w0$}", opcode, w0 = widths[0]), "36"), operands,
+            paint(format!("{:<w2$}", jump, w2 = widths[2]), jump_color),
+            paint(format!("{:<w3$}", loc, w3 = widths[3]), "2"), token, w1 = widths[1]);
+        view = view.trim_end().to_string() + "\n";
+    }
+    view
+}
+
+// the `end`s of the loops taken at least half as many times as the hottest one
+fn hot_back_edges(program : &[Instruction], counts : &[u64]) -> Vec<usize> {
+    let back_edges : Vec<(usize, u64)> = program.iter()
+        .filter(|ins| matches!(flow(ins), Flow::Jump(target) if target < ins.ip))
+        .filter_map(|ins| Some((ins.ip, *counts.get(ins.ip)?)))
+        .filter(|(_, count)| *count > 0)
+        .collect();
+    let hottest = back_edges.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    back_edges.into_iter().filter(|(_, count)| count * 2 >= hottest).map(|(ip, _)| ip).collect()
+}
+
+const PROFILE_HEADER: &str = "rorth profile 1";
+
+// one `ip count` line for every instruction, after a header
+fn profile_to_string(counts : &[u64]) -> String {
+    let mut profile = format!("{}\n", PROFILE_HEADER);
+    for (ip, count) in counts.iter().enumerate() {
+        profile += &format!("{} {}\n", ip, count);
+    }
+    profile
+}
+
+// reads the counts written by `--profile`, which must be for a program of
+// `len` instructions
+fn read_profile(filename : &str, len : usize) -> Result<Vec<u64>, String> {
+    let text = std::fs::read_to_string(filename)
+        .map_err(|err| format!("Could not read profile {}: {}", filename, err))?;
+    let mut lines = text.lines();
+    if lines.next() != Some(PROFILE_HEADER) {
+        return Err(format!("{} is not a rorth profile", filename));
+    }
+    let counts = lines.enumerate().map(|(ip, line)| match line.split_once(' ') {
+        Some((line_ip, count)) if line_ip == ip.to_string() => count.parse::<u64>().ok(),
+        _ => None,
+    }).collect::<Option<Vec<u64>>>().ok_or_else(|| format!("Malformed profile {}", filename))?;
+    if counts.len() != len {
+        return Err(format!("Profile {} has {} instructions, the program has {}", filename, counts.len(), len));
+    }
+    Ok(counts)
+}
+
+// debug function
+fn _dump_bytecode_to_string(program : &[Instruction]) -> String {
+    let mut bytecode_str = String::new();
+    for (i, ins) in program.iter().enumerate() {
+        bytecode_str += format!("{:>3}   {:?} {:>?}\n", i, ins.opcode, ins.operands).to_string().as_str();
+    }
+    bytecode_str
+}
+
+// debug function
+fn _dump_crossref(stack: &[usize]) {
+    print!("Crossref:");
+    for (i, val) in stack.iter().enumerate() {
+        print!("({}, {}) ", i, val);
+    }
+    println!();
+}
+
+// debug function
+fn _dump_stack(stack: &[i64]) {
+    print!("Stack: ");
+    for val in stack.iter() {
+        print!("({}) ", val);
+    }
+    println!();
+}
+
+// debug function
+fn _dump_stack_to_string(stack: &[i64]) -> String {
+    let mut stack_str = String::new();
+    for (i, val) in stack.iter().enumerate() {
+        stack_str += format!("({}, {}) \n", i, val).to_string().as_str();
+    }
+    stack_str
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn lexer(filename: &str) -> Vec<Token> {
+    lexer_with(filename, &LexOptions::default())
+}
+
+fn lexer_with(filename: &str, options: &LexOptions) -> Vec<Token> {
+    let source : String = std::fs::read_to_string(filename)
+        .unwrap_or_else(|_| panic!("Could not read file {}", filename));
+    lex_source_with(&source, filename, options)
+}
+
+// turns a word into the tokens it stands for, or None to leave it alone
+type TokenRecognizer = Box<dyn Fn(&str) -> Option<Vec<String>> + Send + Sync>;
+
+// what starts a comment and how words become tokens
+pub struct LexOptions {
+    // each of these starts a comment running to the end of the line
+    pub line_comments: Vec<String>,
+    // tried in order on every word, the first to recognize it replaces it
+    recognizers: Vec<TokenRecognizer>,
+}
+
+impl Default for LexOptions {
+    fn default() -> Self {
+        LexOptions { line_comments: vec![String::from("//")], recognizers: Vec::new() }
+    }
+}
+
+impl LexOptions {
+    // lets embedders add their own tokens without touching the lexer, the
+    // tokens a word is replaced with all get its location
+    pub fn recognize(&mut self, recognizer: impl Fn(&str) -> Option<Vec<String>> + Send + Sync + 'static) {
+        self.recognizers.push(Box::new(recognizer));
+    }
+
+    fn push_word(&self, tokens: &mut Vec<Token>, word: String, filename: &str, row: usize, col: usize) {
+        match self.recognizers.iter().find_map(|recognizer| recognizer(&word)) {
+            Some(words) => tokens.extend(words.into_iter().map(|word| Token::new(word, filename, row, col))),
+            None => tokens.push(Token::new(word, filename, row, col)),
+        }
+    }
+}
+
+// tab stops are this many columns apart
+const TAB_WIDTH: usize = 8;
+
+// warnings named by a `rorth: allow(unused, ...)` comment
+fn parse_pragma(comment: &str) -> Option<Vec<String>> {
+    let names = comment.trim().strip_prefix("rorth:")?.trim().strip_prefix("allow(")?.strip_suffix(')')?;
+    Some(names.split(',').map(|name| name.trim().to_string()).filter(|name| !name.is_empty()).collect())
+}
+
+// the code and the comment of a line, started by the first of `prefixes`
+// found outside a string literal
+fn split_comment<'a>(line: &'a str, prefixes: &[String]) -> (&'a str, &'a str) {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if in_string {
+            in_string = escaped || c != '"';
+            escaped = !escaped && c == '\\';
+        } else if c == '"' {
+            in_string = true;
+        } else if let Some(prefix) = prefixes.iter().find(|prefix| line[i..].starts_with(prefix.as_str())) {
+            return (&line[..i], &line[i+prefix.len()..]);
+        }
+    }
+    (line, "")
+}
+
+// splits the source into whitespace separated words, dropping comments
+// rows and cols are 0 based, cols count characters with tabs expanded to the
+// next tab stop, so they line up with the source as shown in a terminal
+// pragma comments attach to the token following them
+fn lex_source(source: &str, filename: &str) -> Vec<Token> {
+    lex_source_with(source, filename, &LexOptions::default())
+}
+
+fn lex_source_with(source: &str, filename: &str, options: &LexOptions) -> Vec<Token> {
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    let mut tokens : Vec<Token> = Vec::new();
+    let mut allow : Vec<String> = Vec::new();
+    // `lines` also strips the `\r` of `\r\n` line endings
+    for (row, line) in source.lines().enumerate() {
+        let (code, comment) = split_comment(line, &options.line_comments);
+        let first = tokens.len();
+        let mut word = String::new();
+        let mut word_col = 0;
+        let mut col = 0;
+        let mut in_string = false;
+        let mut escaped = false;
+        for c in code.chars() {
+            if word.is_empty() && c == '"' {
+                in_string = true;
+            } else if in_string {
+                in_string = escaped || c != '"';
+                escaped = !escaped && c == '\\';
+            }
+            if c.is_whitespace() && !in_string {
+                if !word.is_empty() {
+                    options.push_word(&mut tokens, std::mem::take(&mut word), filename, row, word_col);
+                }
+                col = if c == '\t' { (col / TAB_WIDTH + 1) * TAB_WIDTH } else { col + 1 };
+            } else {
+                if word.is_empty() {
+                    word_col = col;
+                }
+                word.push(c);
+                col += 1;
+            }
+        }
+        if !word.is_empty() {
+            options.push_word(&mut tokens, word, filename, row, word_col);
+        }
+        if let Some(tok) = tokens.get_mut(first) {
+            tok.allow.append(&mut allow);
+        }
+        if let Some(names) = parse_pragma(comment) {
+            allow.extend(names);
+        }
+    }
+    tokens
+}
+
+// warnings for variables declared but never referenced, unless their `var`
+// allows `unused`
+fn unused_variables(tokens: &[Token]) -> Vec<String> {
+    let mut warnings : Vec<String> = Vec::new();
+    for (i, tok) in tokens.iter().enumerate() {
+        let Some(name) = tokens.get(i + 1).filter(|_| tok.tok == "var") else { continue };
+        if tok.allow.iter().any(|allowed| allowed == "unused") {
+            continue;
+        }
+        if !tokens.iter().enumerate().any(|(j, other)| j != i + 1 && other.tok == name.tok) {
+            warnings.push(format!("[WARNING] {}:{}:{}: Variable `{}` is never used",
+                name.file, name.row+1, name.col+1, name.tok));
+        }
+    }
+    warnings
+}
+
+// concatenates the token streams of several files into a single program,
+// each token keeps track of the file it came from
+// files are lexed in parallel, at most one per available core at a time,
+// and their tokens concatenated in the order the files were given
+fn lex_files(filenames: &[String], options: &LexOptions, timings: &mut Timings) -> Vec<Token> {
+    let workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    timings.begin(&format!("lex {} file(s)", filenames.len()));
+    let mut tokens : Vec<Token> = Vec::new();
+    for chunk in filenames.chunks(workers) {
+        let lexed : Vec<(Vec<Token>, std::time::Duration)> = std::thread::scope(|scope| {
+            let handles : Vec<_> = chunk.iter().map(|filename| scope.spawn(move || {
+                let start = std::time::Instant::now();
+                let tokens = lexer_with(filename, options);
+                (tokens, start.elapsed())
+            })).collect();
+            handles.into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|err| std::panic::resume_unwind(err)))
+                .collect()
+        });
+        for (filename, (file_tokens, duration)) in chunk.iter().zip(lexed) {
+            timings.record_count(&format!("lex {}", filename), duration, file_tokens.len(), "tokens");
+            tokens.extend(file_tokens);
+        }
+    }
+    tokens
+}
+
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum TokenKind {
+    Keyword,
+    Number,
+    Word,
+    // name being declared by `var` or `struct`
+    Definition,
+    // use of a declared name
+    Name,
+    Comment,
+}
+
+const KEYWORDS: &[&str] = &["if", "else", "end", "while", "do", "var", "struct", "extern", "proc"];
+
+// words the parser turns into instructions, they can't be used as names
+const BUILTIN_WORDS: &[&str] = &[
+    "@", "store", "+", "-", "*", "/", "mod", "divmod", "pow", "sqrt", "neg", "abs", "1+", "1-", "gcd", "!",
+    "=", "!=", ">", ">=", "<", "<=", "shl", "shr", "bor", "band", "bxor", "bnot", "concat", "slice",
+    "strlen", "puts", "rotl", "rotr", "bswap16", "bswap32", "bswap64", "bswap", "popcount", "clz", "ctz",
+    "dump", "eprint", ".q", "dup", "swap", "rot", "2dup", "2drop", "2swap", "2over", "pick", "roll",
+    "depth", "and", "or", "base!", "true", "false",
+];
+
+fn is_reserved_name(name: &str) -> bool {
+    KEYWORDS.contains(&name) || BUILTIN_WORDS.contains(&name)
+}
+
+// registers of the first arguments of a call in the System V ABI
+const ARG_REGISTERS: &[&str] = &["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+
+// the name of an extern proc as operands of OP_CALL, 8 bytes in each
+fn pack_name(name: &str) -> Vec<i64> {
+    name.as_bytes().chunks(8).map(|chunk| {
+        let mut bytes = [0u8; 8];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        i64::from_le_bytes(bytes)
+    }).collect()
+}
+
+fn unpack_name(operands: &[i64]) -> String {
+    let bytes : Vec<u8> = operands.iter().flat_map(|operand| operand.to_le_bytes()).take_while(|&byte| byte != 0).collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl TokenKind {
+    fn css_class(&self) -> &'static str {
+        match self {
+            TokenKind::Keyword => "keyword",
+            TokenKind::Number => "number",
+            TokenKind::Word => "word",
+            TokenKind::Definition => "definition",
+            TokenKind::Name => "name",
+            TokenKind::Comment => "comment",
+        }
+    }
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// renders source as a <pre> block where every token is wrapped in a span
+// with the class of its kind, whitespace is kept as is
+fn highlight_html(source: &str, line_comments: &[String]) -> String {
+    let mut names : Vec<String> = Vec::new();
+    let mut define_next = false;
+    // inside a struct body, alternating between field names and sizes
+    let mut struct_field_next : Option<bool> = None;
+    let mut html = String::from("<pre class=\"rorth\">");
+    // the tokens are the ones the compiler sees, the text between them is copied from the source
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    let options = LexOptions { line_comments: line_comments.to_vec(), ..LexOptions::default() };
+    let tokens = lex_source_with(source, "", &options);
+    let mut tokens = tokens.iter().peekable();
+    for (row, line) in source.lines().enumerate() {
+        // the comment is shown with its prefix
+        let code = split_comment(line, line_comments).0;
+        let comment = &line[code.len()..];
+        // byte offset of each column counted by the lexer
+        let mut offsets : HashMap<usize, usize> = HashMap::new();
+        let mut col = 0;
+        for (offset, c) in code.char_indices() {
+            offsets.insert(col, offset);
+            col = if c == '\t' { (col / TAB_WIDTH + 1) * TAB_WIDTH } else { col + 1 };
+        }
+        let mut end = 0;
+        while let Some(tok) = tokens.next_if(|tok| tok.row == row) {
+            let start = offsets[&tok.col];
+            html += &code[end..start];
+            end = start + tok.tok.len();
+            let tok = tok.tok.as_str();
+            let kind = if define_next {
+                define_next = false;
+                names.push(tok.to_string());
+                TokenKind::Definition
+            } else if struct_field_next.is_some() && tok == "end" {
+                let name = names.last().unwrap().clone();
+                names.push(format!("sizeof({})", name));
+                struct_field_next = None;
+                TokenKind::Keyword
+            } else if let Some(field) = struct_field_next {
+                struct_field_next = Some(!field);
+                if field {
+                    let name = names.iter().rev().find(|name| !name.contains('.')).unwrap().clone();
+                    names.push(format!("{}.{}", name, tok));
+                    TokenKind::Definition
+                } else {
+                    TokenKind::Number
+                }
+            } else if KEYWORDS.contains(&tok) {
+                if tok == "var" || tok == "struct" {
+                    define_next = true;
+                }
+                if tok == "struct" {
+                    struct_field_next = Some(true);
+                }
+                TokenKind::Keyword
+            } else if parse_integer(tok).is_ok() || bool_literal(tok).is_some() {
+                TokenKind::Number
+            } else if names.iter().any(|name| name == tok) {
+                TokenKind::Name
+            } else {
+                TokenKind::Word
+            };
+            html += &format!("<span class=\"{}\">{}</span>", kind.css_class(), html_escape(tok));
+        }
+        html += &code[end..];
+        if !comment.is_empty() {
+            html += &format!("<span class=\"{}\">{}</span>", TokenKind::Comment.css_class(), html_escape(comment));
+        }
+        html += "\n";
+    }
+    html += "</pre>\n";
+    html
+}
+
+/**
+ * struct Point        // Point.x is 0, Point.y is 8, sizeof(Point) is 16
+ *     x 8
+ *     y 8
+ * end
+ *
+ * Struct declarations are removed from the token stream and every use of their
+ * offset and size constants is replaced by the corresponding integer, as are
+ * the names given with `-D`.
+ */
+fn expand_structs(tokens : Vec<Token>, defines : &[(String, i64)]) -> Vec<Token> {
+    expand_structs_with_constants(tokens, defines).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    }).0
+}
+
+// same as `expand_structs`, also returning the value of every constant
+fn expand_structs_with_constants(tokens : Vec<Token>, defines : &[(String, i64)]) -> Result<(Vec<Token>, HashMap<String, i64>), String> {
+    let mut constants : HashMap<String, i64> = defines.iter().cloned().collect();
+    let mut expanded : Vec<Token> = Vec::new();
+    let mut tokens = tokens.into_iter();
+    while let Some(mut tok) = tokens.next() {
+        if tok.tok != "struct" {
+            if let Some(value) = constants.get(&tok.tok) {
+                tok.tok = value.to_string();
+            }
+            expanded.push(tok);
+            continue;
+        }
+        let name = match tokens.next() {
+            Some(name) if name.tok != "end" => name,
+            _ => {
+                return Err(format!("[ERROR] {}:{}:{}: Expected struct name after `struct`",
+                    tok.file, tok.row+1, tok.col+1));
+            }
+        };
+        if constants.contains_key(&format!("sizeof({})", name.tok)) {
+            return Err(format!("[ERROR] {}:{}:{}: Redefinition of struct `{}`",
+                name.file, name.row+1, name.col+1, name.tok));
+        }
+        let mut offset : i64 = 0;
+        loop {
+            let field = match tokens.next() {
+                Some(field) => field,
+                None => {
+                    return Err(format!("[ERROR] {}:{}:{}: Found `struct` without matching `end`",
+                        tok.file, tok.row+1, tok.col+1));
+                }
+            };
+            if field.tok == "end" {
+                break;
+            }
+            let size = match tokens.next().map(|size| (parse_integer(&size.tok), size)) {
+                Some((Ok(size), _)) if size > 0 => size,
+                Some((_, size)) => {
+                    return Err(format!("[ERROR] {}:{}:{}: Expected a positive size for field `{}`, got {}",
+                        size.file, size.row+1, size.col+1, field.tok, size.tok));
+                },
+                None => {
+                    return Err(format!("[ERROR] {}:{}:{}: Expected a size for field `{}`",
+                        field.file, field.row+1, field.col+1, field.tok));
+                }
+            };
+            constants.insert(format!("{}.{}", name.tok, field.tok), offset);
+            offset += size;
+        }
+        constants.insert(format!("sizeof({})", name.tok), offset);
+    }
+    Ok((expanded, constants))
+}
+
+// parses a decimal literal, or a hexadecimal one prefixed with `0x` giving
+// the 64-bit pattern of the value
+fn parse_integer(tok: &str) -> Result<i64, String> {
+    if let Some(digits) = tok.strip_prefix("0x") {
+        if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return u64::from_str_radix(digits, 16)
+                .map(|value| value as i64)
+                .map_err(|_| format!("Integer literal `{}` does not fit in 64 bits", tok));
+        }
+    }
+    let digits = tok.strip_prefix('-').unwrap_or(tok);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("Expected integer, got {}", tok));
+    }
+    tok.parse::<i64>().map_err(|_| {
+        let mut err = format!("Integer literal `{}` does not fit in 64 bits, the range is {} to {}",
+            tok, i64::MIN, i64::MAX);
+        if let Ok(value) = tok.parse::<u64>() {
+            err += &format!(", write it as 0x{:X} to use its 64-bit pattern", value);
+        }
+        err
+    })
+}
+
+// `true` and `false` push 1 and 0, the values comparisons give
+fn bool_literal(tok: &str) -> Option<i64> {
+    match tok {
+        "true" => Some(1),
+        "false" => Some(0),
+        _ => None,
+    }
+}
+
+// radixes `dump` can print numbers in, hexadecimal and binary show the 64-bit
+// pattern of the value
+const DUMP_RADIXES: &[i64] = &[2, 10, 16];
+
+// the text of a `"..."` token, with `\n`, `\t`, `\"` and `\\` escapes
+fn parse_string_literal(tok: &str) -> Result<String, String> {
+    let mut text = String::new();
+    let mut chars = tok.chars().skip(1);
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if chars.next().is_none() => return Ok(text),
+            '"' => return Err(format!("Unexpected characters after the string {}", tok)),
+            '\\' => text.push(match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('"') => '"',
+                Some('\\') => '\\',
+                other => return Err(format!("Unknown escape `\\{}` in string", other.map(String::from).unwrap_or_default())),
+            }),
+            c => text.push(c),
+        }
+    }
+    Err(String::from("Unterminated string"))
+}
+
+fn format_radix(value: i64, radix: i64) -> String {
+    match radix {
+        2 => format!("0b{:b}", value as u64),
+        16 => format!("0x{:x}", value as u64),
+        _ => value.to_string(),
+    }
+}
+
+// makes the `dump`s not preceded by a `base!` print in `radix`
+fn set_dump_radix(program: &mut [Instruction], radix: i64) {
+    for ins in program.iter_mut().filter(|ins| ins.opcode == Opcode::OP_DUMP && ins.operands.is_empty()) {
+        ins.operands.push(radix);
+    }
+}
+
+// the word of an instruction of a block
+fn block_keyword(opcode: &Opcode) -> &'static str {
+    match opcode {
+        Opcode::OP_IF => "if",
+        Opcode::OP_ELSE => "else",
+        Opcode::OP_WHILE => "while",
+        Opcode::OP_DO => "do",
+        _ => "end",
+    }
+}
+
+/**
+ * +---------------------+       +-------------------+
+ * |        IF           |       |     WHILE    <-+  |
+ * |    <condition> --+  |       |  <condition>   |  |
+ * |        .     |   |  |       |      DO        |  |
+ * |        .     |   |  |       |      .         |  |
+ * |       ELSE <-+   |  |       |      .         |  |
+ * |        .         |  |       |      .         |  |
+ * |        .         |  |       |      .         |  |
+ * |        .         |  |       |      .         |  |
+ * |        END       |  |       |      END ------+  |
+ * |           <------+  |       |                   |
+ * |                     |       |                   |
+ * +---------------------+       +-------------------+
+ */
+fn parser(tokens : &[Token]) -> Vec<Instruction> {
+    parse_program(tokens).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    })
+}
+
+fn parse_program(tokens : &[Token]) -> Result<Vec<Instruction>, String> {
+    let mut program : Vec<Instruction> = Vec::new();
+    let mut crossref : Vec<usize> = Vec::new();
+    let mut globals : HashMap<String, usize> = HashMap::new();
+    let mut var_decl : Option<&Token> = None;
+    // `extern proc NAME INPUTS OUTPUTS`, collected until complete
+    let mut extern_decl : Vec<&Token> = Vec::new();
+    let mut externs : HashMap<String, (i64, i64)> = HashMap::new();
+    let mut prev_is_global = false;
+    // radix of the last `base!`, the `dump`s after it in the source take it
+    let mut dump_radix : Option<i64> = None;
+    for tok in tokens.iter() {
+        let ip = program.len();
+        if tok.tok == "base!" {
+            match program.last().filter(|ins| ins.opcode == Opcode::OP_PUSH).map(|ins| ins.operands[0]) {
+                Some(radix) if DUMP_RADIXES.contains(&radix) => {
+                    program.pop();
+                    dump_radix = Some(radix);
+                },
+                _ => {
+                    return Err(format!("[ERROR] {}:{}:{}: @ip {}: Expected a radix of 2, 10 or 16 right before `base!`",
+                        tok.file, tok.row+1, tok.col+1, ip));
+                },
+            }
+            prev_is_global = false;
+            continue;
+        }
+        if let Some(var_tok) = var_decl.take() {
+            if parse_integer(&tok.tok).is_ok() || is_reserved_name(&tok.tok) || globals.contains_key(&tok.tok) {
+                return Err(format!("[ERROR] {}:{}:{}: Invalid or redefined variable name `{}` after `{}`",
+                    tok.file, tok.row+1, tok.col+1, tok.tok, var_tok.tok));
+            }
+            globals.insert(tok.tok.clone(), globals.len());
+            continue;
+        }
+        if tok.tok == "var" {
+            var_decl = Some(tok);
+            continue;
+        }
+        if !extern_decl.is_empty() || tok.tok == "extern" {
+            extern_decl.push(tok);
+            if extern_decl.len() == 5 {
+                let (name, counts) = parse_extern_decl(&extern_decl, &globals, &externs)
+                    .map_err(|err| format!("[ERROR] {}", err))?;
+                externs.insert(name, counts);
+                extern_decl.clear();
+            }
+            continue;
+        }
+        if tok.tok == "!" && prev_is_global {
+            program.push(Instruction::new(Opcode::OP_STORE, vec![], ip));
+        }
+        else if let Some(&index) = globals.get(&tok.tok) {
+            program.push(Instruction::new(Opcode::OP_GLOBAL, vec![index as i64], ip));
+        }
+        else if tok.tok.starts_with('"') {
+            let text = parse_string_literal(&tok.tok)
+                .map_err(|err| format!("[ERROR] {}:{}:{}: @ip {}: {}", tok.file, tok.row+1, tok.col+1, ip, err))?;
+            let mut operands = vec![text.len() as i64];
+            operands.extend(pack_name(&text));
+            program.push(Instruction::new(Opcode::OP_STRING, operands, ip));
+        }
+        else if let Some(&(inputs, outputs)) = externs.get(&tok.tok) {
+            let operands = [inputs, outputs].into_iter().chain(pack_name(&tok.tok)).collect();
+            program.push(Instruction::new(Opcode::OP_CALL, operands, ip));
+        }
+        else if tok.tok == "@"      { program.push(Instruction::new(Opcode::OP_LOAD, vec![], ip)); }
+        else if tok.tok == "store"  { program.push(Instruction::new(Opcode::OP_STORE, vec![], ip)); }
+        else if tok.tok == "+"           { program.push(Instruction::new(Opcode::OP_ADD, vec![], ip)); }
+        else if tok.tok == "-"      { program.push(Instruction::new(Opcode::OP_SUB, vec![], ip)); }
+        else if tok.tok == "*"      { program.push(Instruction::new(Opcode::OP_MUL, vec![], ip)); }
+        else if tok.tok == "/"      { program.push(Instruction::new(Opcode::OP_DIV, vec![], ip)); }
+        else if tok.tok == "mod"    { program.push(Instruction::new(Opcode::OP_MOD, vec![], ip)); }
+        else if tok.tok == "divmod" { program.push(Instruction::new(Opcode::OP_DIVMOD, vec![], ip)); }
+        else if tok.tok == "pow"    { program.push(Instruction::new(Opcode::OP_POW, vec![], ip)); }
+        else if tok.tok == "sqrt"   { program.push(Instruction::new(Opcode::OP_SQRT, vec![], ip)); }
+        else if tok.tok == "neg"    { program.push(Instruction::new(Opcode::OP_NEG, vec![], ip)); }
+        else if tok.tok == "abs"    { program.push(Instruction::new(Opcode::OP_ABS, vec![], ip)); }
+        else if tok.tok == "1+"     { program.push(Instruction::new(Opcode::OP_INC, vec![], ip)); }
+        else if tok.tok == "1-"     { program.push(Instruction::new(Opcode::OP_DEC, vec![], ip)); }
+        else if tok.tok == "gcd"    { program.push(Instruction::new(Opcode::OP_GCD, vec![], ip)); }
+        else if tok.tok == "!"      { program.push(Instruction::new(Opcode::OP_NOT, vec![], ip)); }
+        else if tok.tok == "="      { program.push(Instruction::new(Opcode::OP_EQ, vec![], ip)); }
+        else if tok.tok == "!="     { program.push(Instruction::new(Opcode::OP_NE, vec![], ip)); }
+        else if tok.tok == ">"      { program.push(Instruction::new(Opcode::OP_GT, vec![], ip)); }
+        else if tok.tok == ">="     { program.push(Instruction::new(Opcode::OP_GE, vec![], ip)); }
+        else if tok.tok == "<"      { program.push(Instruction::new(Opcode::OP_LT, vec![], ip)); }
+        else if tok.tok == "<="     { program.push(Instruction::new(Opcode::OP_LE, vec![], ip)); }
+        else if tok.tok == "shl"    { program.push(Instruction::new(Opcode::OP_SHL, vec![], ip)); }
+        else if tok.tok == "shr"    { program.push(Instruction::new(Opcode::OP_SHR, vec![], ip)); }
+        else if tok.tok == "bor"    { program.push(Instruction::new(Opcode::OP_BOR, vec![], ip)); }
+        else if tok.tok == "band"   { program.push(Instruction::new(Opcode::OP_BAND, vec![], ip)); }
+        else if tok.tok == "bxor"   { program.push(Instruction::new(Opcode::OP_BXOR, vec![], ip)); }
+        else if tok.tok == "bnot"   { program.push(Instruction::new(Opcode::OP_BNOT, vec![], ip)); }
+        else if tok.tok == "concat" { program.push(Instruction::new(Opcode::OP_CONCAT, vec![], ip)); }
+        else if tok.tok == "slice"  { program.push(Instruction::new(Opcode::OP_SLICE, vec![], ip)); }
+        else if tok.tok == "strlen" { program.push(Instruction::new(Opcode::OP_STRLEN, vec![], ip)); }
+        else if tok.tok == "puts"   { program.push(Instruction::new(Opcode::OP_PUTS, vec![], ip)); }
+        else if tok.tok == "rotl"   { program.push(Instruction::new(Opcode::OP_ROTL, vec![], ip)); }
+        else if tok.tok == "rotr"   { program.push(Instruction::new(Opcode::OP_ROTR, vec![], ip)); }
+        else if tok.tok == "bswap16" { program.push(Instruction::new(Opcode::OP_BSWAP16, vec![], ip)); }
+        else if tok.tok == "bswap32" { program.push(Instruction::new(Opcode::OP_BSWAP32, vec![], ip)); }
+        // `bswap` swaps the whole word
+        else if tok.tok == "bswap64" || tok.tok == "bswap" { program.push(Instruction::new(Opcode::OP_BSWAP64, vec![], ip)); }
+        else if tok.tok == "popcount" { program.push(Instruction::new(Opcode::OP_POPCOUNT, vec![], ip)); }
+        else if tok.tok == "clz"    { program.push(Instruction::new(Opcode::OP_CLZ, vec![], ip)); }
+        else if tok.tok == "ctz"    { program.push(Instruction::new(Opcode::OP_CTZ, vec![], ip)); }
+        else if tok.tok == "dump"   { program.push(Instruction::new(Opcode::OP_DUMP, dump_radix.into_iter().collect(), ip)); }
+        else if tok.tok == "eprint" { program.push(Instruction::new(Opcode::OP_EPRINT, vec![], ip)); }
+        else if tok.tok == ".q"     { program.push(Instruction::new(Opcode::OP_DUMPQ, vec![], ip)); }
+        else if tok.tok == "dup"    { program.push(Instruction::new(Opcode::OP_DUP, vec![], ip)); }
+        else if tok.tok == "swap"   { program.push(Instruction::new(Opcode::OP_SWAP, vec![], ip)); }
+        else if tok.tok == "rot"    { program.push(Instruction::new(Opcode::OP_ROT, vec![], ip)); }
+        else if tok.tok == "2dup"   { program.push(Instruction::new(Opcode::OP_2DUP, vec![], ip)); }
+        else if tok.tok == "2drop"  { program.push(Instruction::new(Opcode::OP_2DROP, vec![], ip)); }
+        else if tok.tok == "2swap"  { program.push(Instruction::new(Opcode::OP_2SWAP, vec![], ip)); }
+        else if tok.tok == "2over"  { program.push(Instruction::new(Opcode::OP_2OVER, vec![], ip)); }
+        else if tok.tok == "pick"   { program.push(Instruction::new(Opcode::OP_PICK, vec![], ip)); }
+        else if tok.tok == "roll"   { program.push(Instruction::new(Opcode::OP_ROLL, vec![], ip)); }
+        else if tok.tok == "depth"  { program.push(Instruction::new(Opcode::OP_DEPTH, vec![], ip)); }
+        else if tok.tok == "and"    { program.push(Instruction::new(Opcode::OP_AND, vec![], ip)); }
+        else if tok.tok == "or"     { program.push(Instruction::new(Opcode::OP_OR, vec![], ip)); }
+        else if let Some(value) = bool_literal(&tok.tok) { program.push(Instruction::new(Opcode::OP_PUSH, vec![value], ip)); }
+        else if tok.tok == "if" {
+            program.push(Instruction::new(Opcode::OP_IF, vec![], ip));
+            crossref.push(ip);
+        }
+        else if tok.tok == "else" {
+            program.push(Instruction::new(Opcode::OP_ELSE, vec![], ip));
+            if let Some(if_ip) = crossref.pop() {
+                if program[if_ip].opcode != Opcode::OP_IF {
+                    return Err(format!("[ERROR] {}:{}:{}: @ip {}: Found `else` without matching `if`",
+                        tok.file, tok.row+1, tok.col+1, ip));
+                }
+                program[if_ip].operands.push(ip as i64);
+                crossref.push(ip);
+        } else {
+                return Err(format!("[ERROR] {}:{}:{}: @ip {}: Found `else` without matching `if`",
+                    tok.file, tok.row+1, tok.col+1, ip));
+            }
+        }
+        else if tok.tok == "while" {
+            program.push(Instruction::new(Opcode::OP_WHILE, vec![], ip));
+            crossref.push(program[ip].ip);
+        }
+        else if tok.tok == "do" {
+            if let Some(while_ip) = crossref.pop() {
+                if program[while_ip].opcode != Opcode::OP_WHILE {
+                    return Err(format!("[ERROR] {}:{}:{}: @ip {}: Found `while` without matching `do`",
+                        tok.file, tok.row+1, tok.col+1, ip));
+                }
+                program.push(Instruction::new(Opcode::OP_DO, vec![], ip));
+                program[ip].operands.push(while_ip as i64);
+                crossref.push(program[ip].ip);
+            } else {
+                return Err(format!("[ERROR] {}:{}:{}: @ip {}: Found `do` without matching `while`",
+                    tok.file, tok.row+1, tok.col+1, ip));
+            }
+        }
+        //TODO: support nested whiles
+        else if tok.tok == "end" {
+            // three situations
+            // situation 1 : if -> end
+            // situation 2 : if -> else -> end
+            // situation 3 : while -> do -> end
+            // --- situation 1 ---
+            //      if points to end+1, end fallsthrough
+            // --- situation 2 ---
+            //      if points to else+1, else fallsthrough, end fallsthrough
+            // --- situation 3 ---
+            // do points to end+1, end points to while+1
+            program.push(Instruction::new(Opcode::OP_END, vec![], ip));
+            if let Some(prev_ip) = crossref.pop() {
+                // situation 1 or 2
+                if program[prev_ip].opcode == Opcode::OP_IF
+                    || program[prev_ip].opcode == Opcode::OP_ELSE {
+                    program[prev_ip].operands.push(ip as i64);
+                }
+                if program[prev_ip].opcode == Opcode::OP_WHILE {
+                    return Err(format!("[ERROR] {}:{}:{}: @ip {}: Found `while` without matching `do`",
+                        tok.file, tok.row+1, tok.col+1, ip));
+                }
+                // situation 3, DO has WHILE's ip in its operands
+                if program[prev_ip].opcode == Opcode::OP_DO {
+                    if let Some(while_ip) = program[prev_ip].operands.pop() {
+                        program[ip].operands.push(while_ip);
+                        program[prev_ip].operands.push(ip as i64);
+                    } else {
+                        return Err(format!("[ERROR] {}:{}:{}: @ip {}:Found `do` without matching `while`",
+                            tok.file, tok.row+1, tok.col+1, ip));
+                    }
+                }
+            } else {
+                return Err(format!("[ERROR] {}:{}:{}: @ip {}: Found `end` without matching `if-else` or `while-do`",
+                    tok.file, tok.row+1, tok.col+1, ip));
+            }
+        }
+        else {
+            let immediate = parse_integer(&tok.tok)
+                .map_err(|err| format!("[ERROR] {}:{}:{}: @ip {}: {}", tok.file, tok.row+1, tok.col+1, ip, err))?;
+            program.push(Instruction::new(Opcode::OP_PUSH, vec![immediate], ip));
+        }
+        prev_is_global = program[ip].opcode == Opcode::OP_GLOBAL;
+        program[ip].loc = tok.loc();
+    }
+    if let Some(var_tok) = var_decl {
+        return Err(format!("[ERROR] {}:{}:{}: Expected variable name after `var`",
+            var_tok.file, var_tok.row+1, var_tok.col+1));
+    }
+    if let Some(extern_tok) = extern_decl.first() {
+        return Err(format!("[ERROR] {}:{}:{}: Expected `proc NAME INPUTS OUTPUTS` after `extern`",
+            extern_tok.file, extern_tok.row+1, extern_tok.col+1));
+    }
+    if let Some(&open_ip) = crossref.last() {
+        let open = &program[open_ip];
+        return Err(format!("[ERROR] {}:{}:{}: @ip {}: Found `{}` without matching `end`",
+            open.loc.file, open.loc.row+1, open.loc.col+1, open_ip, block_keyword(&open.opcode)));
+    }
+    Ok(program)
+}
+
+// the name, and the number of values popped as arguments and pushed as result
+// of `extern proc NAME INPUTS OUTPUTS`
+fn parse_extern_decl(decl: &[&Token], globals: &HashMap<String, usize>, externs: &HashMap<String, (i64, i64)>)
+    -> Result<(String, (i64, i64)), String> {
+    let error = |tok: &Token, err: String| Err(format!("{}:{}:{}: {}", tok.file, tok.row+1, tok.col+1, err));
+    if decl[1].tok != "proc" {
+        return error(decl[1], format!("Expected `proc` after `extern`, got {}", decl[1].tok));
+    }
+    let name = &decl[2].tok;
+    let is_symbol = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !is_symbol || is_reserved_name(name) || globals.contains_key(name) || externs.contains_key(name) {
+        return error(decl[2], format!("Invalid or redefined extern proc name `{}`", name));
+    }
+    let inputs = match parse_integer(&decl[3].tok) {
+        Ok(inputs) if (0..=ARG_REGISTERS.len() as i64).contains(&inputs) => inputs,
+        _ => return error(decl[3], format!("Expected 0 to {} inputs for `{}`, got {}", ARG_REGISTERS.len(), name, decl[3].tok)),
+    };
+    let outputs = match parse_integer(&decl[4].tok) {
+        Ok(outputs) if (0..=1).contains(&outputs) => outputs,
+        _ => return error(decl[4], format!("Expected 0 or 1 outputs for `{}`, got {}", name, decl[4].tok)),
+    };
+    Ok((name.clone(), (inputs, outputs)))
+}
+
+// `history` holds the steps already known, either recorded with `--record` or executed
+// in this session; `cursor` is how many of them are applied to the current state
+fn debug(program : &[Instruction], mut history : Vec<TraceStep>) {
+    let mut stack : Vec<i64> = Vec::new();
+    let mut globals : Vec<i64> = vec![0; globals_count(program)];
+    let mut ip : usize = 0;
+    let mut cursor : usize = 0;
+
+    let stdin = io::stdin();
+    print!("> ");
+    io::stdout().flush().expect("Unable to flush stdout");
+    for line in stdin.lock().lines() {
+        match line {
+            Err(_) => break,
+            Ok(s) => {
+                if s == "h" || s == "help" {
+                    println!("Possible commands: [n]ext, [e]xit, [s]tack, [g]lobals, [l]ine, [p]rint ip, [b]ytecode");
+                    println!("                   set SLOT VALUE, push VALUE, pop, jump IP, jump line LINE");
+                    println!("                   step-[b]ack, [r]eplay (apply all recorded steps)");
+                } else if s == "n" || s == "next" {
+                    if cursor < history.len() {
+                        match history[cursor].redo(&mut ip, &mut stack, &mut globals) {
+                            Ok(()) => cursor += 1,
+                            Err(err) => println!("[ERROR] {}", err),
+                        }
+                    } else if ip < program.len() {
+                        let (before, globals_before) = (stack.clone(), globals.clone());
+                        let next_ip = interpret_single_instruction(program, ip, &mut stack, &mut globals, &mut io::stdout(), &mut io::stderr());
+                        history.push(TraceStep::new(ip, next_ip, &before, &stack, &globals_before, &globals));
+                        cursor += 1;
+                        ip = next_ip;
+                    } else {
+                        println!("Program finished");
+                    }
+                } else if s == "sb" || s == "step-back" {
+                    if cursor > 0 {
+                        match history[cursor - 1].undo(&mut ip, &mut stack, &mut globals) {
+                            Ok(()) => cursor -= 1,
+                            Err(err) => println!("[ERROR] {}", err),
+                        }
+                    } else {
+                        println!("Already at the start of the program");
+                    }
+                } else if s == "r" || s == "replay" {
+                    while cursor < history.len() {
+                        if let Err(err) = history[cursor].redo(&mut ip, &mut stack, &mut globals) {
+                            println!("[ERROR] {}", err);
+                            break;
+                        }
+                        cursor += 1;
+                    }
+                    println!("ip: {}", ip);
+                } else if s == "e" || s == "exit" {
+                    break;
+                } else if s == "s" || s == "stack" {
+                    _dump_stack(&stack);
+                } else if s == "g" || s == "globals" {
+                    _dump_stack(&globals);
+                } else if s == "p" || s == "print" {
+                    println!("ip: {}", ip);
+                } else if s == "b" || s == "bytecode" {
+                    _dump_bytecode(program);
+                } else if s.starts_with("set ") || s.starts_with("push ") || s == "pop" || s.starts_with("jump ") {
+                    let (before_ip, before) = (ip, stack.clone());
+                    match debug_modify_state(&s, program, &mut ip, &mut stack) {
+                        Ok(()) => {
+                            history.truncate(cursor);
+                            history.push(TraceStep::new(before_ip, ip, &before, &stack, &globals, &globals));
+                            cursor += 1;
+                        },
+                        Err(err) => println!("{}", err),
+                    }
+                } else if (s == "l" || s == "line") && ip < program.len() {
+                    if ip > 0 {                 println!("    {:>3}   {:?}\t{:>?}", ip-1, program[ip-1].opcode, program[ip-1].operands); }
+                                                println!("--> {:>3}   {:?}\t{:>?}", ip,   program[ip].opcode, program[ip].operands);
+                    if ip + 1 < program.len() { println!("    {:>3}   {:?}\t{:>?}", ip+1, program[ip+1].opcode, program[ip+1].operands); }
+                } else if s.is_empty() {
+
+                } else {
+                    println !("Unknown command: {}", s);
+                }
+                print!("> ");
+                io::stdout().flush().expect("Unable to flush stdout");
+            }
+        }
+    }
+    println!();
+}
+
+// handles the debugger commands that modify the state of the program:
+//   set SLOT VALUE   overwrites stack slot SLOT (0 is the bottom of the stack)
+//   push VALUE       pushes VALUE to the stack
+//   pop              pops the top of the stack
+//   jump IP          continues execution at instruction IP
+//   jump line LINE   continues execution at the first instruction of source line LINE
+fn debug_modify_state(command : &str, program : &[Instruction], ip : &mut usize, stack : &mut Vec<i64>) -> Result<(), String> {
+    let parse_int = |s : &str| s.parse::<i64>().map_err(|_| format!("Expected integer, got {}", s));
+    let parse_index = |s : &str| s.parse::<usize>().map_err(|_| format!("Expected index, got {}", s));
+    let words : Vec<&str> = command.split_whitespace().collect();
+    match words.as_slice() {
+        ["set", slot, value] => {
+            let slot = parse_index(slot)?;
+            let value = parse_int(value)?;
+            match stack.get_mut(slot) {
+                Some(val) => *val = value,
+                None => return Err(format!("Stack slot {} out of range, stack has {} values", slot, stack.len())),
+            }
+        },
+        ["push", value] => {
+            stack.push(parse_int(value)?);
+        },
+        ["pop"] => {
+            if stack.pop().is_none() {
+                return Err(String::from("Tried to pop but stack was empty"));
+            }
+        },
+        ["jump", "line", line] => {
+            let line = parse_index(line)?;
+            match program.iter().position(|ins| ins.loc.row + 1 == line) {
+                Some(target) => *ip = target,
+                None => return Err(format!("No instruction found at line {}", line)),
+            }
+        },
+        ["jump", target] => {
+            let target = parse_index(target)?;
+            if target >= program.len() {
+                return Err(format!("ip {} out of range, program has {} instructions", target, program.len()));
+            }
+            *ip = target;
+        },
+        _ => return Err(format!("Invalid command: {}", command)),
+    }
+    Ok(())
+}
+
+// the change to the state of the program caused by executing the instruction at `ip`,
+// enough to redo or undo it
+#[derive(PartialEq, Eq, Debug, Clone)]
+struct TraceStep {
+    ip: usize,
+    next_ip: usize,
+    popped: Vec<i64>,
+    pushed: Vec<i64>,
+    // (global, old value, new value)
+    store: Option<(usize, i64, i64)>,
+}
+
+impl TraceStep {
+    fn new(ip: usize, next_ip: usize, before: &[i64], after: &[i64], globals_before: &[i64], globals_after: &[i64]) -> Self {
+        let common = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+        let store = globals_before.iter().zip(globals_after).enumerate()
+            .find(|(_, (old, new))| old != new)
+            .map(|(global, (&old, &new))| (global, old, new));
+        TraceStep { ip, next_ip, popped: before[common..].to_vec(), pushed: after[common..].to_vec(), store }
+    }
+
+    fn redo(&self, ip: &mut usize, stack: &mut Vec<i64>, globals: &mut [i64]) -> Result<(), String> {
+        self.check(stack, &self.popped, globals)?;
+        stack.truncate(stack.len() - self.popped.len());
+        stack.extend(&self.pushed);
+        if let Some((global, _, new)) = self.store {
+            globals[global] = new;
+        }
+        *ip = self.next_ip;
+        Ok(())
+    }
+
+    fn undo(&self, ip: &mut usize, stack: &mut Vec<i64>, globals: &mut [i64]) -> Result<(), String> {
+        self.check(stack, &self.pushed, globals)?;
+        stack.truncate(stack.len() - self.pushed.len());
+        stack.extend(&self.popped);
+        if let Some((global, old, _)) = self.store {
+            globals[global] = old;
+        }
+        *ip = self.ip;
+        Ok(())
+    }
+
+    // whether the step can be applied to the state, removing `removed` from the stack
+    fn check(&self, stack: &[i64], removed: &[i64], globals: &[i64]) -> Result<(), String> {
+        if removed.len() > stack.len() {
+            return Err(format!("@ip {}: Step removes {} values but stack has {}", self.ip, removed.len(), stack.len()));
+        }
+        match self.store {
+            Some((global, _, _)) if global >= globals.len() => {
+                Err(format!("@ip {}: Step stores to global {} but program has {}", self.ip, global, globals.len()))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /**
+     * ip (u64) | next ip (u64) | popped count (u32) | popped (i64 each) | pushed count (u32) | pushed (i64 each)
+     * | has store (u8) | [global (u64) | old value (i64) | new value (i64)]
+     * all integers are little endian
+     */
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes : Vec<u8> = Vec::new();
+        bytes.extend_from_slice(&(self.ip as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.next_ip as u64).to_le_bytes());
+        for values in [&self.popped, &self.pushed] {
+            bytes.extend_from_slice(&(values.len() as u32).to_le_bytes());
+            for val in values {
+                bytes.extend_from_slice(&val.to_le_bytes());
+            }
+        }
+        match self.store {
+            Some((global, old, new)) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&(global as u64).to_le_bytes());
+                bytes.extend_from_slice(&old.to_le_bytes());
+                bytes.extend_from_slice(&new.to_le_bytes());
+            },
+            None => bytes.push(0),
+        }
+        bytes
+    }
+}
+
+// 3: the steps refer to programs of bytecode version 2
+// 4: the header has the hash of the program that was recorded
+const TRACE_VERSION: u32 = 4;
+const TRACE_MAGIC: &[u8; 8] = b"RORTHTR\0";
+
+// reads a trace written by `interpret_recording` from `program`, a trace cut
+// short by a crash is read up to its last complete step
+fn read_trace(filename: &str, program: &[Instruction]) -> Result<Vec<TraceStep>, String> {
+    let bytes = std::fs::read(filename)
+        .map_err(|err| format!("Could not read trace {}: {}", filename, err))?;
+    if bytes.len() < 12 || &bytes[0..8] != TRACE_MAGIC {
+        return Err(format!("{} is not a rorth trace", filename));
+    }
+    let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    if version != TRACE_VERSION {
+        return Err(format!("Unsupported trace version {}, expected {}", version, TRACE_VERSION));
+    }
+    if bytes.get(12..20) != Some(&Checkpoint::program_hash(program).to_le_bytes()[..]) {
+        return Err(format!("Trace {} was recorded from a different program", filename));
+    }
+    let mut pos = 20;
+    let mut take = |n: usize| -> Option<&[u8]> {
+        let chunk = bytes.get(pos..pos+n)?;
+        pos += n;
+        Some(chunk)
+    };
+    let mut steps : Vec<TraceStep> = Vec::new();
+    'steps: while let (Some(ip), Some(next_ip)) = (take(8), take(8)) {
+        let ip = u64::from_le_bytes(ip.try_into().unwrap()) as usize;
+        let next_ip = u64::from_le_bytes(next_ip.try_into().unwrap()) as usize;
+        let mut values : [Vec<i64>; 2] = [Vec::new(), Vec::new()];
+        for vals in values.iter_mut() {
+            let Some(count) = take(4) else { break 'steps };
+            for _ in 0..u32::from_le_bytes(count.try_into().unwrap()) {
+                let Some(val) = take(8) else { break 'steps };
+                vals.push(i64::from_le_bytes(val.try_into().unwrap()));
+            }
+        }
+        let [popped, pushed] = values;
+        let store = match take(1) {
+            Some([0]) => None,
+            Some(_) => {
+                let (Some(global), Some(old), Some(new)) = (take(8), take(8), take(8)) else { break 'steps };
+                Some((u64::from_le_bytes(global.try_into().unwrap()) as usize,
+                    i64::from_le_bytes(old.try_into().unwrap()),
+                    i64::from_le_bytes(new.try_into().unwrap())))
+            },
+            None => break 'steps,
+        };
+        steps.push(TraceStep { ip, next_ip, popped, pushed, store });
+    }
+    Ok(steps)
+}
+
+// same as `interpret`, but writes every step to `trace` as soon as it is executed,
+// so the trace is complete up to the failing instruction even if the program aborts
+fn interpret_recording<W: Write, T: Write>(program : &[Instruction], stdout : &mut W, trace : &mut T) {
+    trace.write_all(TRACE_MAGIC).unwrap();
+    trace.write_all(&TRACE_VERSION.to_le_bytes()).unwrap();
+    trace.write_all(&Checkpoint::program_hash(program).to_le_bytes()).unwrap();
+    let mut stack : Vec<i64> = Vec::new();
+    let mut globals : Vec<i64> = vec![0; globals_count(program)];
+    let mut ip = 0;
+    while ip < program.len() {
+        let (before, globals_before) = (stack.clone(), globals.clone());
+        let next_ip = interpret_single_instruction(program, ip, &mut stack, &mut globals, stdout, &mut io::stderr());
+        trace.write_all(&TraceStep::new(ip, next_ip, &before, &stack, &globals_before, &globals).to_bytes()).unwrap();
+        ip = next_ip;
+    }
+}
+
+// the interpreter state between two instructions, saved by `--checkpoint` and
+// loaded back by `--resume`
+#[derive(Debug, PartialEq)]
+struct Checkpoint {
+    ip: usize,
+    stack: Vec<i64>,
+    globals: Vec<i64>,
+}
+
+const CHECKPOINT_VERSION: u32 = 1;
+const CHECKPOINT_MAGIC: &[u8; 8] = b"RORTHCP\0";
+
+impl Checkpoint {
+    fn new(program : &[Instruction]) -> Self {
+        Checkpoint { ip: 0, stack: Vec::new(), globals: vec![0; globals_count(program)] }
+    }
+
+    // a checkpoint only makes sense for the program that saved it
+    fn program_hash(program : &[Instruction]) -> u64 {
+        fnv1a(0xcbf29ce484222325, program_to_cache(program).as_bytes())
+    }
+
+    /**
+     * magic | version (u32) | program hash (u64) | ip (u64)
+     * | stack count (u32) | stack (i64 each) | globals count (u32) | globals (i64 each)
+     * all integers are little endian
+     */
+    fn to_bytes(&self, program : &[Instruction]) -> Vec<u8> {
+        let mut bytes : Vec<u8> = CHECKPOINT_MAGIC.to_vec();
+        bytes.extend_from_slice(&CHECKPOINT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&Self::program_hash(program).to_le_bytes());
+        bytes.extend_from_slice(&(self.ip as u64).to_le_bytes());
+        for values in [&self.stack, &self.globals] {
+            bytes.extend_from_slice(&(values.len() as u32).to_le_bytes());
+            for val in values {
+                bytes.extend_from_slice(&val.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes : &[u8], program : &[Instruction]) -> Result<Self, String> {
+        if bytes.len() < 12 || &bytes[0..8] != CHECKPOINT_MAGIC {
+            return Err(String::from("not a rorth checkpoint"));
+        }
+        let mut pos = 8;
+        let mut take = |n: usize| -> Result<&[u8], String> {
+            let chunk = bytes.get(pos..pos+n).ok_or("truncated checkpoint")?;
+            pos += n;
+            Ok(chunk)
+        };
+        let version = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        if version != CHECKPOINT_VERSION {
+            return Err(format!("unsupported checkpoint version {}, expected {}", version, CHECKPOINT_VERSION));
+        }
+        if u64::from_le_bytes(take(8)?.try_into().unwrap()) != Self::program_hash(program) {
+            return Err(String::from("checkpoint was saved by a different program"));
+        }
+        let ip = u64::from_le_bytes(take(8)?.try_into().unwrap()) as usize;
+        let mut values : [Vec<i64>; 2] = [Vec::new(), Vec::new()];
+        for vals in values.iter_mut() {
+            for _ in 0..u32::from_le_bytes(take(4)?.try_into().unwrap()) {
+                vals.push(i64::from_le_bytes(take(8)?.try_into().unwrap()));
+            }
+        }
+        let [stack, globals] = values;
+        if ip > program.len() || globals.len() != globals_count(program) {
+            return Err(String::from("checkpoint does not match the program"));
+        }
+        Ok(Checkpoint { ip, stack, globals })
+    }
+
+    fn read(filename : &str, program : &[Instruction]) -> Result<Self, String> {
+        let bytes = std::fs::read(filename)
+            .map_err(|err| format!("Could not read checkpoint {}: {}", filename, err))?;
+        Self::from_bytes(&bytes, program).map_err(|err| format!("{}: {}", filename, err))
+    }
+}
+
+// interprets the program from `state`, stopping after `steps` instructions if
+// given, returns whether the program ran to its end
+fn interpret_resumable<W: Write>(program : &[Instruction], state : &mut Checkpoint, steps : Option<u64>, stdout : &mut W) -> bool {
+    let mut executed = 0;
+    while state.ip < program.len() {
+        if steps == Some(executed) {
+            return false;
+        }
+        state.ip = interpret_single_instruction(program, state.ip, &mut state.stack, &mut state.globals, stdout, &mut io::stderr());
+        executed += 1;
+    }
+    true
+}
+
+// number of `var` globals declared in the program
+fn globals_count(program : &[Instruction]) -> usize {
+    program.iter()
+        .filter(|ins| ins.opcode == Opcode::OP_GLOBAL)
+        .map(|ins| ins.operands[0] as usize + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+// address of the first global, the compiled programs link `.bss` here so that
+// both backends see the same addresses
+const GLOBALS_BASE: i64 = 0x10000000;
+
+fn global_address(index: usize) -> i64 {
+    GLOBALS_BASE + 8 * index as i64
+}
+
+// index of the global at `addr`, if it is the aligned address of one of them
+fn global_index(addr: i64, globals: usize) -> Option<usize> {
+    let offset = addr.checked_sub(GLOBALS_BASE).filter(|offset| *offset >= 0 && offset % 8 == 0)?;
+    Some((offset / 8) as usize).filter(|&index| index < globals)
+}
+
+// interprets the program tracking which globals were written, returns a warning
+// for every instruction that read a global before anything was stored in it
+fn interpret_checking_memory<W: Write>(program : &[Instruction], stdout : &mut W) -> Vec<String> {
+    let mut stack : Vec<i64> = Vec::new();
+    let mut globals : Vec<i64> = vec![0; globals_count(program)];
+    let mut written : Vec<bool> = vec![false; globals.len()];
+    let mut reported : Vec<(usize, usize)> = Vec::new();
+    let mut warnings : Vec<String> = Vec::new();
+    let mut ip = 0;
+    while ip < program.len() {
+        let ins = &program[ip];
+        // invalid addresses are reported by the interpreter itself
+        if let Some(addr) = stack.last().and_then(|&addr| global_index(addr, globals.len())) {
+            if ins.opcode == Opcode::OP_STORE {
+                written[addr] = true;
+            } else if ins.opcode == Opcode::OP_LOAD && !written[addr] && !reported.contains(&(ip, addr)) {
+                reported.push((ip, addr));
+                warnings.push(format!("[WARNING] {}:{}:{}: @ip {}: Read of global {} before it was written",
+                    ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip, addr));
+            }
+        }
+        ip = interpret_single_instruction(program, ip, &mut stack, &mut globals, stdout, &mut io::stderr());
+    }
+    warnings
+}
+
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct RunStats {
+    pub peak_stack_depth: usize,
+    pub instructions_executed: u64,
+}
+
+impl RunStats {
+    fn to_summary(&self) -> String {
+        format!("[INFO] Resource usage:\n    peak data stack depth: {}\n    instructions executed: {}\n",
+            self.peak_stack_depth, self.instructions_executed)
+    }
+}
+
+fn uses_strings(opcode: &Opcode) -> bool {
+    matches!(opcode, Opcode::OP_STRING | Opcode::OP_CONCAT | Opcode::OP_SLICE | Opcode::OP_STRLEN | Opcode::OP_PUTS)
+}
+
+// handles of strings are numbers above the addresses of globals
+const STRINGS_BASE: i64 = 0x20000000;
+// strings allocated before the first collection
+const GC_THRESHOLD: usize = 64;
+
+// the strings of `interpret --strings`. A string is a handle, STRINGS_BASE
+// plus its slot, that goes on the stack and in globals like any number, so the
+// collector is conservative: a slot is kept while any value on the stack or in
+// a global equals its handle
+#[derive(Debug)]
+struct StringHeap {
+    slots: Vec<Option<String>>,
+    free: Vec<usize>,
+    live: usize,
+    next_collection: usize,
+}
+
+impl Default for StringHeap {
+    fn default() -> Self {
+        StringHeap { slots: Vec::new(), free: Vec::new(), live: 0, next_collection: GC_THRESHOLD }
+    }
+}
+
+impl StringHeap {
+    fn alloc(&mut self, text: String) -> i64 {
+        let slot = match self.free.pop() {
+            Some(slot) => { self.slots[slot] = Some(text); slot },
+            None => { self.slots.push(Some(text)); self.slots.len() - 1 },
+        };
+        self.live += 1;
+        STRINGS_BASE + slot as i64
+    }
+
+    fn get(&self, handle: i64) -> Option<&str> {
+        let slot = usize::try_from(handle.checked_sub(STRINGS_BASE)?).ok()?;
+        self.slots.get(slot)?.as_deref()
+    }
+
+    // frees the strings no root refers to, once enough were allocated since
+    // the last collection
+    fn collect(&mut self, roots: &[&[i64]]) {
+        if self.live < self.next_collection {
+            return;
+        }
+        let mut marked = vec![false; self.slots.len()];
+        for &value in roots.iter().flat_map(|values| values.iter()) {
+            if self.get(value).is_some() {
+                marked[(value - STRINGS_BASE) as usize] = true;
+            }
+        }
+        for (slot, marked) in marked.into_iter().enumerate() {
+            if !marked && self.slots[slot].take().is_some() {
+                self.free.push(slot);
+                self.live -= 1;
+            }
+        }
+        self.next_collection = GC_THRESHOLD.max(2 * self.live);
+    }
+}
+
+// interprets a program using strings, the other instructions run as in `interpret`
+fn interpret_strings<W: Write>(program : &[Instruction], stdout : &mut W) -> StringHeap {
+    let mut stack : Vec<i64> = Vec::new();
+    let mut globals : Vec<i64> = vec![0; globals_count(program)];
+    let mut heap = StringHeap::default();
+    let mut ip = 0;
+    while ip < program.len() {
+        let ins = &program[ip];
+        if !uses_strings(&ins.opcode) {
+            ip = interpret_single_instruction(program, ip, &mut stack, &mut globals, stdout, &mut io::stderr());
+            continue;
+        }
+        heap.collect(&[&stack, &globals]);
+        let inputs = stack_inputs(&ins.opcode);
+        if stack.len() < inputs {
+            eprintln!("[ERROR] {}:{}:{}: @ip {}: Tried to pop {} values but stack has {}",
+                ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip, inputs, stack.len());
+            process::exit(1);
+        }
+        let args = stack.split_off(stack.len() - inputs);
+        // the string in the first argument
+        let text = || heap.get(args[0]).map(String::from).unwrap_or_else(|| {
+            eprintln!("[ERROR] {}:{}:{}: @ip {}: Expected a string but found {}",
+                ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip, args[0]);
+            process::exit(1);
+        });
+        match ins.opcode {
+            Opcode::OP_STRING => {
+                let bytes : Vec<u8> = ins.operands[1..].iter().flat_map(|operand| operand.to_le_bytes()).collect();
+                let text = String::from_utf8_lossy(&bytes[..ins.operands[0] as usize]).into_owned();
+                stack.push(heap.alloc(text));
+            },
+            Opcode::OP_CONCAT => {
+                let mut text = text();
+                match heap.get(args[1]) {
+                    Some(tail) => text.push_str(tail),
+                    None => text.push_str(&args[1].to_string()),
+                }
+                stack.push(heap.alloc(text));
+            },
+            Opcode::OP_SLICE => {
+                let (start, len) = (args[1], args[2]);
+                let chars : Vec<char> = text().chars().collect();
+                let end = start.checked_add(len).filter(|&end| start >= 0 && len >= 0 && end as usize <= chars.len());
+                let Some(end) = end else {
+                    eprintln!("[ERROR] {}:{}:{}: @ip {}: Slice of {} characters at {} is outside a string of {}",
+                        ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip, len, start, chars.len());
+                    process::exit(1);
+                };
+                stack.push(heap.alloc(chars[start as usize..end as usize].iter().collect()));
+            },
+            Opcode::OP_STRLEN => stack.push(text().chars().count() as i64),
+            _ => write!(stdout, "{}", text()).unwrap(),
+        }
+        ip += 1;
+    }
+    heap
+}
+
+// what a program run by `run_program` printed and how it ended
+#[derive(Debug, Default)]
+pub struct RunResult {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub exit_code: i32,
+    pub stats: RunStats,
+}
+
+// exit code of a program stopped by one of its `RunLimits`, as `timeout` does
+const LIMIT_EXIT_CODE : i32 = 124;
+
+// how far `run_program_limited` lets a program go, `None` for no limit
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RunLimits {
+    pub max_steps: Option<u64>,
+    pub max_output: Option<usize>,
+    pub max_stack_depth: Option<usize>,
+}
+
+// lexes, parses and interprets `source` capturing its output, for tests and
+// tools embedding rorth. Errors end up in `stderr` with exit code 1 instead of
+// exiting the process, and no word reads `stdin` yet
+pub fn run_program(source: &str, stdin: &[u8]) -> RunResult {
+    run_program_limited(source, stdin, RunLimits::default())
+}
+
+pub fn run_program_limited(source: &str, stdin: &[u8], limits: RunLimits) -> RunResult {
+    run_program_with(source, stdin, &LexOptions::default(), limits)
+}
+
+// like `run_program_limited`, lexing `source` with custom comments and recognizers
+pub fn run_program_with(source: &str, stdin: &[u8], lex_options: &LexOptions, limits: RunLimits) -> RunResult {
+    match parse_source_with(source, lex_options) {
+        Ok(program) => run_instructions(&program, stdin, limits),
+        Err(err) => parse_failure(&err),
+    }
+}
+
+// like `run_program_limited`, starting from the state saved in `checkpoint`
+// if given, also returns the state of a program stopped by `max_steps` so
+// that it can be resumed later, in the format of `--checkpoint`
+pub fn run_program_resumable(source: &str, stdin: &[u8], checkpoint: Option<&[u8]>, limits: RunLimits) -> (RunResult, Option<Vec<u8>>) {
+    let program = match parse_source(source) {
+        Ok(program) => program,
+        Err(err) => return (parse_failure(&err), None),
+    };
+    let mut state = match checkpoint.map(|bytes| Checkpoint::from_bytes(bytes, &program)) {
+        Some(Ok(state)) => state,
+        Some(Err(err)) => return (parse_failure(&format!("[ERROR] Could not resume: {}", err)), None),
+        None => Checkpoint::new(&program),
+    };
+    let result = run_from(&program, &mut state, stdin, limits);
+    let stopped = result.exit_code == LIMIT_EXIT_CODE && state.ip < program.len()
+        && limits.max_steps == Some(result.stats.instructions_executed);
+    let checkpoint = stopped.then(|| state.to_bytes(&program));
+    (result, checkpoint)
+}
+
+fn parse_source(source: &str) -> Result<Vec<Instruction>, String> {
+    parse_source_with(source, &LexOptions::default())
+}
+
+fn parse_source_with(source: &str, lex_options: &LexOptions) -> Result<Vec<Instruction>, String> {
+    let (tokens, _) = expand_structs_with_constants(lex_source_with(source, "<source>", lex_options), &[])?;
+    parse_program(&tokens)
+}
+
+// the result of a program that didn't parse
+fn parse_failure(err: &str) -> RunResult {
+    RunResult { stderr: format!("{}\n", err).into_bytes(), exit_code: 1, ..RunResult::default() }
+}
+
+fn run_instructions(program: &[Instruction], stdin: &[u8], limits: RunLimits) -> RunResult {
+    run_from(program, &mut Checkpoint::new(program), stdin, limits)
+}
+
+// runs the program from `state`, leaving in it where the program stopped
+fn run_from(program: &[Instruction], state: &mut Checkpoint, _stdin: &[u8], limits: RunLimits) -> RunResult {
+    let mut result = RunResult::default();
+    let Checkpoint { ip, stack, globals } = state;
+    while *ip < program.len() {
+        if limits.max_steps.is_some_and(|max| result.stats.instructions_executed >= max) {
+            writeln!(result.stderr, "[ERROR] Stopped after {} instructions", result.stats.instructions_executed).unwrap();
+            result.exit_code = LIMIT_EXIT_CODE;
+            break;
+        }
+        match try_interpret_single_instruction(program, *ip, stack, globals, &mut result.stdout, &mut result.stderr) {
+            Ok(next) => *ip = next,
+            Err(err) => {
+                writeln!(result.stderr, "{}", err).unwrap();
+                result.exit_code = 1;
+                break;
+            }
+        }
+        result.stats.instructions_executed += 1;
+        result.stats.peak_stack_depth = result.stats.peak_stack_depth.max(stack.len());
+        if let Some(max) = limits.max_stack_depth.filter(|&max| stack.len() > max) {
+            writeln!(result.stderr, "[ERROR] Stopped with more than {} values on the stack", max).unwrap();
+            result.exit_code = LIMIT_EXIT_CODE;
+            break;
+        }
+        if let Some(max) = limits.max_output.filter(|&max| result.stdout.len() > max) {
+            result.stdout.truncate(max);
+            writeln!(result.stderr, "[ERROR] Stopped after printing {} bytes", max).unwrap();
+            result.exit_code = LIMIT_EXIT_CODE;
+            break;
+        }
+    }
+    result
+}
+
+fn interpret_with_stats<W: Write>(program : &[Instruction], stdout : &mut W) -> RunStats {
+    let mut stack : Vec<i64> = Vec::new();
+    let mut globals : Vec<i64> = vec![0; globals_count(program)];
+    let mut stats = RunStats::default();
+    let mut ip = 0;
+    while ip < program.len() {
+        ip = interpret_single_instruction(program, ip, &mut stack, &mut globals, stdout, &mut io::stderr());
+        stats.instructions_executed += 1;
+        stats.peak_stack_depth = stats.peak_stack_depth.max(stack.len());
+    }
+    stats
+}
+
+// "1", "1 and 2", "1, 2 and 3"
+fn list_values(values: &[i64]) -> String {
+    let values : Vec<String> = values.iter().map(|value| value.to_string()).collect();
+    match values.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} and {}", rest.join(", "), last),
+        _ => values.join(""),
+    }
+}
+
+// what an instruction did, from the stack before and after it and where the
+// program continues, e.g. "pop 3 and 4, push 7"
+fn explain_step(ins: &Instruction, before: &[i64], after: &[i64], next_ip: usize) -> String {
+    let kept = before.len() - stack_inputs(&ins.opcode).min(before.len());
+    let mut steps : Vec<String> = Vec::new();
+    if kept < before.len() {
+        steps.push(format!("pop {}", list_values(&before[kept..])));
+    }
+    if kept < after.len() {
+        steps.push(format!("push {}", list_values(&after[kept..])));
+    }
+    match flow(ins) {
+        Flow::JumpIfZero(_) if next_ip != ins.ip + 1 => steps.push(format!("it is false, jump to {}", next_ip)),
+        Flow::JumpIfZero(_) => steps.push("it is true, go on".to_string()),
+        Flow::Jump(_) => steps.push(format!("jump to {}", next_ip)),
+        Flow::Next => {},
+    }
+    if steps.is_empty() {
+        return "nothing changes".to_string();
+    }
+    steps.join(", ")
+}
+
+// interprets the program counting how many times every instruction runs
+fn interpret_profiling<W: Write>(program : &[Instruction], stdout : &mut W) -> Vec<u64> {
+    let mut stack : Vec<i64> = Vec::new();
+    let mut globals : Vec<i64> = vec![0; globals_count(program)];
+    let mut counts : Vec<u64> = vec![0; program.len()];
+    let mut ip = 0;
+    while ip < program.len() {
+        counts[ip] += 1;
+        ip = interpret_single_instruction(program, ip, &mut stack, &mut globals, stdout, &mut io::stderr());
+    }
+    counts
+}
+
+// interprets the program explaining every step to `explain`, together with
+// the stack after it
+fn interpret_explaining<W: Write, E: Write>(program : &[Instruction], stdout : &mut W, explain : &mut E) {
+    let mut stack : Vec<i64> = Vec::new();
+    let mut globals : Vec<i64> = vec![0; globals_count(program)];
+    let mut ip = 0;
+    while ip < program.len() {
+        let before = stack.clone();
+        let next_ip = interpret_single_instruction(program, ip, &mut stack, &mut globals, stdout, &mut io::stderr());
+        let name = format!("{:?}", program[ip].opcode).trim_start_matches("OP_").to_lowercase();
+        let step = explain_step(&program[ip], &before, &stack, next_ip);
+        let values : Vec<String> = stack.iter().map(|value| value.to_string()).collect();
+        writeln!(explain, "{:>4}  {:<8}  {:<32}  [{}]", ip, name, step, values.join(" ")).unwrap();
+        ip = next_ip;
+    }
+}
+
+const DEFAULT_MAX_ERRORS: usize = 20;
+
+// the first `max_errors` errors, followed by a summary of how many there were
+fn report_errors(errors: &[String], max_errors: usize) -> Vec<String> {
+    let mut report: Vec<String> = errors.iter().take(max_errors).cloned().collect();
+    match errors.len() {
+        0 => {},
+        1 => report.push(String::from("[ERROR] aborting due to 1 previous error")),
+        n => report.push(format!("[ERROR] aborting due to {} previous errors", n)),
+    }
+    report
+}
+
+// interprets the program remembering which instruction pushed every value on
+// the stack, returns an error for each value left when the program ends
+fn interpret_strict<W: Write>(program : &[Instruction], stdout : &mut W) -> Vec<String> {
+    let mut stack : Vec<i64> = Vec::new();
+    let mut origins : Vec<usize> = Vec::new();
+    let mut globals : Vec<i64> = vec![0; globals_count(program)];
+    let mut ip = 0;
+    while ip < program.len() {
+        let opcode = &program[ip].opcode;
+        let top = stack.last().copied();
+        let next_ip = interpret_single_instruction(program, ip, &mut stack, &mut globals, stdout, &mut io::stderr());
+        // `dup`, `2dup` and `2over` leave their inputs where they were and push
+        // copies, `swap`, `rot` and `2swap` only reorder their inputs
+        let inputs = match opcode {
+            Opcode::OP_DUP | Opcode::OP_SWAP | Opcode::OP_ROT | Opcode::OP_2DUP | Opcode::OP_2SWAP | Opcode::OP_2OVER => 0,
+            _ => stack_inputs(opcode),
+        };
+        let len = origins.len();
+        match opcode {
+            Opcode::OP_SWAP | Opcode::OP_ROT => origins[len - stack_inputs(opcode)..].rotate_left(1),
+            Opcode::OP_2SWAP => origins[len - 4..].rotate_left(2),
+            // the count is dropped below, the value moves to the top
+            Opcode::OP_ROLL => origins[len - 2 - top.unwrap() as usize..len - 1].rotate_left(1),
+            _ => {},
+        }
+        origins.truncate((origins.len() - inputs).min(stack.len()));
+        origins.resize(stack.len(), ip);
+        ip = next_ip;
+    }
+    stack.iter().zip(origins).map(|(value, origin)| {
+        let loc = &program[origin].loc;
+        format!("[ERROR] {}:{}:{}: @ip {}: Value {} left on the stack", loc.file, loc.row+1, loc.col+1, origin, value)
+    }).collect()
+}
+
+// the instruction every `end` of a while continues at: the first one of the
+// condition, skipping the dispatch of both the `end` and the `while`
+fn fuse_loops(program : &[Instruction]) -> Vec<Option<usize>> {
+    program.iter().map(|ins| match flow(ins) {
+        Flow::Jump(while_ip) if ins.opcode == Opcode::OP_END => Some(while_ip + 1),
+        _ => None,
+    }).collect()
+}
+
+fn interpret_fused<W: Write>(program : &[Instruction], stdout : &mut W) {
+    let mut stack : Vec<i64> = Vec::new();
+    let mut globals : Vec<i64> = vec![0; globals_count(program)];
+    let loops = fuse_loops(program);
+    let mut ip = 0;
+    while ip < program.len() {
+        if let Some(condition) = loops[ip] {
+            ip = condition;
+            continue;
+        }
+        ip = interpret_single_instruction(program, ip, &mut stack, &mut globals, stdout, &mut io::stderr());
+    }
+}
+
+fn interpret<W: Write>(program : &[Instruction], stdout : &mut W) {
+    interpret_with_stderr(program, stdout, &mut io::stderr());
+}
+
+fn interpret_with_stderr<W: Write, E: Write>(program : &[Instruction], stdout : &mut W, stderr : &mut E) {
+    let mut stack : Vec<i64> = Vec::new();
+    let mut globals : Vec<i64> = vec![0; globals_count(program)];
+    let runs = pure_runs(program);
+    let mut ip = 0;
+    while ip < program.len() {
+        // a stack too shallow for the run fails in the slow path, which
+        // reports the instruction that underflows
+        if let Some(run) = runs[ip].filter(|run| stack.len() >= run.needs) {
+            run_pure(&program[ip..run.end], &mut stack);
+            ip = run.end;
+            continue;
+        }
+        ip = interpret_single_instruction(program, ip, &mut stack, &mut globals, stdout, stderr);
+    }
+}
+
+// instructions the fast path of `interpret` runs together: they only compute
+// on the stack, can't fail once it is deep enough and never jump
+fn is_pure(opcode: &Opcode) -> bool {
+    matches!(opcode, Opcode::OP_PUSH | Opcode::OP_ADD | Opcode::OP_SUB | Opcode::OP_MUL | Opcode::OP_NOT
+        | Opcode::OP_EQ | Opcode::OP_NE | Opcode::OP_GT | Opcode::OP_GE | Opcode::OP_LT | Opcode::OP_LE
+        | Opcode::OP_SHL | Opcode::OP_SHR | Opcode::OP_BOR | Opcode::OP_BAND | Opcode::OP_BXOR
+        | Opcode::OP_ROTL | Opcode::OP_ROTR | Opcode::OP_NEG | Opcode::OP_ABS | Opcode::OP_INC
+        | Opcode::OP_DEC | Opcode::OP_AND | Opcode::OP_OR | Opcode::OP_BNOT | Opcode::OP_DUP
+        | Opcode::OP_SWAP | Opcode::OP_ROT | Opcode::OP_2DUP | Opcode::OP_2DROP)
+}
+
+// pure instructions from an ip up to `end`, decoded before the program runs
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+struct PureRun {
+    end: usize,
+    // the stack depth the whole run needs, checked once for all of it
+    needs: usize,
+}
+
+// the run of pure instructions starting at every ip, if it has at least two
+fn pure_runs(program: &[Instruction]) -> Vec<Option<PureRun>> {
+    let mut runs = vec![None; program.len()];
+    let mut end = program.len();
+    let mut needs = 0;
+    for (ip, ins) in program.iter().enumerate().rev() {
+        if !is_pure(&ins.opcode) {
+            end = ip;
+            needs = 0;
+            continue;
+        }
+        let (inputs, outputs) = (stack_inputs(&ins.opcode), stack_outputs(&ins.opcode));
+        needs = inputs.max((needs + inputs).saturating_sub(outputs));
+        if end - ip >= 2 {
+            runs[ip] = Some(PureRun { end, needs });
+        }
+    }
+    runs
+}
+
+// runs pure instructions, the stack has the depth their `PureRun` needs
+fn run_pure(run: &[Instruction], stack: &mut Vec<i64>) {
+    for ins in run {
+        let len = stack.len();
+        match ins.opcode {
+            Opcode::OP_PUSH => stack.push(ins.operands[0]),
+            Opcode::OP_DUP => stack.push(stack[len - 1]),
+            Opcode::OP_SWAP => stack.swap(len - 1, len - 2),
+            Opcode::OP_ROT => stack[len - 3..].rotate_left(1),
+            Opcode::OP_2DUP => stack.extend_from_within(len - 2..),
+            Opcode::OP_2DROP => stack.truncate(len - 2),
+            Opcode::OP_NOT | Opcode::OP_BNOT | Opcode::OP_NEG | Opcode::OP_ABS | Opcode::OP_INC | Opcode::OP_DEC => {
+                let a = &mut stack[len - 1];
+                *a = match ins.opcode {
+                    Opcode::OP_NOT => (*a == 0) as i64,
+                    Opcode::OP_BNOT => !*a,
+                    Opcode::OP_NEG => a.wrapping_neg(),
+                    Opcode::OP_ABS => a.wrapping_abs(),
+                    Opcode::OP_INC => a.wrapping_add(1),
+                    _ => a.wrapping_sub(1),
+                };
+            },
+            // as in `interpret_single_instruction`, `a` is the top and `b` below it
+            _ => {
+                let a = stack.pop().unwrap();
+                let b = &mut stack[len - 2];
+                *b = match ins.opcode {
+                    Opcode::OP_ADD => b.wrapping_add(a),
+                    Opcode::OP_SUB => b.wrapping_sub(a),
+                    Opcode::OP_MUL => b.wrapping_mul(a),
+                    Opcode::OP_EQ => (*b == a) as i64,
+                    Opcode::OP_NE => (*b != a) as i64,
+                    Opcode::OP_GT => (*b > a) as i64,
+                    Opcode::OP_GE => (*b >= a) as i64,
+                    Opcode::OP_LT => (*b < a) as i64,
+                    Opcode::OP_LE => (*b <= a) as i64,
+                    Opcode::OP_SHL => b.wrapping_shl(a as u32),
+                    Opcode::OP_SHR => (*b as u64).wrapping_shr(a as u32) as i64,
+                    Opcode::OP_BOR => *b | a,
+                    Opcode::OP_BAND => *b & a,
+                    Opcode::OP_BXOR => *b ^ a,
+                    Opcode::OP_ROTL => b.rotate_left((a & 63) as u32),
+                    Opcode::OP_ROTR => b.rotate_right((a & 63) as u32),
+                    Opcode::OP_AND => (*b != 0 && a != 0) as i64,
+                    _ => (*b != 0 || a != 0) as i64,
+                };
+            },
+        }
+    }
+}
+
+// interprets the program with 32-bit words: values wrap at 32 bits, shifts only
+// use the low 5 bits of the amount and globals are 4 bytes apart
+fn interpret_32<W: Write>(program : &[Instruction], stdout : &mut W) {
+    let mut stack : Vec<i64> = Vec::new();
+    let mut globals : Vec<i64> = vec![0; globals_count(program)];
+    let mut ip = 0;
+    while ip < program.len() {
+        ip = interpret_single_instruction_32(program, ip, &mut stack, &mut globals, stdout, &mut io::stderr());
+    }
+}
+
+// values are kept sign extended to 64 bits, the instructions that depend on
+// the word size are adapted before running them as 64-bit ones
+fn interpret_single_instruction_32<W: Write, E: Write>(program : &[Instruction], ip : usize, stack : &mut Vec<i64>, globals : &mut [i64], stdout : &mut W, stderr : &mut E) -> usize {
+    try_interpret_single_instruction_32(program, ip, stack, globals, stdout, stderr).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        _dump_bytecode(program);
+        _dump_stack(stack);
+        process::exit(1);
+    })
+}
+
+fn try_interpret_single_instruction_32<W: Write, E: Write>(program : &[Instruction], ip : usize, stack : &mut Vec<i64>, globals : &mut [i64], stdout : &mut W, stderr : &mut E) -> Result<usize, String> {
+    let ins = &program[ip];
+    let inputs = stack_inputs(&ins.opcode);
+    if stack.len() < inputs {
+        return Err(format!("[ERROR] {}:{}:{}: @ip {}: Tried to pop {} values but stack has {}",
+            ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip, inputs, stack.len()));
+    }
+    match ins.opcode {
+        Opcode::OP_GLOBAL => {
+            stack.push(GLOBALS_BASE + 4 * ins.operands[0]);
+            return Ok(ip + 1);
+        },
+        Opcode::OP_LOAD | Opcode::OP_STORE => {
+            // move the address to the 64-bit layout
+            let addr = stack.last_mut().unwrap();
+            match addr.checked_sub(GLOBALS_BASE).filter(|offset| *offset >= 0 && offset % 4 == 0) {
+                Some(offset) => *addr = GLOBALS_BASE + 2 * offset,
+                None => {
+                    return Err(format!("[ERROR] {}:{}:{}: @ip {}: Invalid address {}", ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip, addr));
+                },
+            }
+        },
+        Opcode::OP_SHL | Opcode::OP_SHR => {
+            let len = stack.len();
+            stack[len-1] &= 31;
+            if ins.opcode == Opcode::OP_SHR {
+                stack[len-2] = stack[len-2] as u32 as i64;
+            }
+        },
+        Opcode::OP_ROTL | Opcode::OP_ROTR => {
+            let a = (stack.pop().unwrap() & 31) as u32;
+            let b = stack.pop().unwrap() as u32;
+            stack.push(if ins.opcode == Opcode::OP_ROTL { b.rotate_left(a) } else { b.rotate_right(a) } as i32 as i64);
+            return Ok(ip + 1);
+        },
+        Opcode::OP_POPCOUNT | Opcode::OP_CLZ | Opcode::OP_CTZ => {
+            let a = stack.pop().unwrap() as u32;
+            stack.push(match ins.opcode {
+                Opcode::OP_POPCOUNT => a.count_ones(),
+                Opcode::OP_CLZ => a.leading_zeros(),
+                _ => a.trailing_zeros(),
+            } as i64);
+            return Ok(ip + 1);
+        },
+        Opcode::OP_BSWAP64 => {
+            return Err(format!("[ERROR] {}:{}:{}: @ip {}: `bswap64` needs 64-bit words", ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip));
+        },
+        _ => {},
+    }
+    let next_ip = try_interpret_single_instruction(program, ip, stack, globals, stdout, stderr)?;
+    // only the values on top can have been computed by the instruction
+    let len = stack.len();
+    for value in &mut stack[len.saturating_sub(2)..] {
+        *value = *value as i32 as i64;
+    }
+    Ok(next_ip)
+}
+
+fn interpret_single_instruction<W: Write, E: Write>(program : &[Instruction], ip : usize, stack : &mut Vec<i64>, globals : &mut [i64], stdout : &mut W, stderr : &mut E) -> usize {
+    try_interpret_single_instruction(program, ip, stack, globals, stdout, stderr).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        _dump_bytecode(program);
+        _dump_stack(stack);
+        process::exit(1);
+    })
+}
+
+// runs the instruction at `ip`, returning the ip of the next one or the error
+// that stops the program
+fn try_interpret_single_instruction<W: Write, E: Write>(program : &[Instruction], ip : usize, stack : &mut Vec<i64>, globals : &mut [i64], stdout : &mut W, stderr : &mut E) -> Result<usize, String> {
+    let ins = &program[ip];
+    let inputs = stack_inputs(&ins.opcode);
+    if stack.len() < inputs {
+        return Err(format!("[ERROR] {}:{}:{}: @ip {}: Tried to pop {} values but stack has {}",
+            ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip, inputs, stack.len()));
+    }
+    match ins.opcode {
+        Opcode::OP_PUSH => {
+            stack.push(ins.operands[0]);
+        },
+        // arithmetic wraps around, like in compiled programs
+        Opcode::OP_ADD => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            stack.push(b.wrapping_add(a));
+        },
+        Opcode::OP_SUB => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            stack.push(b.wrapping_sub(a));
+        },
+        Opcode::OP_MUL => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            stack.push(b.wrapping_mul(a));
+        },
+        // division truncates towards zero, the remainder has the sign of the dividend,
+        // i64::MIN / -1 wraps around to i64::MIN and division by zero is an error
+        Opcode::OP_DIV | Opcode::OP_MOD | Opcode::OP_DIVMOD => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            if a == 0 {
+                return Err(format!("[ERROR] {}:{}:{}: @ip {}: Division by zero", ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip));
+            }
+            if ins.opcode != Opcode::OP_MOD {
+                stack.push(b.wrapping_div(a));
+            }
+            if ins.opcode != Opcode::OP_DIV {
+                stack.push(b.wrapping_rem(a));
+            }
+        },
+        // the exponent is taken as unsigned, the result wraps around like `*`
+        Opcode::OP_POW => {
+            let mut exponent = stack.pop().unwrap() as u64;
+            let mut base = stack.pop().unwrap();
+            let mut result : i64 = 1;
+            while exponent != 0 {
+                if exponent & 1 == 1 {
+                    result = result.wrapping_mul(base);
+                }
+                base = base.wrapping_mul(base);
+                exponent >>= 1;
+            }
+            stack.push(result);
+        },
+        // rounds down
+        Opcode::OP_SQRT => {
+            let a = stack.pop().unwrap();
+            if a < 0 {
+                return Err(format!("[ERROR] {}:{}:{}: @ip {}: Square root of negative number",
+                    ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip));
+            }
+            stack.push((a as u64).isqrt() as i64);
+        },
+        // of the absolute values, gcd(0, 0) is 0
+        Opcode::OP_GCD => {
+            let mut a = stack.pop().unwrap().unsigned_abs();
+            let mut b = stack.pop().unwrap().unsigned_abs();
+            while a != 0 {
+                (a, b) = (b % a, a);
+            }
+            stack.push(b as i64);
+        },
+        // logical not: 0 is false, any other value is true
+        Opcode::OP_NOT => {
+            let a = stack.pop().unwrap();
+            stack.push((a == 0) as i64);
+        },
+        // bitwise complement
+        Opcode::OP_BNOT => {
+            let a = stack.pop().unwrap();
+            stack.push(!a);
+        },
+        Opcode::OP_EQ => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            stack.push(((a==b) as i32) as i64);
+        },
+        Opcode::OP_NE => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            stack.push((a != b) as i64);
+        },
+        Opcode::OP_GT => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            stack.push((b > a) as i64);
+        },
+        Opcode::OP_GE => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            stack.push((b >= a) as i64);
+        },
+        Opcode::OP_LT => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            stack.push((b < a) as i64);
+        },
+        Opcode::OP_LE => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            stack.push((b <= a) as i64);
+        },
+        Opcode::OP_SHL => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            // like x86, only the low 6 bits of the amount are used
+            stack.push(b.wrapping_shl(a as u32));
+        },
+        Opcode::OP_SHR => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            // logical shift, like `shr` in compiled code
+            stack.push((b as u64).wrapping_shr(a as u32) as i64);
+        },
+        Opcode::OP_BOR => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            stack.push(b | a);
+        },
+        Opcode::OP_BAND => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            stack.push(b & a);
+        },
+        Opcode::OP_BXOR => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            stack.push(b ^ a);
+        },
+        // like x86, only the low 6 bits of the amount are used
+        Opcode::OP_ROTL | Opcode::OP_ROTR => {
+            let a = (stack.pop().unwrap() & 63) as u32;
+            let b = stack.pop().unwrap();
+            stack.push(if ins.opcode == Opcode::OP_ROTL { b.rotate_left(a) } else { b.rotate_right(a) });
+        },
+        // the swapped bytes are zero extended
+        Opcode::OP_BSWAP16 => {
+            let a = stack.pop().unwrap();
+            stack.push((a as u16).swap_bytes() as i64);
+        },
+        Opcode::OP_BSWAP32 => {
+            let a = stack.pop().unwrap();
+            stack.push((a as u32).swap_bytes() as i64);
+        },
+        Opcode::OP_BSWAP64 => {
+            let a = stack.pop().unwrap();
+            stack.push(a.swap_bytes());
+        },
+        Opcode::OP_POPCOUNT => {
+            let a = stack.pop().unwrap();
+            stack.push(a.count_ones() as i64);
+        },
+        // both wrap around like `-`, so the most negative value stays as is
+        Opcode::OP_NEG => {
+            let a = stack.pop().unwrap();
+            stack.push(a.wrapping_neg());
+        },
+        Opcode::OP_ABS => {
+            let a = stack.pop().unwrap();
+            stack.push(a.wrapping_abs());
+        },
+        // any value other than 0 is true, the result is 0 or 1
+        Opcode::OP_AND => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            stack.push((b != 0 && a != 0) as i64);
+        },
+        Opcode::OP_OR => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            stack.push((b != 0 || a != 0) as i64);
+        },
+        Opcode::OP_INC => {
+            let a = stack.pop().unwrap();
+            stack.push(a.wrapping_add(1));
+        },
+        Opcode::OP_DEC => {
+            let a = stack.pop().unwrap();
+            stack.push(a.wrapping_sub(1));
+        },
+        Opcode::OP_CLZ => {
+            let a = stack.pop().unwrap();
+            stack.push(a.leading_zeros() as i64);
+        },
+        Opcode::OP_CTZ => {
+            let a = stack.pop().unwrap();
+            stack.push(a.trailing_zeros() as i64);
+        },
+        Opcode::OP_DUP => {
+            let a = stack.pop().unwrap();
+            stack.push(a);
+            stack.push(a);
+        },
+        Opcode::OP_SWAP => {
+            let a = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            stack.push(a);
+            stack.push(b);
+        },
+        Opcode::OP_ROT => {
+            let c = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            let a = stack.pop().unwrap();
+            stack.push(b);
+            stack.push(c);
+            stack.push(a);
+        },
+        Opcode::OP_2DUP => {
+            let b = stack.pop().unwrap();
+            let a = stack.pop().unwrap();
+            stack.extend([a, b, a, b]);
+        },
+        Opcode::OP_2DROP => {
+            stack.pop().unwrap();
+            stack.pop().unwrap();
+        },
+        Opcode::OP_2SWAP => {
+            let d = stack.pop().unwrap();
+            let c = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            let a = stack.pop().unwrap();
+            stack.extend([c, d, a, b]);
+        },
+        Opcode::OP_2OVER => {
+            let d = stack.pop().unwrap();
+            let c = stack.pop().unwrap();
+            let b = stack.pop().unwrap();
+            let a = stack.pop().unwrap();
+            stack.extend([a, b, c, d, a, b]);
+        },
+        // the value `n` deep, not counting `n`, 0 is the top
+        Opcode::OP_PICK | Opcode::OP_ROLL => {
+            let n = stack.pop().unwrap();
+            if n < 0 || n as usize >= stack.len() {
+                return Err(format!("[ERROR] {}:{}:{}: @ip {}: Tried to reach value {} but stack has {}",
+                    ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip, n, stack.len()));
+            }
+            let index = stack.len() - 1 - n as usize;
+            let value = if ins.opcode == Opcode::OP_PICK { stack[index] } else { stack.remove(index) };
+            stack.push(value);
+        },
+        Opcode::OP_DEPTH => {
+            stack.push(stack.len() as i64);
+        },
+        Opcode::OP_STRING | Opcode::OP_CONCAT | Opcode::OP_SLICE | Opcode::OP_STRLEN | Opcode::OP_PUTS => {
+            return Err(format!("[ERROR] {}:{}:{}: @ip {}: Strings are only supported by `interpret --strings`",
+                ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip));
+        },
+        Opcode::OP_CALL => {
+            return Err(format!("[ERROR] {}:{}:{}: @ip {}: Extern proc `{}` can only be called from compiled programs",
+                ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip, unpack_name(&ins.operands[2..])));
+        },
+        Opcode::OP_GLOBAL => {
+            stack.push(global_address(ins.operands[0] as usize));
+        },
+        Opcode::OP_LOAD | Opcode::OP_STORE => {
+            let addr = stack.pop().unwrap();
+            let Some(index) = global_index(addr, globals.len()) else {
+                return Err(format!("[ERROR] {}:{}:{}: @ip {}: Invalid address {}", ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip, addr));
+            };
+            if ins.opcode == Opcode::OP_LOAD {
+                stack.push(globals[index]);
+            } else {
+                globals[index] = stack.pop().unwrap();
+            }
+        },
+        Opcode::OP_DUMPQ => {
+            let n = stack.pop().unwrap();
+            if n < 0 || n as usize > stack.len() {
+                return Err(format!("[ERROR] {}:{}:{}: @ip {}: Tried to print {} values but stack has {}",
+                    ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip, n, stack.len()));
+            }
+            let values : Vec<String> = stack.drain(stack.len() - n as usize..)
+                .map(|val| val.to_string())
+                .collect();
+            writeln!(stdout, "{}", values.join(" ")).unwrap();
+        },
+        Opcode::OP_DUMP | Opcode::OP_EPRINT => {
+            if let Some(a) = stack.pop() {
+                if ins.opcode == Opcode::OP_DUMP {
+                    writeln!(stdout, "{}", format_radix(a, ins.operands.first().copied().unwrap_or(10))).unwrap();
+                } else {
+                    writeln!(stderr, "{}", a).unwrap();
+                }
+            } else {
+                return Err(format!("[ERROR] @ip {}: Tried to pop but stack was empty", ip));
+            }
+        }
+        Opcode::OP_IF | Opcode::OP_ELSE | Opcode::OP_END | Opcode::OP_WHILE | Opcode::OP_DO => {
+            match flow(ins) {
+                Flow::Next => { },
+                Flow::Jump(target) => return Ok(target),
+                Flow::JumpIfZero(target) => {
+                    if stack.pop().unwrap() == 0 {
+                        return Ok(target);
+                    }
+                },
+            }
+        },
+    }
+    Ok(ip + 1)
+}
+
+// where execution goes after an instruction, the same for every backend
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Flow {
+    Next,
+    Jump(usize),
+    // pops the condition
+    JumpIfZero(usize),
+}
+
+fn flow(ins: &Instruction) -> Flow {
+    match ins.opcode {
+        // if and do point to their else or end, else to its end,
+        // control goes to the instruction after them
+        Opcode::OP_IF | Opcode::OP_DO => Flow::JumpIfZero(ins.operands[0] as usize + 1),
+        Opcode::OP_ELSE => Flow::Jump(ins.operands[0] as usize + 1),
+        // the end of a while points back to it, the end of an if falls through
+        Opcode::OP_END => ins.operands.first().map_or(Flow::Next, |&while_ip| Flow::Jump(while_ip as usize)),
+        _ => Flow::Next,
+    }
+}
+
+// a run of instructions only entered at `start` and left after `end`,
+// `successors` are the starts of the blocks it continues to, or the length
+// of the program when it exits
+#[derive(PartialEq, Eq, Debug)]
+struct BasicBlock {
+    start: usize,
+    end: usize,
+    successors: Vec<usize>,
+}
+
+fn basic_blocks(program: &[Instruction]) -> Vec<BasicBlock> {
+    let mut leaders : Vec<usize> = vec![0];
+    for ins in program {
+        match flow(ins) {
+            Flow::Next => { },
+            Flow::Jump(target) | Flow::JumpIfZero(target) => leaders.extend([target, ins.ip + 1]),
+        }
+    }
+    leaders.retain(|&leader| leader < program.len());
+    leaders.sort();
+    leaders.dedup();
+    leaders.iter().enumerate().map(|(i, &start)| {
+        let end = leaders.get(i + 1).map_or(program.len(), |&next| next) - 1;
+        let successors = match flow(&program[end]) {
+            Flow::Next => vec![end + 1],
+            Flow::Jump(target) => vec![target],
+            Flow::JumpIfZero(target) if target == end + 1 => vec![end + 1],
+            Flow::JumpIfZero(target) => vec![end + 1, target],
+        };
+        BasicBlock { start, end, successors }
+    }).collect()
+}
+
+// rough cost of an instruction in cycles, once compiled
+fn cycle_cost(opcode: &Opcode) -> u64 {
+    match opcode {
+        Opcode::OP_DIV | Opcode::OP_MOD | Opcode::OP_DIVMOD => 40,
+        Opcode::OP_GCD => 60,
+        Opcode::OP_POW => 30,
+        Opcode::OP_SQRT => 20,
+        // formatting the number and copying it to the output buffer
+        Opcode::OP_DUMP | Opcode::OP_EPRINT | Opcode::OP_DUMPQ => 50,
+        Opcode::OP_CALL => 20,
+        Opcode::OP_ROLL => 5,
+        Opcode::OP_MUL | Opcode::OP_LOAD | Opcode::OP_STORE => 3,
+        _ => 1,
+    }
+}
+
+// how many times `analyze` assumes every loop runs, trip counts aren't known statically
+const ASSUMED_TRIPS: u64 = 10;
+
+// a loop, or the whole program for the region at depth 0
+#[derive(PartialEq, Eq, Debug)]
+struct Region {
+    start: usize,
+    end: usize,
+    depth: u32,
+    // cost of one iteration, inner loops running ASSUMED_TRIPS times
+    iteration_cost: u64,
+    // cost over the whole run, outer loops running ASSUMED_TRIPS times
+    total_cost: u64,
+}
+
+// finds the loops from the back edges of the control flow graph and
+// estimates their cost, most expensive first
+fn analyze_regions(program: &[Instruction]) -> Vec<Region> {
+    let mut loops : Vec<(usize, usize)> = basic_blocks(program).iter()
+        .flat_map(|block| block.successors.iter()
+            .filter(|&&successor| successor <= block.start)
+            .map(|&header| (header, block.end)))
+        .collect();
+    loops.sort();
+    let depth = |ip: usize| loops.iter().filter(|&&(start, end)| start <= ip && ip <= end).count() as u32;
+    let weighted = |start: usize, end: usize| -> u64 {
+        (start..=end).map(|ip| cycle_cost(&program[ip].opcode) * ASSUMED_TRIPS.pow(depth(ip))).sum()
+    };
+    let mut regions : Vec<Region> = Vec::new();
+    if !program.is_empty() {
+        let total_cost = weighted(0, program.len() - 1);
+        regions.push(Region { start: 0, end: program.len() - 1, depth: 0, iteration_cost: total_cost, total_cost });
+    }
+    for &(start, end) in &loops {
+        let depth = depth(start);
+        let total_cost = weighted(start, end);
+        regions.push(Region { start, end, depth, iteration_cost: total_cost / ASSUMED_TRIPS.pow(depth), total_cost });
+    }
+    regions.sort_by_key(|region| std::cmp::Reverse(region.total_cost));
+    regions
+}
+
+fn regions_to_table(program: &[Instruction], regions: &[Region]) -> String {
+    let mut table = format!("{:<7} {:<5} {:>12} {:>12}  location\n", "ips", "depth", "cycles/iter", "cycles");
+    for region in regions {
+        table += &format!("{:<7} {:<5} {:>12} {:>12}  {}\n",
+            format!("{}-{}", region.start, region.end), region.depth, region.iteration_cost, region.total_cost,
+            if region.depth == 0 { String::from("whole program") } else {
+                let loc = &program[region.start].loc;
+                format!("while at {}:{}:{}", loc.file, loc.row+1, loc.col+1)
+            });
+    }
+    table
+}
+
+// programs with more instructions than this get a warning about their memory
+// use, see `--warn-size`
+const DEFAULT_WARN_SIZE: usize = 1_000_000;
+
+// bytes taken by the instructions of `program`, roughly
+fn program_memory(program: &[Instruction]) -> usize {
+    program.iter()
+        .map(|ins| std::mem::size_of::<Instruction>() + ins.operands.capacity() * 8 + ins.loc.file.capacity())
+        .sum()
+}
+
+// "parse: 1200 instructions in 2.500 ms (480000 instructions/s)"
+fn progress_line(phase: &str, duration: std::time::Duration, count: Option<(usize, &str)>) -> String {
+    let ms = duration.as_secs_f64() * 1000.0;
+    match count {
+        Some((count, unit)) => {
+            let rate = count as f64 / duration.as_secs_f64().max(1e-9);
+            format!("{}: {} {} in {:.3} ms ({:.0} {}/s)", phase, count, unit, ms, rate, unit)
+        },
+        None => format!("{}: done in {:.3} ms", phase, ms),
+    }
+}
+
+// wall clock time spent in each phase of the build, in order
+#[derive(Default)]
+struct Timings {
+    phases: Vec<(String, std::time::Duration)>,
+    // report every phase on stderr as it finishes, see `--progress`
+    progress: bool,
+}
+
+impl Timings {
+    fn time<T>(&mut self, phase: &str, f: impl FnOnce() -> T) -> T {
+        self.begin(phase);
+        let start = std::time::Instant::now();
+        let result = f();
+        self.record(phase, start.elapsed());
+        result
+    }
+
+    // with `--progress`, says which phase is running since the slow ones can
+    // take minutes on generated programs
+    fn begin(&self, phase: &str) {
+        if self.progress {
+            eprintln!("[PROGRESS] {}...", phase);
+        }
+    }
+
+    fn record(&mut self, phase: &str, duration: std::time::Duration) {
+        self.record_progress(phase, duration, None);
+    }
+
+    // like `record` for a phase that went through `count` tokens or instructions
+    fn record_count(&mut self, phase: &str, duration: std::time::Duration, count: usize, unit: &str) {
+        self.record_progress(phase, duration, Some((count, unit)));
+    }
+
+    fn record_progress(&mut self, phase: &str, duration: std::time::Duration, count: Option<(usize, &str)>) {
+        if self.progress {
+            eprintln!("[PROGRESS] {}", progress_line(phase, duration, count));
+        }
+        self.phases.push((phase.to_string(), duration));
+    }
+
+    fn to_table(&self) -> String {
+        let width = self.phases.iter().map(|(phase, _)| phase.len()).max().unwrap_or(0).max("total".len());
+        let mut table = format!("[INFO] Timings:\n{:<width$}  {:>10}\n", "phase", "time (ms)", width = width);
+        for (phase, duration) in &self.phases {
+            table += &format!("{:<width$}  {:>10.3}\n", phase, duration.as_secs_f64() * 1000.0, width = width);
+        }
+        let total : std::time::Duration = self.phases.iter().map(|(_, duration)| *duration).sum();
+        table += &format!("{:<width$}  {:>10.3}\n", "total", total.as_secs_f64() * 1000.0, width = width);
+        table
+    }
+}
+
+// progress of the compiler, on stderr so that it never mixes with the output
+// of the program, and only with `-v`
+fn info(verbose: bool, message: &str) {
+    if verbose {
+        eprintln!("[INFO] {}", message);
+    }
+}
+
+// returns the exit code of the program if it was run, 0 otherwise
+fn compile(program : &[Instruction], exec_file: &str, run_prog : bool, prog_args : &[String], options : &CompileOptions, timings : &mut Timings) -> i32 {
+    if let Err(err) = build_executable(program, exec_file, options, timings) {
+        eprintln!("[ERROR] {}", err);
+        process::exit(1);
+    }
+    if run_prog {
+        return execute(exec_file, prog_args, options.run_with.as_deref());
+    }
+    0
+}
+
+// writes the executable `exec_file` with the backend of `options`, or why it couldn't
+fn build_executable(program : &[Instruction], exec_file: &str, options : &CompileOptions, timings : &mut Timings) -> Result<(), String> {
+    if let Some(ins) = program.iter().find(|ins| uses_strings(&ins.opcode)) {
+        return Err(format!("{}:{}:{}: @ip {}: Strings are only supported by `interpret --strings`, compiled programs have no managed memory",
+            ins.loc.file, ins.loc.row+1, ins.loc.col+1, ins.ip));
+    }
+    let path = env::var_os("PATH").unwrap_or_default();
+    let backend = find_backend(options.backend.as_deref(), &path)?;
+    if let Some(err) = backend.missing_tool(options, &path) {
+        return Err(err);
+    }
+    info(options.verbose, &format!("building {} with backend {}", exec_file, backend.name()));
+    backend.build(program, exec_file, options, timings).inspect_err(|_| _dump_bytecode(program))
+}
+
+// turns the program, already parsed and checked, into an executable, out of
+// tree backends are executables named `rorth-backend-<NAME>` in PATH, see
+// `backend_request` for what they are given
+trait Backend {
+    // name selecting it with `--backend`
+    fn name(&self) -> &str;
+    // what is needed to build and not in `path`, if anything
+    fn missing_tool(&self, _options : &CompileOptions, _path : &std::ffi::OsStr) -> Option<String> {
+        None
+    }
+    // writes the executable `exec_file`
+    fn build(&self, program : &[Instruction], exec_file : &str, options : &CompileOptions, timings : &mut Timings) -> Result<(), String>;
+}
+
+const NATIVE_BACKEND: &str = "x86_64-linux";
+const BACKEND_PREFIX: &str = "rorth-backend-";
+// version of the request given to out of tree backends, bumped on any
+// change they could notice
+const BACKEND_ABI_VERSION: u32 = 1;
+
+// x86_64 assembly for nasm or GNU as, linked with ld
+struct NativeBackend;
+
+impl Backend for NativeBackend {
+    fn name(&self) -> &str {
+        NATIVE_BACKEND
+    }
+
+    fn missing_tool(&self, options : &CompileOptions, path : &std::ffi::OsStr) -> Option<String> {
+        missing_tool(options, path)
+    }
+
+    fn build(&self, program : &[Instruction], exec_file : &str, options : &CompileOptions, timings : &mut Timings) -> Result<(), String> {
+        info(options.verbose, &format!("generating {}.asm", exec_file));
+        timings.begin("codegen");
+        let start = std::time::Instant::now();
+        codegen(program, exec_file, options);
+        timings.record_count("codegen", start.elapsed(), program.len(), "instructions");
+        if let Some(source_map) = &options.source_map {
+            std::fs::write(source_map, source_map_to_json(program, &(exec_file.to_string() + ".asm")))
+                .unwrap_or_else(|_| panic!("Could not write file {}", source_map));
+        }
+        if let Some(layout) = &options.layout {
+            std::fs::write(layout, layout_to_string(&plan_bss(program, options)))
+                .unwrap_or_else(|_| panic!("Could not write file {}", layout));
+        }
+        build(exec_file, options, timings)?;
+        if let Some(listing) = &options.listing {
+            emit_listing(program, exec_file, listing);
+        }
+        Ok(())
+    }
+}
+
+// an executable reading the request of `backend_request` on stdin and writing
+// the executable named by its `output`, its stdout and stderr go to stderr
+struct ExternalBackend {
+    name: String,
+    path: std::path::PathBuf,
+}
+
+impl Backend for ExternalBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn build(&self, program : &[Instruction], exec_file : &str, options : &CompileOptions, timings : &mut Timings) -> Result<(), String> {
+        if options.listing.is_some() || options.source_map.is_some() || options.layout.is_some() {
+            return Err(format!("`--emit listing`, `source-map` and `layout` describe native code, backend `{}` can't write them", self.name));
+        }
+        info(options.verbose, &format!("running {}", self.path.display()));
+        let request = backend_request(program, exec_file, options);
+        let status = timings.time(&self.name, || -> io::Result<process::ExitStatus> {
+            let mut child = Command::new(&self.path)
+                .stdin(Stdio::piped())
+                .stdout(io::stderr())
+                .stderr(Stdio::inherit())
+                .spawn()?;
+            // a backend exiting without reading the whole request fails below
+            let _ = child.stdin.take().unwrap().write_all(request.as_bytes());
+            child.wait()
+        }).map_err(|err| format!("Could not run backend {} ({}): {}", self.name, self.path.display(), err))?;
+        if !status.success() {
+            return Err(format!("Backend {} failed with {}", self.name, status));
+        }
+        Ok(())
+    }
+}
+
+// the native backend, or the executable `rorth-backend-<NAME>` in `path`
+fn find_backend(name : Option<&str>, path : &std::ffi::OsStr) -> Result<Box<dyn Backend>, String> {
+    let name = match name {
+        None => return Ok(Box::new(NativeBackend)),
+        Some(name) if name == NATIVE_BACKEND => return Ok(Box::new(NativeBackend)),
+        Some(name) => name,
+    };
+    let exe = format!("{}{}", BACKEND_PREFIX, name);
+    env::split_paths(path).map(|dir| dir.join(&exe)).find(|candidate| candidate.is_file())
+        .map(|path| Box::new(ExternalBackend { name: name.to_string(), path }) as Box<dyn Backend>)
+        .ok_or_else(|| format!("Unknown backend `{}`, expected {} or an executable `{}` in PATH", name, NATIVE_BACKEND, exe))
+}
+
+/**
+ * {"abi": 1, "backend": "6502", "output": "out",
+ *  "options": {"opt": "O0", "runtime_checks": false, "buffer_output": true, "data_stack": null,
+ *              "link": ["extra.o"], "source_hash": "00000000deadbeef", "verbose": false},
+ *  "instructions": [{"ip": 0, "opcode": "OP_PUSH", "operands": [34], "jump": null, "span": {...}}, ...]}
+ * as given on stdin to out of tree backends, instructions are the same as in
+ * the json-ir export, `abi` is BACKEND_ABI_VERSION
+ */
+fn backend_request(program : &[Instruction], exec_file : &str, options : &CompileOptions) -> String {
+    let link : Vec<String> = options.link_objects.iter().map(|obj| format!("\"{}\"", json_escape(obj))).collect();
+    let instructions : Vec<String> = program.iter().map(instruction_to_json).collect();
+    format!("{{\"abi\": {}, \"backend\": \"{}\", \"output\": \"{}\",\n \"options\": {{\"opt\": \"{}\", \"runtime_checks\": {}, \"buffer_output\": {}, \
+        \"data_stack\": {}, \"link\": [{}], \"source_hash\": {}, \"verbose\": {}}},\n \"instructions\": [{}]}}\n",
+        BACKEND_ABI_VERSION, json_escape(options.backend.as_deref().unwrap_or(NATIVE_BACKEND)), json_escape(exec_file),
+        if options.schedule { "O2" } else { "O0" }, options.runtime_checks, options.buffer_output,
+        options.data_stack.map_or(String::from("null"), |size| size.to_string()), link.join(", "),
+        options.source_hash.map_or(String::from("null"), |hash| format!("\"{:016x}\"", hash)), options.verbose,
+        instructions.join(", "))
+}
+
+const OUT_BUF_SIZE: usize = 4096;
+// flush when there may not be room for another number (20 digits, sign and newline)
+const OUT_BUF_FLUSH: usize = OUT_BUF_SIZE - 32;
+
+// number of values an instruction pops, before any it pops dynamically
+fn stack_inputs(opcode: &Opcode) -> usize {
+    match opcode {
+        Opcode::OP_PUSH | Opcode::OP_GLOBAL | Opcode::OP_ELSE | Opcode::OP_END | Opcode::OP_WHILE
+            | Opcode::OP_CALL | Opcode::OP_DEPTH | Opcode::OP_STRING => 0,
+        Opcode::OP_NOT | Opcode::OP_DUP | Opcode::OP_LOAD | Opcode::OP_DUMP | Opcode::OP_EPRINT
+            | Opcode::OP_PICK | Opcode::OP_ROLL | Opcode::OP_STRLEN | Opcode::OP_PUTS
+            | Opcode::OP_DUMPQ | Opcode::OP_IF | Opcode::OP_DO | Opcode::OP_SQRT | Opcode::OP_BSWAP16
+            | Opcode::OP_BSWAP32 | Opcode::OP_BSWAP64 | Opcode::OP_POPCOUNT | Opcode::OP_CLZ
+            | Opcode::OP_CTZ | Opcode::OP_NEG | Opcode::OP_ABS | Opcode::OP_INC | Opcode::OP_DEC | Opcode::OP_BNOT => 1,
+        Opcode::OP_ADD | Opcode::OP_SUB | Opcode::OP_MUL | Opcode::OP_DIV | Opcode::OP_MOD
+            | Opcode::OP_DIVMOD | Opcode::OP_EQ | Opcode::OP_NE | Opcode::OP_GT | Opcode::OP_LT
+            | Opcode::OP_GE | Opcode::OP_LE | Opcode::OP_SHR | Opcode::OP_SHL | Opcode::OP_BOR
+            | Opcode::OP_BAND | Opcode::OP_BXOR | Opcode::OP_ROTL | Opcode::OP_ROTR | Opcode::OP_STORE | Opcode::OP_POW | Opcode::OP_GCD | Opcode::OP_SWAP
+            | Opcode::OP_2DUP | Opcode::OP_2DROP | Opcode::OP_CONCAT | Opcode::OP_AND | Opcode::OP_OR => 2,
+        Opcode::OP_ROT | Opcode::OP_SLICE => 3,
+        Opcode::OP_2SWAP | Opcode::OP_2OVER => 4,
+    }
+}
+
+// number of values an instruction pushes, after popping its inputs
+fn stack_outputs(opcode: &Opcode) -> usize {
+    match opcode {
+        Opcode::OP_ELSE | Opcode::OP_END | Opcode::OP_WHILE | Opcode::OP_CALL | Opcode::OP_DUMP
+            | Opcode::OP_EPRINT | Opcode::OP_PUTS | Opcode::OP_DUMPQ | Opcode::OP_IF | Opcode::OP_DO
+            | Opcode::OP_ROLL | Opcode::OP_STORE | Opcode::OP_2DROP => 0,
+        Opcode::OP_PUSH | Opcode::OP_GLOBAL | Opcode::OP_DEPTH | Opcode::OP_STRING | Opcode::OP_NOT
+            | Opcode::OP_LOAD | Opcode::OP_PICK | Opcode::OP_STRLEN | Opcode::OP_SQRT | Opcode::OP_BSWAP16
+            | Opcode::OP_BSWAP32 | Opcode::OP_BSWAP64 | Opcode::OP_POPCOUNT | Opcode::OP_CLZ
+            | Opcode::OP_CTZ | Opcode::OP_NEG | Opcode::OP_ABS | Opcode::OP_INC | Opcode::OP_DEC
+            | Opcode::OP_BNOT | Opcode::OP_ADD | Opcode::OP_SUB | Opcode::OP_MUL | Opcode::OP_DIV | Opcode::OP_MOD
+            | Opcode::OP_EQ | Opcode::OP_NE | Opcode::OP_GT | Opcode::OP_LT | Opcode::OP_GE | Opcode::OP_LE
+            | Opcode::OP_SHR | Opcode::OP_SHL | Opcode::OP_BOR | Opcode::OP_BAND | Opcode::OP_BXOR
+            | Opcode::OP_ROTL | Opcode::OP_ROTR | Opcode::OP_POW | Opcode::OP_GCD | Opcode::OP_CONCAT
+            | Opcode::OP_AND | Opcode::OP_OR | Opcode::OP_SLICE => 1,
+        Opcode::OP_DUP | Opcode::OP_DIVMOD | Opcode::OP_SWAP => 2,
+        Opcode::OP_ROT => 3,
+        Opcode::OP_2DUP | Opcode::OP_2SWAP => 4,
+        Opcode::OP_2OVER => 6,
+    }
+}
+
+// the deepest the stack gets on any path and the first instruction leaving it
+// that deep, or why there is no static bound: paths reaching an instruction
+// with different depths, like a loop pushing more than it pops
+fn max_stack_depth(program: &[Instruction]) -> Result<(usize, usize), String> {
+    let mut depths : Vec<Option<usize>> = vec![None; program.len() + 1];
+    depths[0] = Some(0);
+    let mut pending : Vec<usize> = vec![0];
+    let mut max = (0, 0);
+    while let Some(ip) = pending.pop() {
+        let Some(ins) = program.get(ip) else {
+            continue;
+        };
+        let (inputs, outputs) = match ins.opcode {
+            Opcode::OP_CALL => (ins.operands[0] as usize, ins.operands[1] as usize),
+            // only a literal count is known, nothing jumps between it and `.q`
+            Opcode::OP_DUMPQ => match ip.checked_sub(1).map(|prev| &program[prev]) {
+                Some(prev) if prev.opcode == Opcode::OP_PUSH => (1 + prev.operands[0].max(0) as usize, 0),
+                _ => return Err(format!("[WARNING] {}:{}:{}: @ip {}: `.q` pops a computed number of values, the stack depth has no static bound",
+                    ins.loc.file, ins.loc.row+1, ins.loc.col+1, ip)),
+            },
+            _ => (stack_inputs(&ins.opcode), stack_outputs(&ins.opcode)),
+        };
+        // an underflow stops the program, it can't make the stack deeper
+        let after = depths[ip].unwrap().saturating_sub(inputs) + outputs;
+        if after > max.0 {
+            max = (after, ip);
+        }
+        let successors = match flow(ins) {
+            Flow::Next => vec![ip + 1],
+            Flow::Jump(target) => vec![target],
+            Flow::JumpIfZero(target) => vec![ip + 1, target],
+        };
+        for next in successors {
+            match depths[next] {
+                None => {
+                    depths[next] = Some(after);
+                    pending.push(next);
+                },
+                // leaving the program with different depths is fine
+                Some(depth) if depth != after && next < program.len() => {
+                    let loc = &program[next].loc;
+                    return Err(format!("[WARNING] {}:{}:{}: @ip {}: The stack is {} or {} values deep here depending on the path, its depth has no static bound",
+                        loc.file, loc.row+1, loc.col+1, next, depth.min(after), depth.max(after)));
+                },
+                Some(_) => {},
+            }
+        }
+    }
+    Ok(max)
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum AsmSyntax {
+    Nasm,
+    // GNU as, in intel syntax
+    Gas,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+enum AsmLine {
+    Define(String, i64),
+    Section(String),
+    // a section that isn't loaded in memory when the program runs
+    UnallocatedSection(String),
+    Global(String),
+    Extern(String),
+    Label(String),
+    Instruction(String),
+    Comment(String),
+    Align(usize),
+    Bytes(String, Vec<u8>),
+    // 64 bit values, numbers or labels
+    Quads(Vec<String>),
+    // an empty label only pads
+    Reserve(String, usize),
+}
+
+// the generated assembly, rendered for an assembler once it is complete
+#[derive(Default)]
+struct Asm {
+    lines: Vec<AsmLine>,
+}
+
+// rewrites two adjacent instructions, None keeps them as they are
+type Peephole = fn(&str, &str) -> Option<Vec<String>>;
+
+const PEEPHOLES: &[Peephole] = &[push_pop];
+
+// a value pushed only to be popped right away can be moved instead
+fn push_pop(first: &str, second: &str) -> Option<Vec<String>> {
+    let value = first.strip_prefix("push ")?.trim();
+    let register = second.strip_prefix("pop ")?.trim();
+    if register.contains('[') {
+        return None;
+    }
+    if value == register {
+        return Some(Vec::new());
+    }
+    Some(vec![format!("mov {}, {}", register, value)])
+}
+
+const SCHEDULED_REGISTERS: &[&str] = &[
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15",
+];
+
+// the registers an instruction reads and writes, None unless it only works on
+// registers and immediates, so it can't touch the stack or the flags
+// something after it depends on
+fn register_effects(text: &str) -> Option<(Vec<&str>, Vec<&str>)> {
+    let (mnemonic, operands) = text.split_once(' ').unwrap_or((text, ""));
+    let operands : Vec<&str> = operands.split(',').map(|operand| operand.trim()).filter(|operand| !operand.is_empty()).collect();
+    let is_register = |operand: &&str| SCHEDULED_REGISTERS.contains(operand);
+    if !operands.iter().all(|operand| is_register(operand) || parse_integer(operand).is_ok()) {
+        return None;
+    }
+    let registers : Vec<&str> = operands.iter().copied().filter(is_register).collect();
+    match (mnemonic, operands.as_slice()) {
+        ("mov", [dst, _]) if is_register(dst) => Some((registers[1..].to_vec(), vec![dst])),
+        ("add" | "sub" | "and" | "or" | "xor" | "imul", [dst, _]) if is_register(dst) => Some((registers, vec![dst])),
+        ("neg" | "not", [dst]) if is_register(dst) => Some((registers, vec![dst])),
+        ("mul", [src]) if is_register(src) => Some((vec!["rax", src], vec!["rax", "rdx"])),
+        _ => None,
+    }
+}
+
+impl Asm {
+    fn define(&mut self, name: &str, value: i64) {
+        self.lines.push(AsmLine::Define(name.to_string(), value));
+    }
+
+    fn section(&mut self, name: &str) {
+        self.lines.push(AsmLine::Section(name.to_string()));
+    }
+
+    fn unallocated_section(&mut self, name: &str) {
+        self.lines.push(AsmLine::UnallocatedSection(name.to_string()));
+    }
+
+    fn global(&mut self, name: &str) {
+        self.lines.push(AsmLine::Global(name.to_string()));
+    }
+
+    fn external(&mut self, name: &str) {
+        self.lines.push(AsmLine::Extern(name.to_string()));
+    }
+
+    fn label(&mut self, name: &str) {
+        self.lines.push(AsmLine::Label(name.to_string()));
+    }
+
+    fn ins(&mut self, text: &str) {
+        self.lines.push(AsmLine::Instruction(text.to_string()));
+    }
+
+    fn comment(&mut self, text: &str) {
+        self.lines.push(AsmLine::Comment(text.to_string()));
+    }
+
+    fn align(&mut self, bytes: usize) {
+        self.lines.push(AsmLine::Align(bytes));
+    }
+
+    fn bytes(&mut self, label: &str, bytes: &[u8]) {
+        self.lines.push(AsmLine::Bytes(label.to_string(), bytes.to_vec()));
+    }
+
+    fn quads(&mut self, values: &[String]) {
+        self.lines.push(AsmLine::Quads(values.to_vec()));
+    }
+
+    fn reserve(&mut self, label: &str, size: usize) {
+        self.lines.push(AsmLine::Reserve(label.to_string(), size));
+    }
+
+    fn apply_peepholes(&mut self) {
+        let mut i = 0;
+        while i + 1 < self.lines.len() {
+            let rewrite = match (&self.lines[i], &self.lines[i+1]) {
+                (AsmLine::Instruction(first), AsmLine::Instruction(second)) =>
+                    PEEPHOLES.iter().find_map(|peephole| peephole(first, second)),
+                _ => None,
+            };
+            match rewrite {
+                Some(rewrite) => {
+                    self.lines.splice(i..i+2, rewrite.into_iter().map(AsmLine::Instruction));
+                    // the rewrite can form a new pair with the line before it
+                    i = i.saturating_sub(1);
+                },
+                None => i += 1,
+            }
+        }
+    }
+
+    // moves every `pop` up to the `push` of the value it takes, across
+    // instructions that leave its register alone and labels nothing jumps
+    // to, and turns the pair into a register move
+    fn schedule(&mut self) {
+        let targets : HashSet<String> = self.lines.iter()
+            .filter_map(|line| match line { AsmLine::Instruction(text) => Some(text), _ => None })
+            .flat_map(|text| text.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.')))
+            .map(String::from)
+            .collect();
+        let mut j = 0;
+        while j < self.lines.len() {
+            let fused = match &self.lines[j] {
+                AsmLine::Instruction(pop) => pop.strip_prefix("pop ")
+                    .filter(|register| SCHEDULED_REGISTERS.contains(register))
+                    .and_then(|register| self.matching_push(j, register, &targets))
+                    .map(|k| (k, push_pop(&self.instruction(k), pop).unwrap())),
+                _ => None,
+            };
+            match fused {
+                Some((k, rewrite)) => {
+                    self.lines.remove(j);
+                    j = j + rewrite.len() - 1;
+                    self.lines.splice(k..k+1, rewrite.into_iter().map(AsmLine::Instruction));
+                },
+                None => j += 1,
+            }
+        }
+    }
+
+    // the push whose value the pop at `j` into `register` takes, if nothing
+    // between them reads or writes `register` or touches the stack
+    fn matching_push(&self, j: usize, register: &str, targets: &HashSet<String>) -> Option<usize> {
+        for k in (0..j).rev() {
+            match &self.lines[k] {
+                AsmLine::Comment(_) => continue,
+                AsmLine::Label(name) if !targets.contains(name) => continue,
+                AsmLine::Instruction(text) if text.starts_with("push ") => {
+                    return Some(k).filter(|_| push_pop(text, &format!("pop {}", register)).is_some());
+                },
+                AsmLine::Instruction(text) => match register_effects(text) {
+                    Some((reads, writes)) if !reads.contains(&register) && !writes.contains(&register) => continue,
+                    _ => return None,
+                },
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    fn instruction(&self, i: usize) -> String {
+        match &self.lines[i] {
+            AsmLine::Instruction(text) => text.clone(),
+            line => panic!("{:?} is not an instruction", line),
+        }
+    }
+
+    fn finish(mut self, syntax: AsmSyntax) -> String {
+        self.apply_peepholes();
+        let mut text = String::new();
+        if syntax == AsmSyntax::Gas {
+            text += ".intel_syntax noprefix\n";
+        }
+        // nasm scopes a label starting with `.` to the label before it, other
+        // scopes refer to it by both names
+        let mut scoped : HashMap<&str, String> = HashMap::new();
+        let mut scope = "";
+        for line in &self.lines {
+            match line {
+                AsmLine::Label(name) if name.starts_with('.') => { scoped.insert(name, format!("{}{}", scope, name)); },
+                AsmLine::Label(name) => scope = name,
+                _ => {},
+            }
+        }
+        for line in &self.lines {
+            text += &match (line, syntax) {
+                (AsmLine::Define(name, value), AsmSyntax::Nasm) => format!("%define {} {}", name, value),
+                (AsmLine::Define(name, value), AsmSyntax::Gas) => format!(".equ {}, {}", name, value),
+                (AsmLine::Section(name), AsmSyntax::Nasm) => format!("section {}", name),
+                (AsmLine::Section(name), AsmSyntax::Gas) => format!(".section {}", name),
+                (AsmLine::UnallocatedSection(name), AsmSyntax::Nasm) => format!("section {} noalloc", name),
+                // gas doesn't allocate sections with names it doesn't know
+                (AsmLine::UnallocatedSection(name), AsmSyntax::Gas) => format!(".section {}", name),
+                (AsmLine::Global(name), AsmSyntax::Nasm) => format!("global {}", name),
+                (AsmLine::Global(name), AsmSyntax::Gas) => format!(".globl {}", name),
+                (AsmLine::Extern(name), AsmSyntax::Nasm) => format!("extern {}", name),
+                (AsmLine::Extern(name), AsmSyntax::Gas) => format!(".extern {}", name),
+                (AsmLine::Label(name), _) => format!("{}:", name),
+                (AsmLine::Instruction(text), AsmSyntax::Nasm) => format!("    {}", text),
+                // gas needs `PTR` after the size of a memory operand
+                (AsmLine::Instruction(text), AsmSyntax::Gas) => ["BYTE", "WORD", "DWORD", "QWORD"].iter()
+                    .fold(format!("    {}", text), |text, size| text.replace(&format!(" {} [", size), &format!(" {} PTR [", size))),
+                (AsmLine::Comment(text), AsmSyntax::Nasm) => format!("    ;; {}", text),
+                (AsmLine::Comment(text), AsmSyntax::Gas) => format!("    # {}", text),
+                (AsmLine::Align(bytes), AsmSyntax::Nasm) => format!("    align {}", bytes),
+                (AsmLine::Align(bytes), AsmSyntax::Gas) => format!("    .balign {}", bytes),
+                (AsmLine::Bytes(label, bytes), AsmSyntax::Nasm) => format!("{}: db {}", label, db_operands(bytes)),
+                (AsmLine::Bytes(label, bytes), AsmSyntax::Gas) => {
+                    let bytes : Vec<String> = bytes.iter().map(|byte| byte.to_string()).collect();
+                    format!("{}: .byte {}", label, if bytes.is_empty() { String::from("0") } else { bytes.join(",") })
+                },
+                (AsmLine::Quads(values), AsmSyntax::Nasm) => {
+                    let values : Vec<&str> = values.iter().map(|value| scoped.get(value.as_str()).unwrap_or(value).as_str()).collect();
+                    format!("    dq {}", values.join(","))
+                },
+                (AsmLine::Quads(values), AsmSyntax::Gas) => format!("    .quad {}", values.join(",")),
+                (AsmLine::Reserve(label, size), AsmSyntax::Nasm) if label.is_empty() => format!("    resb {}", size),
+                (AsmLine::Reserve(label, size), AsmSyntax::Nasm) => format!("{}: resb {}", label, size),
+                (AsmLine::Reserve(label, size), AsmSyntax::Gas) if label.is_empty() => format!("    .skip {}", size),
+                (AsmLine::Reserve(label, size), AsmSyntax::Gas) => format!("{}: .skip {}", label, size),
+            };
+            text += "\n";
+        }
+        text
+    }
+}
+
+// checks that the address in rax is one of the `globals` cells, clobbers rcx and rdx
+fn emit_address_check(asm: &mut Asm, ins: &Instruction, globals: usize, traps: &mut Vec<(String, (String, String))>) {
+    if globals == 0 {
+        asm.ins(&format!("jmp .invalid_address_{}", ins.ip));
+    } else {
+        asm.ins("lea rcx, [global_0]");
+        asm.ins("mov rdx, rax");
+        asm.ins("sub rdx, rcx");
+        asm.ins("test dl, 7");
+        asm.ins(&format!("jnz .invalid_address_{}", ins.ip));
+        asm.ins(&format!("cmp rdx, {}", (globals - 1) * 8));
+        asm.ins(&format!("ja .invalid_address_{}", ins.ip));
+    }
+    traps.push((format!("invalid_address_{}", ins.ip), trap_message(ins, "Invalid address")));
+}
+
+// constant data of the generated program, identical entries share a label
+#[derive(Default)]
+struct DataSection {
+    entries: Vec<Vec<u8>>,
+}
+
+impl DataSection {
+    // label of the entry holding `bytes`, added if there is none yet
+    fn add(&mut self, bytes: &[u8]) -> String {
+        let index = self.entries.iter().position(|entry| entry == bytes).unwrap_or_else(|| {
+            self.entries.push(bytes.to_vec());
+            self.entries.len() - 1
+        });
+        format!("data_{}", index)
+    }
+
+    fn emit(&self, asm: &mut Asm) {
+        if self.entries.is_empty() {
+            return;
+        }
+        asm.section(".data");
+        for (index, bytes) in self.entries.iter().enumerate() {
+            asm.align(8);
+            asm.bytes(&format!("data_{}", index), bytes);
+        }
+    }
+}
+
+// operands of a `db` directive: runs of printable characters as strings, nasm
+// has no escapes in double quotes so the rest are written as numbers
+fn db_operands(bytes: &[u8]) -> String {
+    let mut operands : Vec<String> = Vec::new();
+    let mut run = String::new();
+    for &byte in bytes {
+        if (0x20..0x7f).contains(&byte) && byte != b'"' {
+            run.push(byte as char);
+            continue;
+        }
+        if !run.is_empty() {
+            operands.push(format!("\"{}\"", run));
+            run.clear();
+        }
+        operands.push(byte.to_string());
+    }
+    if !run.is_empty() {
+        operands.push(format!("\"{}\"", run));
+    }
+    // `db` needs at least one operand, a length of 0 never reads it
+    if operands.is_empty() {
+        operands.push(String::from("0"));
+    }
+    operands.join(",")
+}
+
+// size of the guard page under the data stack
+const PAGE_SIZE: usize = 4096;
+
+// error reported by the generated code when a runtime check fails at `ins`,
+// as the location and the error, which is shared by all the checks for it
+fn trap_message(ins: &Instruction, error: &str) -> (String, String) {
+    (format!("[ERROR] {}:{}:{}: @ip {}: ", ins.loc.file, ins.loc.row+1, ins.loc.col+1, ins.ip), format!("{}\n", error))
+}
+
+// signals the generated code reports the location of before dying of them
+const CRASH_SIGNALS: &[(i64, &str)] = &[(4, "SIGILL"), (7, "SIGBUS"), (8, "SIGFPE"), (11, "SIGSEGV")];
+// bytes of the stack crash handlers run on, so they still can when the
+// program overflowed its own
+const SIGNAL_STACK_SIZE: usize = 4 * PAGE_SIZE;
+// SA_SIGINFO | SA_RESTORER | SA_ONSTACK | SA_RESETHAND
+const CRASH_SA_FLAGS: i64 = 0x8c000004;
+// offset of the faulting rip in the ucontext given to a SA_SIGINFO handler
+const UCONTEXT_RIP: usize = 168;
+
+// printed by the crash handler when the faulting address is in the code of
+// `ins`, followed by the name of the signal
+fn crash_message(ins: &Instruction) -> String {
+    let (location, _) = trap_message(ins, "");
+    format!("{}Runtime crash in {:?}: ", location, ins.opcode)
+}
+
+// `crash`, the handler of CRASH_SIGNALS: it finds the instruction the faulting
+// rip belongs to in `crash_table`, writes its message and the name of the
+// signal to stderr, and raises the signal again, which is back to its
+// default action and kills the program once the handler returns
+fn emit_crash_handler(asm: &mut Asm, data: &mut DataSection, instructions: usize, options: &CompileOptions) {
+    asm.label("crash");
+    asm.ins(&format!("mov     rbx, QWORD [rdx+{}]", UCONTEXT_RIP));
+    asm.ins("mov     r12, rdi");
+    if options.buffer_output {
+        asm.ins("call    flush");
+    }
+    let outside = "[ERROR] Runtime crash outside the code of the program: ";
+    asm.ins(&format!("lea     rsi, [{}]", data.add(outside.as_bytes())));
+    asm.ins(&format!("mov     rdx, {}", outside.len()));
+    asm.ins("lea     rcx, [crash_table]");
+    asm.ins("cmp     rbx, QWORD [rcx]");
+    asm.ins("jb      .write_location");
+    asm.ins(&format!("cmp     rbx, QWORD [rcx+{}]", instructions * 24));
+    asm.ins("jae     .write_location");
+    asm.label(".find_instruction");
+    asm.ins("cmp     rbx, QWORD [rcx+24]");
+    asm.ins("jb      .found_instruction");
+    asm.ins("add     rcx, 24");
+    asm.ins("jmp     .find_instruction");
+    asm.label(".found_instruction");
+    asm.ins("mov     rsi, QWORD [rcx+8]");
+    asm.ins("mov     rdx, QWORD [rcx+16]");
+    asm.label(".write_location");
+    asm.ins("mov     rax, SYS_WRITE");
+    asm.ins("mov     rdi, 2");
+    asm.ins("syscall");
+    for (signal, name) in CRASH_SIGNALS {
+        let name = format!("{}\n", name);
+        asm.ins(&format!("cmp     r12, {}", signal));
+        asm.ins(&format!("jne     .not_signal_{}", signal));
+        asm.ins(&format!("lea     rsi, [{}]", data.add(name.as_bytes())));
+        asm.ins(&format!("mov     rdx, {}", name.len()));
+        asm.ins("mov     rax, SYS_WRITE");
+        asm.ins("mov     rdi, 2");
+        asm.ins("syscall");
+        asm.label(&format!(".not_signal_{}", signal));
+    }
+    // still blocked, it is delivered as the handler returns
+    asm.ins("mov     rax, SYS_GETPID");
+    asm.ins("syscall");
+    asm.ins("mov     rdi, rax");
+    asm.ins("mov     rsi, r12");
+    asm.ins("mov     rax, SYS_KILL");
+    asm.ins("syscall");
+    asm.ins("ret");
+    asm.label("crash_return");
+    asm.ins("mov     rax, SYS_RT_SIGRETURN");
+    asm.ins("syscall");
+}
+
+// makes `crash` handle CRASH_SIGNALS on `signal_stack`, leaving rsp as it was
+fn install_crash_handler(asm: &mut Asm) {
+    // stack_t: ss_sp, ss_flags, ss_size
+    asm.ins(&format!("push {}", SIGNAL_STACK_SIZE));
+    asm.ins("push 0");
+    asm.ins("lea rax, [signal_stack]");
+    asm.ins("push rax");
+    asm.ins("mov rdi, rsp");
+    asm.ins("mov rsi, 0");
+    asm.ins("mov rax, SYS_SIGALTSTACK");
+    asm.ins("syscall");
+    // struct sigaction of the kernel: handler, flags, restorer, mask
+    asm.ins("push 0");
+    asm.ins("lea rax, [crash_return]");
+    asm.ins("push rax");
+    // too big for the 32 bit immediate of push
+    asm.ins(&format!("mov rax, {}", CRASH_SA_FLAGS));
+    asm.ins("push rax");
+    asm.ins("lea rax, [crash]");
+    asm.ins("push rax");
+    for (signal, _) in CRASH_SIGNALS {
+        asm.ins(&format!("mov rdi, {}", signal));
+        asm.ins("mov rsi, rsp");
+        asm.ins("mov rdx, 0");
+        asm.ins("mov r10, 8");
+        asm.ins("mov rax, SYS_RT_SIGACTION");
+        asm.ins("syscall");
+    }
+    asm.ins("add rsp, 56");
+}
+
+// section of compiled executables recording how they were built, read back by
+// `inspect`
+const METADATA_SECTION: &str = ".rorth";
+
+// `key=value` lines stored in the metadata section
+fn build_metadata(options: &CompileOptions) -> String {
+    let mut metadata = format!("version={} {}\ntarget={}\nopt={}\n",
+        NAME, VERSION, NATIVE_BACKEND, if options.schedule { "O2" } else { "O0" });
+    if let Some(hash) = options.source_hash {
+        metadata += &format!("source-hash={:016x}\n", hash);
+    }
+    metadata
+}
+
+// the contents of the metadata section of the 64-bit little endian ELF file in `bytes`
+fn read_metadata(bytes: &[u8]) -> Result<String, String> {
+    let read = |offset: usize, size: usize| -> Result<u64, String> {
+        let field = bytes.get(offset..offset + size).ok_or("Truncated ELF file")?;
+        Ok(field.iter().rev().fold(0, |value, &byte| value << 8 | byte as u64))
+    };
+    if !bytes.starts_with(b"\x7fELF\x02\x01") {
+        return Err(String::from("Not a 64-bit little endian ELF file"));
+    }
+    let (shoff, shentsize, shnum, shstrndx) = (read(0x28, 8)? as usize, read(0x3a, 2)? as usize, read(0x3c, 2)? as usize, read(0x3e, 2)? as usize);
+    // name offset, file offset and size of every section
+    let sections = (0..shnum).map(|i| {
+        let header = shoff + i * shentsize;
+        Ok((read(header, 4)? as usize, read(header + 0x18, 8)? as usize, read(header + 0x20, 8)? as usize))
+    }).collect::<Result<Vec<_>, String>>()?;
+    let &(_, names, _) = sections.get(shstrndx).ok_or("Missing section names")?;
+    for &(name, offset, size) in &sections {
+        if bytes.get(names + name..).is_some_and(|name| name.starts_with(METADATA_SECTION.as_bytes()) && name.get(METADATA_SECTION.len()) == Some(&0)) {
+            let contents = bytes.get(offset..offset + size).ok_or("Truncated ELF file")?;
+            return Ok(String::from_utf8_lossy(contents).into_owned());
+        }
+    }
+    Err(format!("No `{}` section, the executable wasn't built by {} or the section was removed", METADATA_SECTION, NAME))
+}
+
+fn codegen(program: &[Instruction], exec_file : &str, options : &CompileOptions) {
+    let asm_filename = exec_file.to_string() + ".asm";
+    let mut asm = Asm::default();
+    asm.define("SYS_EXIT", 60);
+    asm.define("SYS_WRITE", 1);
+    asm.define("SYS_MMAP", 9);
+    asm.define("SYS_MPROTECT", 10);
+    asm.define("SYS_RT_SIGACTION", 13);
+    asm.define("SYS_RT_SIGRETURN", 15);
+    asm.define("SYS_GETPID", 39);
+    asm.define("SYS_KILL", 62);
+    asm.define("SYS_SIGALTSTACK", 131);
+    // bits of `cpu_features`
+    asm.define("CPU_POPCNT", 1);
+    asm.define("CPU_LZCNT", 2);
+    asm.define("CPU_TZCNT", 4);
+    let mut externs : Vec<String> = program.iter()
+        .filter(|ins| ins.opcode == Opcode::OP_CALL)
+        .map(|ins| unpack_name(&ins.operands[2..]))
+        .collect();
+    externs.sort();
+    externs.dedup();
+    for name in &externs {
+        asm.external(name);
+    }
+    asm.section(".text");
+    // prints rdi followed by a newline, `dump` to stdout and `edump` to stderr
+    asm.label("edump");
+    asm.ins("mov     rsi, 2");
+    asm.ins("mov     rdx, 10");
+    asm.ins("jmp     print");
+    // prints the 64-bit pattern of rdi to stdout, with cl bits per digit and a
+    // `0b` prefix for 1 or `0x` for 4, followed by a newline
+    asm.label("dump_pow2");
+    asm.ins("sub     rsp, 72");
+    asm.ins("lea     r9, [rsp+71]");
+    asm.ins("mov     BYTE [r9], 10");
+    asm.ins("mov     r10, 1");
+    asm.ins("shl     r10, cl");
+    asm.ins("sub     r10, 1");
+    asm.label(".L19");
+    asm.ins("sub     r9, 1");
+    asm.ins("mov     rax, rdi");
+    asm.ins("and     rax, r10");
+    asm.ins("add     rax, 48");
+    asm.ins("cmp     rax, 57");
+    asm.ins("jbe     .L20");
+    asm.ins("add     rax, 39");
+    asm.label(".L20");
+    asm.ins("mov     BYTE [r9], al");
+    asm.ins("shr     rdi, cl");
+    asm.ins("jnz     .L19");
+    asm.ins("mov     eax, 98");
+    asm.ins("mov     edx, 120");
+    asm.ins("cmp     rcx, 4");
+    asm.ins("cmove   eax, edx");
+    asm.ins("mov     BYTE [r9-1], al");
+    asm.ins("mov     BYTE [r9-2], 48");
+    asm.ins("lea     rsi, [r9-2]");
+    asm.ins("lea     rdx, [rsp+72]");
+    asm.ins("sub     rdx, rsi");
+    asm.ins("mov     rdi, 1");
+    asm.ins("call    write_out");
+    asm.ins("add     rsp, 72");
+    asm.ins("ret");
+    asm.label("dump");
+    asm.ins("mov     rsi, 1");
+    asm.ins("mov     rdx, 10");
+    // prints rdi to file descriptor rsi, followed by the character in rdx
+    asm.label("print");
+    asm.ins("sub     rsp, 40");
+    asm.ins("mov     [rsp], rsi");
+    asm.ins("mov     BYTE [rsp+32], dl");
+    asm.ins("mov     rsi, rdi");
+    asm.ins("mov     r11, rdi");
+    asm.ins("test    rdi, rdi");
+    asm.ins("jns     .L1");
+    asm.ins("neg     rsi");
+    asm.label(".L1");
+    asm.ins("mov     r10, -3689348814741910323");
+    asm.ins("lea     rcx, [rsp+31]");
+    asm.ins("lea     r8, [rsp+33]");
+    asm.label(".L2");
+    asm.ins("mov     rax, rsi");
+    asm.ins("mov     r9, r8");
+    asm.ins("mul     r10");
+    asm.ins("mov     rax, rsi");
+    asm.ins("sub     r9, rcx");
+    asm.ins("shr     rdx, 3");
+    asm.ins("lea     rdi, [rdx+rdx*4]");
+    asm.ins("add     rdi, rdi");
+    asm.ins("sub     rax, rdi");
+    asm.ins("add     eax, 48");
+    asm.ins("mov     BYTE [rcx], al");
+    asm.ins("mov     rax, rsi");
+    asm.ins("mov     rsi, rdx");
+    asm.ins("mov     rdx, rcx");
+    asm.ins("sub     rcx, 1");
+    asm.ins("cmp     rax, 9");
+    asm.ins("ja      .L2");
+    asm.ins("test    r11, r11");
+    asm.ins("jns     .L3");
+    asm.ins("mov     BYTE [rcx], 45");
+    asm.ins("mov     rdx, rcx");
+    asm.ins("add     r9, 1");
+    asm.label(".L3");
+    asm.ins("sub     rdx, r8");
+    asm.ins("mov     rdi, [rsp]");
+    asm.ins("lea     rsi, [rsp+33+rdx]");
+    asm.ins("mov     rdx, r9");
+    asm.ins("call    write_out");
+    asm.ins("add     rsp, 40");
+    asm.ins("ret");
+    // writes rdx bytes at rsi to file descriptor rdi
+    asm.label("write_out");
+    if options.buffer_output {
+        // only stdout is buffered
+        asm.ins("cmp     rdi, 1");
+        asm.ins("jne     .L6");
+        asm.ins("mov     rdi, [out_len]");
+        asm.ins("lea     rdi, [rdi+out_buf]");
+        asm.ins("mov     rcx, rdx");
+        asm.ins("rep     movsb");
+        asm.ins("add     QWORD [out_len], rdx");
+        asm.ins(&format!("cmp     QWORD [out_len], {}", OUT_BUF_FLUSH));
+        asm.ins("jb      .L4");
+        asm.ins("call    flush");
+        asm.ins("jmp     .L4");
+        asm.label(".L6");
+    }
+    asm.ins("mov     rax, SYS_WRITE");
+    asm.ins("syscall");
+    asm.label(".L4");
+    asm.ins("ret");
+    if options.buffer_output {
+        asm.label("flush");
+        asm.ins("mov     rdx, [out_len]");
+        asm.ins("test    rdx, rdx");
+        asm.ins("jz      .L5");
+        asm.ins("mov     rax, SYS_WRITE");
+        asm.ins("mov     rdi, 1");
+        asm.ins("lea     rsi, [out_buf]");
+        asm.ins("syscall");
+        asm.ins("mov     QWORD [out_len], 0");
+        asm.label(".L5");
+        asm.ins("ret");
+    }
+    // prints the rdi values below the return address on one line, deepest first,
+    // and returns rdi in rax so the caller can drop them
+    asm.label("dumpq");
+    asm.ins("mov     r12, rdi");
+    asm.ins("mov     r13, rdi");
+    asm.ins("test    r12, r12");
+    asm.ins("jnz     .L7");
+    asm.ins("sub     rsp, 8");
+    asm.ins("mov     BYTE [rsp], 10");
+    asm.ins("mov     rdi, 1");
+    asm.ins("mov     rsi, rsp");
+    asm.ins("mov     rdx, 1");
+    asm.ins("call    write_out");
+    asm.ins("add     rsp, 8");
+    asm.ins("jmp     .L9");
+    asm.label(".L7");
+    asm.ins("mov     rdi, [rsp+r12*8]");
+    asm.ins("mov     rsi, 1");
+    asm.ins("mov     rdx, 32");
+    asm.ins("cmp     r12, 1");
+    asm.ins("jne     .L8");
+    asm.ins("mov     rdx, 10");
+    asm.label(".L8");
+    asm.ins("call    print");
+    asm.ins("sub     r12, 1");
+    asm.ins("jnz     .L7");
+    asm.label(".L9");
+    asm.ins("mov     rax, r13");
+    asm.ins("ret");
+    // rax = rdi to the power of rsi, with rsi taken as unsigned
+    asm.label("pow");
+    asm.ins("mov     rax, 1");
+    asm.label(".L10");
+    asm.ins("test    rsi, rsi");
+    asm.ins("jz      .L12");
+    asm.ins("test    sil, 1");
+    asm.ins("jz      .L11");
+    asm.ins("imul    rax, rdi");
+    asm.label(".L11");
+    asm.ins("imul    rdi, rdi");
+    asm.ins("shr     rsi, 1");
+    asm.ins("jmp     .L10");
+    asm.label(".L12");
+    asm.ins("ret");
+    // rax = floor(sqrt(rdi)), computed digit by digit
+    asm.label("isqrt");
+    asm.ins("xor     rax, rax");
+    asm.ins("mov     rcx, 4611686018427387904");
+    asm.label(".L13");
+    asm.ins("cmp     rcx, rdi");
+    asm.ins("jbe     .L14");
+    asm.ins("shr     rcx, 2");
+    asm.ins("jmp     .L13");
+    asm.label(".L14");
+    asm.ins("test    rcx, rcx");
+    asm.ins("jz      .L16");
+    asm.ins("lea     rdx, [rax+rcx]");
+    asm.ins("shr     rax, 1");
+    asm.ins("cmp     rdi, rdx");
+    asm.ins("jb      .L15");
+    asm.ins("sub     rdi, rdx");
+    asm.ins("add     rax, rcx");
+    asm.label(".L15");
+    asm.ins("shr     rcx, 2");
+    asm.ins("jmp     .L14");
+    asm.label(".L16");
+    asm.ins("ret");
+    // rax = gcd(|rdi|, |rsi|)
+    asm.label("gcd");
+    asm.ins("mov     rax, rdi");
+    asm.ins("neg     rax");
+    asm.ins("cmovs   rax, rdi");
+    asm.ins("mov     rcx, rsi");
+    asm.ins("neg     rcx");
+    asm.ins("cmovs   rcx, rsi");
+    asm.label(".L17");
+    asm.ins("test    rcx, rcx");
+    asm.ins("jz      .L18");
+    asm.ins("xor     rdx, rdx");
+    asm.ins("div     rcx");
+    asm.ins("mov     rax, rcx");
+    asm.ins("mov     rcx, rdx");
+    asm.ins("jmp     .L17");
+    asm.label(".L18");
+    asm.ins("ret");
+    if counts_bits(program) {
+        // rax = number of bits set in rdi, with popcnt when the CPU has it
+        asm.label("popcount");
+        asm.ins("test    BYTE [cpu_features], CPU_POPCNT");
+        asm.ins("jz      .L21");
+        asm.ins("popcnt  rax, rdi");
+        asm.ins("ret");
+        asm.label(".L21");
+        asm.ins("mov     rax, rdi");
+        asm.ins("shr     rax, 1");
+        asm.ins("mov     rcx, 0x5555555555555555");
+        asm.ins("and     rax, rcx");
+        asm.ins("sub     rdi, rax");
+        asm.ins("mov     rax, rdi");
+        asm.ins("mov     rcx, 0x3333333333333333");
+        asm.ins("and     rax, rcx");
+        asm.ins("shr     rdi, 2");
+        asm.ins("and     rdi, rcx");
+        asm.ins("add     rax, rdi");
+        asm.ins("mov     rdi, rax");
+        asm.ins("shr     rdi, 4");
+        asm.ins("add     rax, rdi");
+        asm.ins("mov     rcx, 0x0F0F0F0F0F0F0F0F");
+        asm.ins("and     rax, rcx");
+        asm.ins("mov     rcx, 0x0101010101010101");
+        asm.ins("imul    rax, rcx");
+        asm.ins("shr     rax, 56");
+        asm.ins("ret");
+        // rax = number of leading zeros of rdi, CPUs without lzcnt silently run
+        // it as bsr, so it is only used when cpuid reports it
+        asm.label("clz");
+        asm.ins("test    BYTE [cpu_features], CPU_LZCNT");
+        asm.ins("jz      .L22");
+        asm.ins("lzcnt   rax, rdi");
+        asm.ins("ret");
+        asm.label(".L22");
+        // bsr sets ZF and leaves rax undefined for 0
+        asm.ins("mov     rdx, -1");
+        asm.ins("bsr     rax, rdi");
+        asm.ins("cmovz   rax, rdx");
+        asm.ins("mov     rcx, 63");
+        asm.ins("sub     rcx, rax");
+        asm.ins("mov     rax, rcx");
+        asm.ins("ret");
+        // rax = number of trailing zeros of rdi, tzcnt is bsf without BMI1
+        asm.label("ctz");
+        asm.ins("test    BYTE [cpu_features], CPU_TZCNT");
+        asm.ins("jz      .L23");
+        asm.ins("tzcnt   rax, rdi");
+        asm.ins("ret");
+        asm.label(".L23");
+        asm.ins("mov     rdx, 64");
+        asm.ins("bsf     rax, rdi");
+        asm.ins("cmovz   rax, rdx");
+        asm.ins("ret");
+    }
+    // writes the rdx bytes at rsi to stderr and exits with 1
+    asm.label("trap");
+    if options.buffer_output {
+        asm.ins("push    rsi");
+        asm.ins("push    rdx");
+        asm.ins("push    r8");
+        asm.ins("push    r9");
+        asm.ins("call    flush");
+        asm.ins("pop     r9");
+        asm.ins("pop     r8");
+        asm.ins("pop     rdx");
+        asm.ins("pop     rsi");
+    }
+    asm.ins("mov     rax, SYS_WRITE");
+    asm.ins("mov     rdi, 2");
+    asm.ins("syscall");
+    asm.ins("mov     rax, SYS_WRITE");
+    asm.ins("mov     rdi, 2");
+    asm.ins("mov     rsi, r8");
+    asm.ins("mov     rdx, r9");
+    asm.ins("syscall");
+    asm.ins("mov     rax, SYS_EXIT");
+    asm.ins("mov     rdi, 1");
+    asm.ins("syscall");
+    let mut data = DataSection::default();
+    emit_crash_handler(&mut asm, &mut data, program.len(), options);
+    asm.global("_start");
+    asm.label("_start");
+    install_crash_handler(&mut asm);
+    // label and message of every runtime check that can fail
+    let mut traps : Vec<(String, (String, String))> = Vec::new();
+    if let Some(size) = options.data_stack {
+        // map the guard page and the stack inaccessible, then open the stack
+        asm.ins("mov rax, SYS_MMAP");
+        asm.ins("mov rdi, 0");
+        asm.ins(&format!("mov rsi, {}", size + PAGE_SIZE));
+        asm.ins("mov rdx, 0");
+        asm.ins("mov r10, 0x22");
+        asm.ins("mov r8, -1");
+        asm.ins("mov r9, 0");
+        asm.ins("syscall");
+        asm.ins("test rax, rax");
+        asm.ins("js .data_stack_failed");
+        asm.ins(&format!("lea rdi, [rax+{}]", PAGE_SIZE));
+        asm.ins(&format!("mov rsi, {}", size));
+        asm.ins("mov rdx, 3");
+        asm.ins("mov rax, SYS_MPROTECT");
+        asm.ins("syscall");
+        asm.ins("test rax, rax");
+        asm.ins("js .data_stack_failed");
+        asm.ins(&format!("lea rsp, [rdi+{}]", size));
+        traps.push(("data_stack_failed".to_string(), ("[ERROR] ".to_string(), "Could not map the data stack\n".to_string())));
+    }
+    if counts_bits(program) {
+        // bit 23 of ecx for leaf 1, bit 5 of ecx for leaf 0x80000001 and
+        // bit 3 of ebx for leaf 7, if the CPU has it
+        asm.ins("mov eax, 1");
+        asm.ins("cpuid");
+        asm.ins("shr ecx, 23");
+        asm.ins("and ecx, 1");
+        asm.ins("mov r8, rcx");
+        // leaf 0x80000000 gives the highest extended leaf
+        asm.ins("mov eax, 0x80000000");
+        asm.ins("cpuid");
+        asm.ins("cmp eax, 0x80000001");
+        asm.ins("jb .cpu_extended_features_done");
+        asm.ins("mov eax, 0x80000001");
+        asm.ins("cpuid");
+        asm.ins("shr ecx, 5");
+        asm.ins("and ecx, 1");
+        asm.ins("shl ecx, 1");
+        asm.ins("or r8, rcx");
+        asm.label(".cpu_extended_features_done");
+        asm.ins("mov eax, 0");
+        asm.ins("cpuid");
+        asm.ins("cmp eax, 7");
+        asm.ins("jb .cpu_features_done");
+        asm.ins("mov eax, 7");
+        asm.ins("mov ecx, 0");
+        asm.ins("cpuid");
+        asm.ins("shr ebx, 3");
+        asm.ins("and ebx, 1");
+        asm.ins("shl ebx, 2");
+        asm.ins("or r8, rbx");
+        asm.label(".cpu_features_done");
+        asm.ins("mov QWORD [cpu_features], r8");
+    }
+    if options.runtime_checks || program.iter().any(|ins| ins.opcode == Opcode::OP_DEPTH) {
+        // the stack base, the stack is empty when rsp is back at it
+        asm.ins("mov r15, rsp");
+    }
+    let globals = globals_count(program);
+    let labels = block_labels(program);
+    // label at the start of the instruction at `target`, a while or the code
+    // right after the instruction ending a block
+    let label = |target: usize| match options.mangle {
+        Mangle::Readable if program.get(target).is_some_and(|ins| ins.opcode == Opcode::OP_WHILE) => format!(".{}", labels[&target]),
+        Mangle::Readable => format!(".{}", labels[&(target - 1)]),
+        Mangle::Plain => format!(".addr_{}", target),
+    };
+    let mut blocks = basic_blocks(program).into_iter().peekable();
+    for ins in program {
+        if let Some(block) = blocks.next_if(|block| block.start == ins.ip) {
+            let successors : Vec<String> = block.successors.iter().map(|successor| successor.to_string()).collect();
+            asm.comment(&format!("block {}..={} -> {}", block.start, block.end, successors.join(", ")));
+        }
+        asm.label(&format!(".addr_{}", ins.ip));
+        asm.comment(&format!("{:?}", ins.opcode));
+        if options.mangle == Mangle::Readable && matches!(ins.opcode, Opcode::OP_IF | Opcode::OP_WHILE) {
+            asm.label(&format!(".{}", labels[&ins.ip]));
+        }
+        if options.runtime_checks && stack_inputs(&ins.opcode) > 0 {
+            asm.ins(&format!("lea rax, [rsp+{}]", stack_inputs(&ins.opcode) * 8));
+            asm.ins("cmp rax, r15");
+            asm.ins(&format!("ja .underflow_{}", ins.ip));
+            traps.push((format!("underflow_{}", ins.ip), trap_message(ins, "Stack underflow")));
+        }
+        match ins.opcode {
+            Opcode::OP_PUSH => {
+                if i32::try_from(ins.operands[0]).is_ok() {
+                    asm.ins(&format!("push {}", ins.operands[0]));
+                } else {
+                    // push only takes 32 bit immediates
+                    asm.ins(&format!("mov rax, {}", ins.operands[0]));
+                    asm.ins("push rax");
+                }
+            },
+            Opcode::OP_ADD => {
+                asm.ins("pop rax");
+                asm.ins("pop rbx");
+                asm.ins("add rax, rbx");
+                asm.ins("push rax");
+            },
+            Opcode::OP_SUB => {
+                asm.ins("pop rax");
+                asm.ins("pop rbx");
+                asm.ins("sub rbx, rax");
+                asm.ins("push rbx");
+            },
+            Opcode::OP_MUL => {
+                asm.ins("pop rax");
+                asm.ins("pop rbx");
+                asm.ins("mul rbx");
+                asm.ins("push rax");
+            },
+            Opcode::OP_DIV | Opcode::OP_MOD | Opcode::OP_DIVMOD => {
+                asm.ins("pop rbx");
+                asm.ins("pop rax");
+                // division by zero traps like in the interpreter, even without --runtime-checks
+                asm.ins("test rbx, rbx");
+                asm.ins(&format!("jz .division_by_zero_{}", ins.ip));
+                traps.push((format!("division_by_zero_{}", ins.ip), trap_message(ins, "Division by zero")));
+                // idiv faults on i64::MIN / -1, dividing by -1 is a wrapping negation instead
+                asm.ins("cmp rbx, -1");
+                asm.ins(&format!("jne .divide_{}", ins.ip));
+                asm.ins("neg rax");
+                asm.ins("xor edx, edx");
+                asm.ins(&format!("jmp .divided_{}", ins.ip));
+                asm.label(&format!(".divide_{}", ins.ip));
+                asm.ins("cqo");
+                asm.ins("idiv rbx");
+                asm.label(&format!(".divided_{}", ins.ip));
+                if ins.opcode != Opcode::OP_MOD {
+                    asm.ins("push rax");
+                }
+                if ins.opcode != Opcode::OP_DIV {
+                    asm.ins("push rdx");
+                }
+            },
+            Opcode::OP_POW => {
+                asm.ins("pop rsi");
+                asm.ins("pop rdi");
+                asm.ins("call pow");
+                asm.ins("push rax");
+            },
+            Opcode::OP_SQRT => {
+                asm.ins("pop rdi");
+                asm.ins("test rdi, rdi");
+                asm.ins(&format!("js .negative_sqrt_{}", ins.ip));
+                asm.ins("call isqrt");
+                asm.ins("push rax");
+                traps.push((format!("negative_sqrt_{}", ins.ip), trap_message(ins, "Square root of negative number")));
+            },
+            Opcode::OP_GCD => {
+                asm.ins("pop rsi");
+                asm.ins("pop rdi");
+                asm.ins("call gcd");
+                asm.ins("push rax");
+            },
+            Opcode::OP_NOT => {
+                asm.ins("xor rcx, rcx");
+                asm.ins("pop rax");
+                asm.ins("test rax, rax");
+                asm.ins("sete cl");
+                asm.ins("push rcx");
+            },
+            Opcode::OP_EQ => {
+                asm.ins("mov rcx, 0");
+                asm.ins("mov rdx, 1");
+                asm.ins("pop rax");
+                asm.ins("pop rbx");
+                asm.ins("cmp rax, rbx");
+                asm.ins("cmove rcx, rdx");
+                asm.ins("push rcx");
+            },
+            Opcode::OP_NE => {
+                asm.ins("mov rcx, 0");
+                asm.ins("mov rdx, 1");
+                asm.ins("pop rax");
+                asm.ins("pop rbx");
+                asm.ins("cmp rax, rbx");
+                asm.ins("cmovne rcx, rdx");
+                asm.ins("push rcx");
+            },
+            Opcode::OP_GT => {
+                asm.ins("mov rcx, 0");
+                asm.ins("mov rdx, 1");
+                asm.ins("pop rbx");
+                asm.ins("pop rax");
+                asm.ins("cmp rax, rbx");
+                asm.ins("cmovg rcx, rdx");
+                asm.ins("push rcx");
+            },
+            Opcode::OP_GE => {
+                asm.ins("mov rcx, 0");
+                asm.ins("mov rdx, 1");
+                asm.ins("pop rbx");
+                asm.ins("pop rax");
+                asm.ins("cmp rax, rbx");
+                asm.ins("cmovge rcx, rdx");
+                asm.ins("push rcx");
+            },
+            Opcode::OP_LT => {
+                asm.ins("mov rcx, 0");
+                asm.ins("mov rdx, 1");
+                asm.ins("pop rbx");
+                asm.ins("pop rax");
+                asm.ins("cmp rax, rbx");
+                asm.ins("cmovl rcx, rdx");
+                asm.ins("push rcx");
+            },
+            Opcode::OP_LE => {
+                asm.ins("mov rcx, 0");
+                asm.ins("mov rdx, 1");
+                asm.ins("pop rbx");
+                asm.ins("pop rax");
+                asm.ins("cmp rax, rbx");
+                asm.ins("cmovle rcx, rdx");
+                asm.ins("push rcx");
+            },
+            Opcode::OP_SHL => {
+                asm.ins("pop rcx");
+                asm.ins("pop rbx");
+                asm.ins("shl rbx, cl");
+                asm.ins("push rbx");
+            }
+            Opcode::OP_SHR => {
+                asm.ins("pop rcx");
+                asm.ins("pop rbx");
+                asm.ins("shr rbx, cl");
+                asm.ins("push rbx");
+            }
+            Opcode::OP_BOR => {
+                asm.ins("pop rax");
+                asm.ins("pop rbx");
+                asm.ins("or rbx, rax");
+                asm.ins("push rbx");
+            }
+            Opcode::OP_BAND => {
+                asm.ins("pop rax");
+                asm.ins("pop rbx");
+                asm.ins("and rbx, rax");
+                asm.ins("push rbx");
+            }
+            Opcode::OP_BXOR => {
+                asm.ins("pop rax");
+                asm.ins("pop rbx");
+                asm.ins("xor rbx, rax");
+                asm.ins("push rbx");
+            }
+            Opcode::OP_ROTL => {
+                asm.ins("pop rcx");
+                asm.ins("pop rbx");
+                asm.ins("rol rbx, cl");
+                asm.ins("push rbx");
+            }
+            Opcode::OP_ROTR => {
+                asm.ins("pop rcx");
+                asm.ins("pop rbx");
+                asm.ins("ror rbx, cl");
+                asm.ins("push rbx");
+            }
+            Opcode::OP_BSWAP16 => {
+                asm.ins("pop rax");
+                asm.ins("ror ax, 8");
+                asm.ins("movzx rax, ax");
+                asm.ins("push rax");
+            }
+            Opcode::OP_BSWAP32 => {
+                // writing eax clears the upper half of rax
+                asm.ins("pop rax");
+                asm.ins("bswap eax");
+                asm.ins("push rax");
+            }
+            Opcode::OP_BSWAP64 => {
+                asm.ins("pop rax");
+                asm.ins("bswap rax");
+                asm.ins("push rax");
+            }
+            Opcode::OP_NEG => {
+                asm.ins("pop rax");
+                asm.ins("neg rax");
+                asm.ins("push rax");
+            }
+            Opcode::OP_ABS => {
+                // keep the original when negating it gives a negative value
+                asm.ins("pop rax");
+                asm.ins("mov rbx, rax");
+                asm.ins("neg rax");
+                asm.ins("cmovl rax, rbx");
+                asm.ins("push rax");
+            }
+            Opcode::OP_AND => {
+                asm.ins("pop rax");
+                asm.ins("pop rbx");
+                asm.ins("test rax, rax");
+                asm.ins("setne al");
+                asm.ins("test rbx, rbx");
+                asm.ins("setne bl");
+                asm.ins("and al, bl");
+                asm.ins("movzx rax, al");
+                asm.ins("push rax");
+            }
+            // the or is zero only if both are
+            Opcode::OP_OR => {
+                asm.ins("pop rax");
+                asm.ins("pop rbx");
+                asm.ins("or rax, rbx");
+                asm.ins("setne al");
+                asm.ins("movzx rax, al");
+                asm.ins("push rax");
+            }
+            // the value is updated in place, without popping it
+            Opcode::OP_BNOT => {
+                asm.ins("not QWORD [rsp]");
+            }
+            Opcode::OP_INC => {
+                asm.ins("inc QWORD [rsp]");
+            }
+            Opcode::OP_DEC => {
+                asm.ins("dec QWORD [rsp]");
+            }
+            Opcode::OP_POPCOUNT => {
+                asm.ins("pop rdi");
+                asm.ins("call popcount");
+                asm.ins("push rax");
+            }
+            Opcode::OP_CLZ => {
+                asm.ins("pop rdi");
+                asm.ins("call clz");
+                asm.ins("push rax");
+            }
+            Opcode::OP_CTZ => {
+                asm.ins("pop rdi");
+                asm.ins("call ctz");
+                asm.ins("push rax");
+            }
+            Opcode::OP_DUP => {
+                asm.ins("pop rax");
+                asm.ins("push rax");
+                asm.ins("push rax");
+            }
+            Opcode::OP_SWAP => {
+                asm.ins("pop rax");
+                asm.ins("pop rbx");
+                asm.ins("push rax");
+                asm.ins("push rbx");
+            }
+            Opcode::OP_ROT => {
+                asm.ins("pop rcx");
+                asm.ins("pop rbx");
+                asm.ins("pop rax");
+                asm.ins("push rbx");
+                asm.ins("push rcx");
+                asm.ins("push rax");
+            }
+            Opcode::OP_2DUP => {
+                asm.ins("mov rax, [rsp+8]");
+                asm.ins("mov rbx, [rsp]");
+                asm.ins("push rax");
+                asm.ins("push rbx");
+            }
+            Opcode::OP_2DROP => {
+                asm.ins("add rsp, 16");
+            }
+            Opcode::OP_2SWAP => {
+                asm.ins("pop rdx");
+                asm.ins("pop rcx");
+                asm.ins("pop rbx");
+                asm.ins("pop rax");
+                asm.ins("push rcx");
+                asm.ins("push rdx");
+                asm.ins("push rax");
+                asm.ins("push rbx");
+            }
+            Opcode::OP_2OVER => {
+                asm.ins("mov rax, [rsp+24]");
+                asm.ins("mov rbx, [rsp+16]");
+                asm.ins("push rax");
+                asm.ins("push rbx");
+            }
+            Opcode::OP_DEPTH => {
+                asm.ins("mov rax, r15");
+                asm.ins("sub rax, rsp");
+                asm.ins("shr rax, 3");
+                asm.ins("push rax");
+            }
+            // rcx is `n`, the values under it are addressed from rsp
+            Opcode::OP_PICK | Opcode::OP_ROLL => {
+                asm.ins("pop rcx");
+                if options.runtime_checks {
+                    // compared unsigned, so negative counts fail too
+                    asm.ins("mov rax, r15");
+                    asm.ins("sub rax, rsp");
+                    asm.ins("shr rax, 3");
+                    asm.ins("cmp rcx, rax");
+                    asm.ins(&format!("jae .underflow_{}", ins.ip));
+                    traps.push((format!("underflow_{}", ins.ip), trap_message(ins, "Stack underflow")));
+                }
+                asm.ins("mov rax, [rsp+rcx*8]");
+                if ins.opcode == Opcode::OP_ROLL {
+                    // move the values above it down one slot
+                    asm.label(&format!(".roll_{}", ins.ip));
+                    asm.ins("test rcx, rcx");
+                    asm.ins(&format!("jz .roll_{}_done", ins.ip));
+                    asm.ins("mov rdx, [rsp+rcx*8-8]");
+                    asm.ins("mov [rsp+rcx*8], rdx");
+                    asm.ins("sub rcx, 1");
+                    asm.ins(&format!("jmp .roll_{}", ins.ip));
+                    asm.label(&format!(".roll_{}_done", ins.ip));
+                    asm.ins("mov [rsp], rax");
+                } else {
+                    asm.ins("push rax");
+                }
+            }
+            // rejected by `compile` before generating any code
+            Opcode::OP_STRING | Opcode::OP_CONCAT | Opcode::OP_SLICE | Opcode::OP_STRLEN | Opcode::OP_PUTS => {
+                unreachable!("strings are not supported by compiled programs");
+            }
+            // the arguments go in registers, the deepest first, and the stack
+            // is aligned as the ABI expects, rbx is kept by the callee
+            Opcode::OP_CALL => {
+                let inputs = ins.operands[0] as usize;
+                if options.runtime_checks && inputs > 0 {
+                    asm.ins(&format!("lea rax, [rsp+{}]", inputs * 8));
+                    asm.ins("cmp rax, r15");
+                    asm.ins(&format!("ja .underflow_{}", ins.ip));
+                    traps.push((format!("underflow_{}", ins.ip), trap_message(ins, "Stack underflow")));
+                }
+                for register in ARG_REGISTERS[..inputs].iter().rev() {
+                    asm.ins(&format!("pop {}", register));
+                }
+                asm.ins("mov rbx, rsp");
+                asm.ins("and rsp, -16");
+                asm.ins(&format!("call {}", unpack_name(&ins.operands[2..])));
+                asm.ins("mov rsp, rbx");
+                if ins.operands[1] == 1 {
+                    asm.ins("push rax");
+                }
+            }
+            Opcode::OP_GLOBAL => {
+                asm.ins(&format!("lea rax, [global_{}]", ins.operands[0]));
+                asm.ins("push rax");
+            }
+            Opcode::OP_LOAD => {
+                asm.ins("pop rax");
+                if options.runtime_checks {
+                    emit_address_check(&mut asm, ins, globals, &mut traps);
+                }
+                asm.ins("push QWORD [rax]");
+            }
+            Opcode::OP_STORE => {
+                asm.ins("pop rax");
+                asm.ins("pop rbx");
+                if options.runtime_checks {
+                    emit_address_check(&mut asm, ins, globals, &mut traps);
+                }
+                asm.ins("mov [rax], rbx");
+            }
+            Opcode::OP_DUMP => {
+                asm.ins("pop rdi");
+                match ins.operands.first() {
+                    Some(2) => {
+                        asm.ins("mov rcx, 1");
+                        asm.ins("call dump_pow2");
+                    },
+                    Some(16) => {
+                        asm.ins("mov rcx, 4");
+                        asm.ins("call dump_pow2");
+                    },
+                    _ => asm.ins("call dump"),
+                }
+            },
+            Opcode::OP_DUMPQ => {
+                asm.ins("pop rdi");
+                if options.runtime_checks {
+                    // compared unsigned, so negative counts underflow too
+                    asm.ins("mov rax, r15");
+                    asm.ins("sub rax, rsp");
+                    asm.ins("shr rax, 3");
+                    asm.ins("cmp rdi, rax");
+                    asm.ins(&format!("ja .underflow_{}", ins.ip));
+                    traps.push((format!("underflow_{}", ins.ip), trap_message(ins, "Stack underflow")));
+                }
+                asm.ins("call dumpq");
+                asm.ins("lea rsp, [rsp+rax*8]");
+            },
+            Opcode::OP_EPRINT => {
+                asm.ins("pop rdi");
+                asm.ins("call edump");
+            },
+            Opcode::OP_IF | Opcode::OP_ELSE | Opcode::OP_END | Opcode::OP_WHILE | Opcode::OP_DO => {
+                match flow(ins) {
+                    Flow::Next => { },
+                    Flow::Jump(target) => {
+                        asm.ins(&format!("jmp {}", label(target)));
+                    },
+                    Flow::JumpIfZero(target) => {
+                        asm.ins("pop rax");
+                        asm.ins("test rax, rax");
+                        asm.ins(&format!("jz {}", label(target)));
+                    },
+                }
+            },
+        }
+        if options.mangle == Mangle::Readable && matches!(ins.opcode, Opcode::OP_ELSE | Opcode::OP_DO | Opcode::OP_END) {
+            asm.label(&format!(".{}", labels[&ins.ip]));
+        }
+    }
+    // blocks ending the program jump past their last instruction
+    asm.label(&format!(".addr_{}", program.len()));
+    asm.label(".end");
+    if options.buffer_output {
+        asm.ins("call flush");
+    }
+    asm.ins("mov rax, SYS_EXIT");
+    asm.ins("mov rdi, 0");
+    asm.ins("syscall");
+    asm.ins("ret");
+    traps.dedup();
+    for (label, (location, error)) in &traps {
+        asm.label(&format!(".{}", label));
+        asm.ins(&format!("lea rsi, [{}]", data.add(location.as_bytes())));
+        asm.ins(&format!("mov rdx, {}", location.len()));
+        asm.ins(&format!("lea r8, [{}]", data.add(error.as_bytes())));
+        asm.ins(&format!("mov r9, {}", error.len()));
+        asm.ins("jmp trap");
+    }
+    let crashes : Vec<String> = program.iter()
+        .map(|ins| data.add(crash_message(ins).as_bytes()))
+        .collect();
+    data.emit(&mut asm);
+    // the start and message of every instruction, by address, and the end of
+    // the last one
+    asm.section(".data");
+    asm.align(8);
+    asm.label("crash_table");
+    for (ins, message) in program.iter().zip(&crashes) {
+        asm.quads(&[format!(".addr_{}", ins.ip), message.clone(), crash_message(ins).len().to_string()]);
+    }
+    asm.quads(&[format!(".addr_{}", program.len()), String::from("0"), String::from("0")]);
+    asm.unallocated_section(METADATA_SECTION);
+    asm.bytes("rorth_metadata", build_metadata(options).as_bytes());
+    asm.section(".bss");
+    let mut end = 0;
+    for region in plan_bss(program, options) {
+        if region.offset > end {
+            asm.reserve("", region.offset - end);
+        }
+        asm.reserve(&region.label, region.size);
+        end = region.offset + region.size;
+    }
+    if options.schedule {
+        asm.schedule();
+    }
+    std::fs::write(&asm_filename, asm.finish(options.asm_syntax))
+        .unwrap_or_else(|_| panic!("Could not write file {}", asm_filename));
+}
+
+// names of the labels of block instructions, after the `if` or `while` they
+// belong to: `if` and `while` are labeled at their start, `else`, `do` and
+// `end` right after their code, e.g. `while_3`, `while_3_do` and `while_3_end`
+fn block_labels(program: &[Instruction]) -> HashMap<usize, String> {
+    let mut labels : HashMap<usize, String> = HashMap::new();
+    let mut blocks : Vec<String> = Vec::new();
+    for ins in program {
+        match ins.opcode {
+            Opcode::OP_IF | Opcode::OP_WHILE => {
+                let block = format!("{}_{}", if ins.opcode == Opcode::OP_IF { "if" } else { "while" }, ins.ip);
+                labels.insert(ins.ip, block.clone());
+                blocks.push(block);
+            },
+            Opcode::OP_ELSE | Opcode::OP_DO => {
+                let kind = if ins.opcode == Opcode::OP_ELSE { "else" } else { "do" };
+                labels.insert(ins.ip, format!("{}_{}", blocks.last().unwrap(), kind));
+            },
+            Opcode::OP_END => {
+                labels.insert(ins.ip, format!("{}_end", blocks.pop().unwrap()));
+            },
+            _ => {},
+        }
+    }
+    labels
+}
+
+// a zero initialized region of `.bss`, `offset` bytes from its start
+#[derive(PartialEq, Eq, Debug)]
+struct BssRegion {
+    label: String,
+    offset: usize,
+    size: usize,
+}
+
+// whether the program uses popcount, clz or ctz, whose routines check the
+// features of the CPU at startup
+fn counts_bits(program: &[Instruction]) -> bool {
+    program.iter().any(|ins| matches!(ins.opcode, Opcode::OP_POPCOUNT | Opcode::OP_CLZ | Opcode::OP_CTZ))
+}
+
+// lays out everything the program reserves in `.bss`, globals go first so that
+// they start at GLOBALS_BASE
+fn plan_bss(program: &[Instruction], options: &CompileOptions) -> Vec<BssRegion> {
+    // label, size and alignment of each region
+    let mut regions : Vec<(String, usize, usize)> = (0..globals_count(program))
+        .map(|global| (format!("global_{}", global), 8, 8))
+        .collect();
+    if counts_bits(program) {
+        regions.push((String::from("cpu_features"), 8, 8));
+    }
+    if options.buffer_output {
+        regions.push((String::from("out_len"), 8, 8));
+        regions.push((String::from("out_buf"), OUT_BUF_SIZE, 16));
+    }
+    regions.push((String::from("signal_stack"), SIGNAL_STACK_SIZE, 16));
+    let mut offset : usize = 0;
+    regions.into_iter().map(|(label, size, align)| {
+        let region = BssRegion { label, offset: offset.next_multiple_of(align), size };
+        offset = region.offset + region.size;
+        region
+    }).collect()
+}
+
+fn layout_to_string(layout: &[BssRegion]) -> String {
+    let mut text = String::from("address     size  label\n");
+    for region in layout {
+        text += &format!("{:#010x}  {:>5}  {}\n", GLOBALS_BASE as usize + region.offset, region.size, region.label);
+    }
+    text
+}
+
+// a directory of its own in the system temporary directory, for the files of
+// one build, removed with everything in it when the session is dropped
+struct BuildSession {
+    dir: std::path::PathBuf,
+}
+
+impl BuildSession {
+    fn new() -> io::Result<Self> {
+        static SESSIONS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let session = SESSIONS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let dir = env::temp_dir().join(format!("{}-{}-{}", NAME, process::id(), session));
+        std::fs::create_dir_all(&dir)?;
+        Ok(BuildSession { dir })
+    }
+
+    // path of the file `name` in the session directory
+    fn path(&self, name: &str) -> String {
+        self.dir.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for BuildSession {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+// what to do about the first tool needed to build that is not in `path`
+fn missing_tool(options : &CompileOptions, path : &std::ffi::OsStr) -> Option<String> {
+    let found = |tool: &str| env::split_paths(path).any(|dir| dir.join(tool).is_file());
+    match options.asm_syntax {
+        AsmSyntax::Nasm if !found("nasm") => Some(String::from(
+            "Could not find `nasm` in PATH, install it (e.g. `apt install nasm`) or assemble with GNU as using `--asm-syntax gas`")),
+        AsmSyntax::Gas if !found("as") => Some(String::from(
+            "Could not find `as` in PATH, install GNU binutils (e.g. `apt install binutils`)")),
+        _ if !found("ld") => Some(String::from(
+            "Could not find `ld` in PATH, install GNU binutils (e.g. `apt install binutils`)")),
+        _ => None,
+    }
+}
+
+// runs `tool`, failing if it can't be started or doesn't succeed
+fn run_tool(tool : &str, args : &[&str], timings : &mut Timings) -> Result<(), String> {
+    // the output of the tools goes to stderr, stdout is left to the program
+    let status = timings.time(tool, || Command::new(tool)
+        .args(args)
+        .stdout(io::stderr())
+        .stderr(Stdio::inherit())
+        .status())
+        .map_err(|err| format!("Could not run {}: {}", tool, err))?;
+    if !status.success() {
+        return Err(format!("{} failed with {}", tool, status));
+    }
+    Ok(())
+}
+
+fn build(exec_file : &str, options : &CompileOptions, timings : &mut Timings) -> Result<(), String> {
+    let asm_filename = exec_file.to_string() + ".asm";
+    let obj_filename = exec_file.to_string() + ".o";
+    let (assembler, args) = match options.asm_syntax {
+        AsmSyntax::Nasm => ("nasm", vec!["-felf64", asm_filename.as_str()]),
+        AsmSyntax::Gas => ("as", vec!["--64", "-o", obj_filename.as_str(), asm_filename.as_str()]),
+    };
+    info(options.verbose, &format!("running {} {}", assembler, args.join(" ")));
+    run_tool(assembler, &args, timings)?;
+
+    let bss_address = format!("-Tbss={:#x}", GLOBALS_BASE);
+    let mut ld_args = vec!["-o", exec_file, bss_address.as_str(), obj_filename.as_str()];
+    ld_args.extend(options.link_objects.iter().map(|object| object.as_str()));
+    info(options.verbose, &format!("running ld {}", ld_args.join(" ")));
+    run_tool("ld", &ld_args, timings)
+}
+
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => escaped += "\\\"",
+            '\\' => escaped += "\\\\",
+            c if (c as u32) < 0x20 => escaped += &format!("\\u{:04x}", c as u32),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// a value of the flat JSON objects `batch` reads, no request needs nested
+// arrays or objects
+#[derive(Debug, Clone, PartialEq)]
+enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+}
+
+impl JsonValue {
+    fn to_json(&self) -> String {
+        match self {
+            JsonValue::Null => String::from("null"),
+            JsonValue::Bool(value) => value.to_string(),
+            JsonValue::Number(value) => value.to_string(),
+            JsonValue::String(value) => format!("\"{}\"", json_escape(value)),
+        }
+    }
+}
+
+// the four hex digits of a `\\u` escape
+fn json_hex4(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<u16, String> {
+    let digits : String = chars.by_ref().take(4).collect();
+    match u16::from_str_radix(&digits, 16) {
+        Ok(unit) if digits.len() == 4 => Ok(unit),
+        _ => Err(format!("Invalid escape `\\u{}`", digits)),
+    }
+}
+
+// reads a JSON string whose opening quote was already read
+fn json_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    let mut text = String::new();
+    loop {
+        match chars.next() {
+            None => return Err(String::from("Unterminated string")),
+            Some('"') => return Ok(text),
+            Some('\\') => match chars.next() {
+                Some('"') => text.push('"'),
+                Some('\\') => text.push('\\'),
+                Some('/') => text.push('/'),
+                Some('b') => text.push('\u{8}'),
+                Some('f') => text.push('\u{c}'),
+                Some('n') => text.push('\n'),
+                Some('r') => text.push('\r'),
+                Some('t') => text.push('\t'),
+                Some('u') => {
+                    let mut units = vec![json_hex4(chars)?];
+                    // characters outside the BMP are escaped as a surrogate pair
+                    if (0xd800..0xdc00).contains(&units[0]) && chars.next_if_eq(&'\\').is_some() && chars.next_if_eq(&'u').is_some() {
+                        units.push(json_hex4(chars)?);
+                    }
+                    for c in char::decode_utf16(units) {
+                        text.push(c.map_err(|_| String::from("Invalid surrogate in `\\u` escape"))?);
+                    }
+                },
+                Some(c) => return Err(format!("Invalid escape `\\{}`", c)),
+                None => return Err(String::from("Unterminated string")),
+            },
+            Some(c) => text.push(c),
+        }
+    }
+}
+
+// parses a JSON object whose values are all strings, integers, booleans or null
+fn parse_flat_json_object(text: &str) -> Result<Vec<(String, JsonValue)>, String> {
+    let mut chars = text.trim().chars().peekable();
+    let skip_spaces = |chars: &mut std::iter::Peekable<std::str::Chars>| while chars.next_if(|c| c.is_whitespace()).is_some() {};
+    let mut fields = Vec::new();
+    if chars.next() != Some('{') {
+        return Err(String::from("Expected `{`"));
+    }
+    skip_spaces(&mut chars);
+    if chars.next_if_eq(&'}').is_none() {
+        loop {
+            skip_spaces(&mut chars);
+            if chars.next() != Some('"') {
+                return Err(String::from("Expected a string key"));
+            }
+            let key = json_string(&mut chars)?;
+            skip_spaces(&mut chars);
+            if chars.next() != Some(':') {
+                return Err(format!("Expected `:` after `{}`", key));
+            }
+            skip_spaces(&mut chars);
+            let value = if chars.next_if_eq(&'"').is_some() {
+                JsonValue::String(json_string(&mut chars)?)
+            } else {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || "+-.".contains(*c)) {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "null" => JsonValue::Null,
+                    "true" => JsonValue::Bool(true),
+                    "false" => JsonValue::Bool(false),
+                    _ => JsonValue::Number(word.parse().map_err(|_| format!("Unsupported value for `{}`", key))?),
+                }
+            };
+            fields.push((key, value));
+            skip_spaces(&mut chars);
+            match chars.next() {
+                Some(',') => continue,
+                Some('}') => break,
+                _ => return Err(String::from("Expected `,` or `}`")),
+            }
+        }
+    }
+    if chars.next().is_some() {
+        return Err(String::from("Unexpected text after the object"));
+    }
+    Ok(fields)
+}
+
+// limits of every `batch` request that doesn't set its own
+const BATCH_MAX_STEPS : u64 = 10_000_000;
+const BATCH_MAX_OUTPUT : u64 = 1 << 20;
+// distinct sources kept parsed, the cache starts over when it is full
+const BATCH_MAX_CACHED : usize = 256;
+
+// runs one line of `batch` input, returning its line of output. Programs are
+// parsed once per distinct source, graders often run one program on many inputs.
+// `stdin` is checked but no word reads it yet
+fn batch_request(line: &str, parsed: &mut HashMap<String, Result<Vec<Instruction>, String>>) -> String {
+    let fields = match parse_flat_json_object(line) {
+        Ok(fields) => fields,
+        Err(err) => return format!("{{\"id\":null,\"error\":\"{}\"}}", json_escape(&err)),
+    };
+    let field = |name: &str| fields.iter().find(|(key, _)| key == name).map(|(_, value)| value);
+    let id = field("id").cloned().unwrap_or(JsonValue::Null).to_json();
+    let error = |err: &str| format!("{{\"id\":{},\"error\":\"{}\"}}", id, json_escape(err));
+    let limit = |name: &str, default: u64| match field(name) {
+        None | Some(JsonValue::Null) => Ok(default),
+        Some(JsonValue::Number(value)) if *value >= 0 => Ok(*value as u64),
+        _ => Err(format!("Expected a non-negative integer `{}`", name)),
+    };
+    let Some(JsonValue::String(source)) = field("source") else {
+        return error("Expected a string `source`");
+    };
+    let stdin = match field("stdin") {
+        None | Some(JsonValue::Null) => "",
+        Some(JsonValue::String(stdin)) => stdin,
+        _ => return error("Expected a string `stdin`"),
+    };
+    let limits = match (limit("max_steps", BATCH_MAX_STEPS), limit("max_output", BATCH_MAX_OUTPUT)) {
+        (Ok(max_steps), Ok(max_output)) => RunLimits { max_steps: Some(max_steps), max_output: Some(max_output as usize), ..RunLimits::default() },
+        (Err(err), _) | (_, Err(err)) => return error(&err),
+    };
+    if parsed.len() >= BATCH_MAX_CACHED && !parsed.contains_key(source) {
+        parsed.clear();
+    }
+    let result = match parsed.entry(source.clone()).or_insert_with(|| parse_source(source)) {
+        Ok(program) => run_instructions(program, stdin.as_bytes(), limits),
+        Err(err) => parse_failure(err),
+    };
+    format!("{{\"id\":{},\"exit_code\":{},\"stdout\":\"{}\",\"stderr\":\"{}\",\"steps\":{}}}",
+        id, result.exit_code, json_escape(&String::from_utf8_lossy(&result.stdout)),
+        json_escape(&String::from_utf8_lossy(&result.stderr)), result.stats.instructions_executed)
+}
+
+// answers every line of JSON requests in `input` with a line of JSON on
+// `output`, flushing after each so a grader can stream requests
+fn batch<R: BufRead, W: Write>(input: R, output: &mut W) {
+    let mut parsed = HashMap::new();
+    for line in input.split(b'\n') {
+        let response = match line.map(String::from_utf8) {
+            Ok(Ok(line)) if line.trim().is_empty() => continue,
+            Ok(Ok(line)) => batch_request(&line, &mut parsed),
+            Ok(Err(_)) => String::from("{\"id\":null,\"error\":\"Expected UTF-8\"}"),
+            Err(err) => {
+                writeln!(output, "{{\"id\":null,\"error\":\"{}\"}}", json_escape(&format!("Could not read input: {}", err))).unwrap();
+                break;
+            },
+        };
+        writeln!(output, "{}", response).unwrap();
+        output.flush().unwrap();
+    }
+}
+
+const SOURCE_MAP_VERSION: u32 = 1;
+
+// maps every `.addr_N` label of the assembly to the location and text of the
+// token it was generated from, as written in the source before struct expansion
+fn source_map_to_json(program : &[Instruction], asm_file : &str) -> String {
+    let mut sources : HashMap<String, HashMap<(usize, usize), String>> = HashMap::new();
+    let mut json = format!("{{\"version\": {}, \"file\": \"{}\", \"mappings\": [", SOURCE_MAP_VERSION, json_escape(asm_file));
+    let mut first = true;
+    for ins in program {
+        let tokens = sources.entry(ins.loc.file.clone()).or_insert_with(|| {
+            std::fs::read_to_string(&ins.loc.file)
+                .map(|source| lex_source(&source, &ins.loc.file).into_iter()
+                    .map(|tok| ((tok.row, tok.col), tok.tok))
+                    .collect())
+                .unwrap_or_default()
+        });
+        let token = tokens.get(&(ins.loc.row, ins.loc.col)).map(String::as_str).unwrap_or("");
+        if !first {
+            json += ", ";
+        }
+        first = false;
+        json += &format!("{{\"label\": \".addr_{}\", \"ip\": {}, \"file\": \"{}\", \"row\": {}, \"col\": {}, \"token\": \"{}\"}}",
+            ins.ip, ins.ip, json_escape(&ins.loc.file), ins.loc.row+1, ins.loc.col+1, json_escape(token));
+    }
+    json += "]}\n";
+    json
+}
+
+// writes the `objdump` disassembly of the executable, with the source line of
+// each instruction above the code generated for it
+fn emit_listing(program : &[Instruction], exec_file : &str, path : &str) {
+    let run_tool = |tool: &str, args: &[&str]| -> String {
+        let output = Command::new(tool)
+            .args(args)
+            .stderr(Stdio::inherit())
+            .output()
+            .unwrap_or_else(|err| {
+                eprintln!("[ERROR] Could not run {}: {}", tool, err);
+                process::exit(1);
+            });
+        if !output.status.success() {
+            eprintln!("[ERROR] {} failed on {}", tool, exec_file);
+            process::exit(1);
+        }
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    // several labels can share an address, objdump only shows one of them,
+    // so the addresses of the `.addr_N` labels are taken from the symbol table
+    let mut labels : Vec<(u64, usize)> = run_tool("nm", &[exec_file]).lines()
+        .filter_map(|line| {
+            let (address, name) = line.split_once(' ')?;
+            let (_, ip) = name.rsplit_once("addr_")?;
+            Some((u64::from_str_radix(address, 16).ok()?, ip.parse().ok()?))
+        })
+        .collect();
+    labels.sort();
+    let mut labels = labels.into_iter().peekable();
+    let mut sources : HashMap<String, Vec<String>> = HashMap::new();
+    let mut last_loc : Option<(String, usize)> = None;
+    let mut listing = String::new();
+    for line in run_tool("objdump", &["-d", "-M", "intel", exec_file]).lines() {
+        // instructions look like `  401000:\tbe 02 00 00 00 \tmov esi,0x2`
+        let address = line.split_once(':')
+            .and_then(|(address, _)| u64::from_str_radix(address.trim(), 16).ok());
+        while let Some(&(label_address, ip)) = labels.peek() {
+            if address.is_none_or(|address| address < label_address) {
+                break;
+            }
+            labels.next();
+            let ins = match program.get(ip) {
+                Some(ins) => ins,
+                None => continue,
+            };
+            let loc = (ins.loc.file.clone(), ins.loc.row);
+            if last_loc.as_ref() == Some(&loc) {
+                continue;
+            }
+            let lines = sources.entry(ins.loc.file.clone()).or_insert_with(|| {
+                std::fs::read_to_string(&ins.loc.file)
+                    .map(|source| source.lines().map(String::from).collect())
+                    .unwrap_or_default()
+            });
+            let text = lines.get(ins.loc.row).map(|text| text.trim()).unwrap_or("");
+            listing += &format!(";; {}:{}: {}\n", ins.loc.file, ins.loc.row+1, text);
+            last_loc = Some(loc);
+        }
+        listing += line;
+        listing += "\n";
+    }
+    std::fs::write(path, listing).unwrap_or_else(|_| panic!("Could not write file {}", path));
+}
+
+// returns the exit code of the program, or 128+N if it was killed by signal N,
+// `run_with` is a command, with its arguments, that runs the program instead
+fn execute(exec_file : &str, prog_args : &[String], run_with : Option<&str>) -> i32 {
+    // a bare name would be looked up in PATH
+    let exec_path = std::path::Path::new(".").join(exec_file);
+    let mut command = match run_with.map(|runner| runner.split_whitespace().collect::<Vec<&str>>()) {
+        Some(runner) if !runner.is_empty() => {
+            let mut command = Command::new(runner[0]);
+            command.args(&runner[1..]).arg(&exec_path);
+            command
+        },
+        _ => Command::new(&exec_path),
+    };
+    let program_status = command
+        .args(prog_args)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .unwrap_or_else(|err| {
+            eprintln!("[ERROR] Could not run {:?}: {}", command.get_program(), err);
+            process::exit(1);
+        });
+
+    match (program_status.code(), program_status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => {
+            eprintln!("[ERROR] {} was terminated by signal {}", exec_file, signal);
+            128 + signal
+        },
+        (None, None) => 1,
+    }
+}
+
+// whether removing tokens left every block with its opening and closing words
+fn blocks_balanced(tokens: &[&str]) -> bool {
+    // the open blocks, with `do` for a `while` that already has one
+    let mut blocks : Vec<&str> = Vec::new();
+    let mut tokens = tokens.iter().peekable();
+    while let Some(&tok) = tokens.next() {
+        match tok {
+            "if" | "while" => blocks.push(tok),
+            "else" if blocks.last() == Some(&"if") => *blocks.last_mut().unwrap() = "else",
+            "do" if blocks.last() == Some(&"while") => *blocks.last_mut().unwrap() = "do",
+            "end" if matches!(blocks.last(), Some(&"if") | Some(&"else") | Some(&"do")) => { blocks.pop(); },
+            "struct" => {
+                // fields and their sizes come in pairs until `end`
+                let body : Vec<&str> = tokens.by_ref().take_while(|&&tok| tok != "end").copied().collect();
+                if body.is_empty() || body.len().is_multiple_of(2) {
+                    return false;
+                }
+            },
+            "var" if tokens.next().is_none_or(|name| is_reserved_name(name) || parse_integer(name).is_ok()) => return false,
+            "else" | "do" | "end" => return false,
+            _ => {},
+        }
+    }
+    blocks.is_empty()
+}
+
+// delta debugging: removes ever smaller chunks of tokens as long as the
+// blocks stay balanced and `interesting` still holds for the result
+fn minimize_source(source: &str, mut interesting: impl FnMut(&str) -> bool) -> String {
+    let tokens = lex_source(source, "");
+    let render = |keep: &[usize]| -> String {
+        let mut text = String::new();
+        for (i, &index) in keep.iter().enumerate() {
+            let tok = &tokens[index];
+            if i > 0 {
+                text += if tokens[keep[i-1]].row == tok.row { " " } else { "\n" };
+            }
+            text += &tok.tok;
+        }
+        text + "\n"
+    };
+    let mut keep : Vec<usize> = (0..tokens.len()).collect();
+    let mut chunks = 2;
+    // whether the last pass down to single tokens removed anything
+    let mut changed = false;
+    while !keep.is_empty() {
+        let chunk = keep.len().div_ceil(chunks);
+        let mut reduced = false;
+        for start in (0..keep.len()).step_by(chunk) {
+            let candidate : Vec<usize> = keep[..start].iter().chain(keep[(start + chunk).min(keep.len())..].iter()).copied().collect();
+            let words : Vec<&str> = candidate.iter().map(|&index| tokens[index].tok.as_str()).collect();
+            if blocks_balanced(&words) && interesting(&render(&candidate)) {
+                keep = candidate;
+                chunks = (chunks - 1).max(2);
+                reduced = true;
+                changed = true;
+                break;
+            }
+        }
+        if !reduced {
+            if chunk == 1 {
+                // removals may have made chunks that failed before removable
+                if !changed {
+                    break;
+                }
+                changed = false;
+                chunks = 2;
+                continue;
+            }
+            chunks = (chunks * 2).min(keep.len());
+        }
+    }
+    render(&keep)
+}
+
+// minimizes `filename` for a predicate command, which is run with the path
+// of each candidate program appended and holds when it exits with 0
+fn minimize_file(filename: &str, command: &[String]) -> Result<String, String> {
+    let source = std::fs::read_to_string(filename).map_err(|_| format!("Could not read file {}", filename))?;
+    let session = BuildSession::new().map_err(|err| format!("Could not create a temporary directory: {}", err))?;
+    let candidate_file = session.path("candidate.rorth");
+    let mut runs = 0;
+    let mut interesting = |candidate: &str| -> bool {
+        runs += 1;
+        std::fs::write(&candidate_file, candidate).is_ok() && Command::new(&command[0])
+            .args(&command[1..])
+            .arg(&candidate_file)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    };
+    if !interesting(&source) {
+        return Err(format!("The predicate does not hold for {}", filename));
+    }
+    let minimized = minimize_source(&source, &mut interesting);
+    println!("[INFO] Ran the predicate {} times", runs);
+    Ok(minimized)
+}
+
+const FUZZ_FEATURES: &[&str] = &["arith", "compare", "bitwise", "if", "while", "var"];
+
+#[derive(Debug, Clone)]
+struct FuzzOptions {
+    seed: u64,
+    size: usize,
+    depth: usize,
+    features: Vec<String>,
+}
+
+impl Default for FuzzOptions {
+    fn default() -> Self {
+        FuzzOptions { seed: 0, size: 20, depth: 2, features: FUZZ_FEATURES.iter().map(|f| f.to_string()).collect() }
+    }
+}
+
+impl FuzzOptions {
+    fn set(&mut self, option: &str, value: &str) -> Result<(), String> {
+        let parse = |value: &str| value.parse::<u64>().map_err(|_| format!("Expected a number for `{}`, got {}", option, value));
+        match option {
+            "--seed" => self.seed = parse(value)?,
+            "--size" => self.size = parse(value)? as usize,
+            "--depth" => self.depth = parse(value)? as usize,
+            "--features" => {
+                self.features = value.split(',').map(String::from).collect();
+                if let Some(feature) = self.features.iter().find(|f| !FUZZ_FEATURES.contains(&f.as_str())) {
+                    return Err(format!("Unknown feature `{}`, expected one of {}", feature, FUZZ_FEATURES.join(", ")));
+                }
+            },
+            _ => return Err(format!("Unknown option `{}`", option)),
+        }
+        Ok(())
+    }
+}
+
+// generates programs that run to completion without errors: blocks leave the
+// stack as they found it, loops count down from a small literal, divisors and
+// shift amounts are masked, and arithmetic results are masked so they can't
+// overflow in later operations
+struct FuzzGen<'a> {
+    options: &'a FuzzOptions,
+    // xorshift64* state
+    state: u64,
+    vars: usize,
+    out: String,
+}
+
+impl FuzzGen<'_> {
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn pick<'b>(&mut self, words: &[&'b str]) -> &'b str {
+        words[self.below(words.len() as u64) as usize]
+    }
+
+    fn has(&self, feature: &str) -> bool {
+        self.options.features.iter().any(|f| f == feature)
+    }
+
+    fn line(&mut self, indent: usize, code: &str) {
+        self.out += &"  ".repeat(indent);
+        self.out += code;
+        self.out += "\n";
+    }
+
+    // `size` statements, then dumps what they left on the stack
+    fn block(&mut self, size: usize, nesting: usize) {
+        let mut depth = 0;
+        for _ in 0..size {
+            depth = self.statement(depth, nesting);
+        }
+        if depth > 0 {
+            self.line(nesting, &vec!["dump"; depth].join(" "));
+        }
+    }
+
+    // returns the stack depth relative to the start of the block after the statement
+    fn statement(&mut self, depth: usize, nesting: usize) -> usize {
+        loop {
+            match self.below(8) {
+                0 | 1 => {
+                    let literal = self.below(201) as i64 - 100;
+                    self.line(nesting, &literal.to_string());
+                    return depth + 1;
+                },
+                2 if depth >= 2 && self.has("arith") => {
+                    let op = self.pick(&["+", "-", "*", "1 bor /", "1 bor mod"]);
+                    self.line(nesting, &format!("{} 1023 band", op));
+                    return depth - 1;
+                },
+                3 if depth >= 2 && self.has("compare") => {
+                    let op = self.pick(&["=", "!=", "<", ">", "<=", ">="]);
+                    self.line(nesting, op);
+                    return depth - 1;
+                },
+                4 if depth >= 2 && self.has("bitwise") => {
+                    let op = self.pick(&["band", "bor", "bxor", "shl 1023 band", "shr 1023 band"]);
+                    self.line(nesting, op);
+                    return depth - 1;
+                },
+                5 if depth >= 1 => {
+                    let op = self.pick(&["dup", "!", "dump"]);
+                    self.line(nesting, op);
+                    return if op == "dup" { depth + 1 } else if op == "!" { depth } else { depth - 1 };
+                },
+                6 if depth >= 1 && nesting < self.options.depth && self.has("if") => {
+                    self.line(nesting, "if");
+                    let size = self.below(4) as usize + 1;
+                    self.block(size, nesting + 1);
+                    if self.below(2) == 0 {
+                        self.line(nesting, "else");
+                        let size = self.below(4) as usize + 1;
+                        self.block(size, nesting + 1);
+                    }
+                    self.line(nesting, "end");
+                    return depth - 1;
+                },
+                7 if nesting < self.options.depth && self.has("while") => {
+                    let count = self.below(5);
+                    self.line(nesting, &format!("{} while dup 0 > do", count));
+                    let size = self.below(4) as usize + 1;
+                    self.block(size, nesting + 1);
+                    self.line(nesting + 1, "1 -");
+                    self.line(nesting, "end dump");
+                    return depth;
+                },
+                7 if self.vars > 0 => {
+                    let var = self.below(self.vars as u64);
+                    if depth >= 1 && self.below(2) == 0 {
+                        self.line(nesting, &format!("v{} !", var));
+                        return depth - 1;
+                    }
+                    self.line(nesting, &format!("v{} @", var));
+                    return depth + 1;
+                },
+                _ => {},
+            }
+        }
+    }
+}
+
+fn fuzz_program(options: &FuzzOptions) -> String {
+    // xorshift gets stuck on a zero state
+    let mut gen = FuzzGen { options, state: options.seed ^ 0x9e3779b97f4a7c15, vars: 0, out: String::new() };
+    gen.out += &format!("// generated by `fuzz-gen --seed {}`\n", options.seed);
+    if gen.has("var") {
+        gen.vars = gen.below(3) as usize + 1;
+        for var in 0..gen.vars {
+            gen.line(0, &format!("var v{}", var));
+        }
+    }
+    gen.block(options.size, 0);
+    gen.out
+}
+
+// options given before the command line, which overrides them by coming
+// later: the `flags` under `[defaults]` in the nearest rorth.toml, then the
+// ones in RORTH_FLAGS
+fn default_flags() -> Result<Vec<String>, String> {
+    let mut flags : Vec<String> = Vec::new();
+    let cwd = env::current_dir().map_err(|err| format!("Could not get the current directory: {}", err))?;
+    if let Some(config) = cwd.ancestors().map(|dir| dir.join("rorth.toml")).find(|config| config.is_file()) {
+        let toml = std::fs::read_to_string(&config)
+            .map_err(|err| format!("Could not read file {}: {}", config.display(), err))?;
+        flags.extend(config_flags(&toml).map_err(|err| format!("{}: {}", config.display(), err))?);
+    }
+    if let Ok(env_flags) = env::var("RORTH_FLAGS") {
+        flags.extend(env_flags.split_whitespace().map(String::from));
+    }
+    Ok(flags)
+}
+
+// reads `flags = ["..", ..]` in the `[defaults]` table of rorth.toml
+fn config_flags(toml: &str) -> Result<Vec<String>, String> {
+    let mut table = "";
+    for (row, line) in toml.lines().enumerate() {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            table = name.trim();
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else { continue };
+        if table != "defaults" || key.trim() != "flags" {
+            continue;
+        }
+        let value = value.trim();
+        let items = value.strip_prefix('[').and_then(|value| value.strip_suffix(']'))
+            .ok_or(format!("{}: Expected an array of strings for `flags`", row+1))?;
+        let mut flags : Vec<String> = Vec::new();
+        let mut chars = items.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    let mut flag = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some('\\') => flag.extend(chars.next()),
+                            Some(c) => flag.push(c),
+                            None => return Err(format!("{}: Unterminated string in `flags`", row+1)),
+                        }
+                    }
+                    flags.push(flag);
+                },
+                ',' => {},
+                c if c.is_whitespace() => {},
+                _ => return Err(format!("{}: Expected an array of strings for `flags`", row+1)),
+            }
+        }
+        return Ok(flags);
+    }
+    Ok(Vec::new())
+}
+
+// creates `dir` with a manifest, an entry point printing a number, an empty
+// tests directory and a .gitignore for the files the compiler leaves behind
+fn init_project(dir: &str) -> Result<(), String> {
+    let path = std::path::Path::new(dir);
+    if path.read_dir().map(|mut entries| entries.next().is_some()).unwrap_or(false) {
+        return Err(format!("Directory {} already exists and is not empty", dir));
+    }
+    let name = path.file_name()
+        .and_then(|name| name.to_str())
+        .ok_or(format!("Invalid project directory {}", dir))?;
+    let files = [
+        ("rorth.toml", format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name)),
+        ("src/main.rorth", String::from("// prints 42\n34 8 + dump\n")),
+        ("tests/.gitkeep", String::new()),
+        (".gitignore", String::from("/out\n*.asm\n*.o\n*.bytecode\n*.lst\n*.asm.map\n")),
+    ];
+    for (file, contents) in files {
+        let file = path.join(file);
+        std::fs::create_dir_all(file.parent().unwrap())
+            .and_then(|_| std::fs::write(&file, contents))
+            .map_err(|err| format!("Could not write file {}: {}", file.display(), err))?;
+    }
+    Ok(())
+}
+
+const SERVE_ADDR: &str = "127.0.0.1:8000";
+// limits of the programs run by the playground
+const SERVE_LIMITS: RunLimits = RunLimits { max_steps: Some(10_000_000), max_output: Some(1 << 20), max_stack_depth: Some(1 << 20) };
+// larger request bodies are refused
+const SERVE_MAX_BODY: usize = 1 << 16;
+// clients taking longer than this to send a request or read the response are dropped
+const SERVE_IO_TIMEOUT_MS: u64 = 5000;
+// connections handled at the same time, the ones after are refused
+const SERVE_MAX_CONNECTIONS: usize = 16;
+
+const PLAYGROUND_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Rorth playground</title></head>
+<body>
+<h1>Rorth playground</h1>
+<textarea id="code" rows="20" cols="80">34 35 + dump</textarea>
+<p><button onclick="run()">Run</button></p>
+<pre id="output"></pre>
+<script>
+function run() {
+    fetch("/run", { method: "POST", body: document.getElementById("code").value })
+        .then(response => response.text())
+        .then(text => { document.getElementById("output").textContent = text; });
+}
+</script>
+</body>
+</html>
+"#;
+
+// serves the playground page on `addr`, each connection in its own thread
+fn serve_playground(addr: &str) {
+    let listener = std::net::TcpListener::bind(addr).unwrap_or_else(|err| {
+        eprintln!("[ERROR] Could not listen on {}: {}", addr, err);
+        process::exit(1);
+    });
+    println!("[INFO] Serving playground on http://{}", addr);
+    let connections = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { continue };
+        let timeout = Some(std::time::Duration::from_millis(SERVE_IO_TIMEOUT_MS));
+        if stream.set_read_timeout(timeout).is_err() || stream.set_write_timeout(timeout).is_err() {
+            continue;
+        }
+        if connections.fetch_add(1, std::sync::atomic::Ordering::SeqCst) >= SERVE_MAX_CONNECTIONS {
+            connections.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+            let _ = stream.write_all(&http_response("503 Service Unavailable", "text/plain; charset=utf-8", "Too many connections\n"));
+            continue;
+        }
+        let connections = connections.clone();
+        std::thread::spawn(move || {
+            let response = match read_http_request(&mut stream) {
+                Ok((method, path, body)) => Some(handle_http_request(&method, &path, &body)),
+                Err(err) if err.kind() == io::ErrorKind::InvalidData =>
+                    Some(http_response("413 Payload Too Large", "text/plain; charset=utf-8", &format!("{}\n", err))),
+                Err(_) => None,
+            };
+            if let Some(response) = response {
+                let _ = stream.write_all(&response);
+            }
+            connections.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+        });
+    }
+}
+
+// returns the method, path and body of a request
+fn read_http_request<R: io::Read>(stream: &mut R) -> io::Result<(String, String, String)> {
+    let mut reader = io::BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("").to_string();
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    if content_length > SERVE_MAX_BODY {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+            format!("Request body of {} bytes is larger than {} bytes", content_length, SERVE_MAX_BODY)));
+    }
+    let mut body = vec![0; content_length];
+    io::Read::read_exact(&mut reader, &mut body)?;
+    Ok((method, path, String::from_utf8_lossy(&body).into_owned()))
+}
+
+fn http_response(status: &str, content_type: &str, body: &str) -> Vec<u8> {
+    format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body).into_bytes()
+}
+
+fn handle_http_request(method: &str, path: &str, body: &str) -> Vec<u8> {
+    match (method, path) {
+        ("GET", "/") => http_response("200 OK", "text/html; charset=utf-8", PLAYGROUND_PAGE),
+        ("POST", "/run") => http_response("200 OK", "text/plain; charset=utf-8", &run_playground(body)),
+        _ => http_response("404 Not Found", "text/plain; charset=utf-8", "Not found\n"),
+    }
+}
+
+// interprets `code` within `SERVE_LIMITS`, so that programs that never
+// terminate or fill the memory don't take the server down
+fn run_playground(code: &str) -> String {
+    let result = run_program_limited(code, b"", SERVE_LIMITS);
+    let mut output = String::from_utf8_lossy(&result.stdout).into_owned();
+    output += &String::from_utf8_lossy(&result.stderr);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    // whether the assembler and linker are installed, if not says the test is skipped
+    fn tools_installed(options : &CompileOptions) -> bool {
+        let missing = missing_tool(options, &env::var_os("PATH").unwrap_or_default());
+        if let Some(err) = &missing {
+            eprintln!("[SKIPPED] {}", err);
+        }
+        missing.is_none()
+    }
+
+    // builds like `compile`, false when the test is skipped
+    fn build_in_test(program : &[Instruction], exec_file : &str, options : &CompileOptions) -> bool {
+        if !tools_installed(options) {
+            return false;
+        }
+        build_executable(program, exec_file, options, &mut Timings::default()).unwrap();
+        true
+    }
+
+    #[test]
+    fn parse_push() {
+        let tokens : Vec<Token> = vec![Token::new(String::from("2"), "", 0, 0)];
+        let program = parser(&tokens);
+        assert_eq!(program[0].opcode, Opcode::OP_PUSH);
+    }
+
+    #[test]
+    fn lex_columns() {
+        let tokens = lex_source("\u{feff}1 2\t+ // sum\r\n  \u{3bb}\u{3bb} dump\r\n", "a.rorth");
+        let words : Vec<(&str, usize, usize)> = tokens.iter()
+            .map(|tok| (tok.tok.as_str(), tok.row, tok.col))
+            .collect();
+        assert_eq!(words, vec![("1", 0, 0), ("2", 0, 2), ("+", 0, 8), ("\u{3bb}\u{3bb}", 1, 2), ("dump", 1, 5)]);
+    }
+
+    #[test]
+    fn parse_integers() {
+        assert_eq!(parse_integer("42"), Ok(42));
+        assert_eq!(parse_integer("-9223372036854775808"), Ok(i64::MIN));
+        assert_eq!(parse_integer("0xff"), Ok(255));
+        assert_eq!(parse_integer("0xFFFFFFFFFFFFFFFF"), Ok(-1));
+        assert_eq!(parse_integer("18446744073709551615"), Err(String::from(
+            "Integer literal `18446744073709551615` does not fit in 64 bits, \
+            the range is -9223372036854775808 to 9223372036854775807, \
+            write it as 0xFFFFFFFFFFFFFFFF to use its 64-bit pattern")));
+        assert_eq!(parse_integer("-99999999999999999999"), Err(String::from(
+            "Integer literal `-99999999999999999999` does not fit in 64 bits, \
+            the range is -9223372036854775808 to 9223372036854775807")));
+        assert!(parse_integer("0x10000000000000000").unwrap_err().contains("does not fit in 64 bits"));
+        assert_eq!(parse_integer("foo"), Err(String::from("Expected integer, got foo")));
+        assert_eq!(parse_integer("0xg"), Err(String::from("Expected integer, got 0xg")));
+    }
+
+    #[test]
+    fn parse_add() {
+        let tokens : Vec<Token> = vec![Token::new(String::from("+"), "", 0, 0)];
+        let program = parser(&tokens);
+        assert_eq!(program[0].opcode, Opcode::OP_ADD);
+    }
+
+    #[test]
+    fn compile_generates_executable() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/arithmetic.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_generates_executable"), &CompileOptions::default()) { return; }
+        assert!(std::path::Path::new(&session.path("test_compile_generates_executable.asm")).exists());
+        assert!(std::path::Path::new(&session.path("test_compile_generates_executable.o")).exists());
+        assert!(std::path::Path::new(&session.path("test_compile_generates_executable")).exists());
+    }
+
+    #[test]
+    fn timings_table() {
+        let mut timings = Timings::default();
+        assert_eq!(timings.time("lex a.rorth", || 42), 42);
+        timings.time("parse", || ());
+        let table = timings.to_table();
+        let phases : Vec<&str> = table.lines().skip(2).map(|line| line.split_whitespace().next().unwrap()).collect();
+        assert_eq!(phases, vec!["lex", "parse", "total"]);
+        assert!(table.starts_with("[INFO] Timings:\nphase      "));
+    }
+
+    #[test]
+    fn progress_lines() {
+        let duration = std::time::Duration::from_micros(2500);
+        assert_eq!(progress_line("parse", duration, Some((1200, "instructions"))),
+            "parse: 1200 instructions in 2.500 ms (480000 instructions/s)");
+        assert_eq!(progress_line("ld", duration, None), "ld: done in 2.500 ms");
+        let program = parser(&lex_source("1 2 +", "a.rorth"));
+        assert_eq!(program_memory(&program), 3 * std::mem::size_of::<Instruction>() + 2 * 8 + 3 * "a.rorth".len());
+    }
+
+    #[test]
+    fn opcode_table_is_in_declaration_order() {
+        for (i, opcode) in Opcode::ALL.iter().enumerate() {
+            assert_eq!(opcode.clone() as usize, i);
+        }
+        assert_eq!(Opcode::ALL.last(), Some(&Opcode::OP_BNOT));
+    }
+
+    #[test]
+    fn defines_are_constants() {
+        let source_files = vec![String::from("tests/defines.rorth")];
+        let defines = vec![parse_define("BUFSIZE=0x1000").unwrap(), parse_define("VERBOSE").unwrap()];
+        let program = load_program(&source_files, &defines, &LexOptions::default(), &mut Timings::default());
+        let mut stdout = Vec::new();
+        interpret(&program, &mut stdout);
+        assert_eq!(String::from_utf8(stdout).unwrap(), "8192\n4096\n");
+        assert_ne!(cache_key(&source_files, &defines, &[]), cache_key(&source_files, &defines[..1], &[]));
+        assert!(parse_define("if=1").is_err());
+        assert!(parse_define("dup=5").is_err());
+        assert!(parse_define("true").is_err());
+        assert!(parse_define("N=x").is_err());
+    }
+
+    #[test]
+    fn cached_program_round_trips() {
+        let source_files = vec![String::from("tests/var.rorth"), String::from("tests/while.rorth")];
+        let session = BuildSession::new().unwrap();
+        let cache_dir = &session.path("cache");
+        let program = load_program(&source_files, &[], &LexOptions::default(), &mut Timings::default());
+        let stored = load_program_cached(&source_files, &[], &LexOptions::default(), cache_dir, &mut Timings::default());
+        let cached = load_program_cached(&source_files, &[], &LexOptions::default(), cache_dir, &mut Timings::default());
+        assert_eq!(_dump_bytecode_to_string(&stored), _dump_bytecode_to_string(&program));
+        assert_eq!(_dump_bytecode_to_string(&cached), _dump_bytecode_to_string(&program));
+        let locs = |program: &[Instruction]| program.iter().map(|ins| ins.loc.clone()).collect::<Vec<Loc>>();
+        assert_eq!(locs(&cached), locs(&program));
+        assert!(program_from_cache("0 255 0 0 0 a.rorth\n").is_none());
+    }
+
+    #[test]
+    fn source_map_links_labels_to_tokens() {
+        let source_file = "tests/struct.rorth";
+        let tokens = expand_structs(lexer(source_file), &[]);
+        let program = parser(&tokens);
+        let json = source_map_to_json(&program, "out.asm");
+        assert!(json.starts_with("{\"version\": 1, \"file\": \"out.asm\", \"mappings\": [{\"label\": \".addr_0\", \"ip\": 0, "));
+        // struct constants keep the name they were written with
+        assert!(json.contains("{\"label\": \".addr_0\", \"ip\": 0, \"file\": \"tests/struct.rorth\", \"row\": 6, \"col\": 1, \"token\": \"Point.x\"}"));
+        assert!(json.contains("\"row\": 8, \"col\": 1, \"token\": \"sizeof(Point)\"}"));
+        assert_eq!(json_escape("a\"b\\c\n"), "a\\\"b\\\\c\\u000a");
+    }
+
+    #[test]
+    fn bytecode_view_shows_jumps_and_tokens() {
+        let source_file = "tests/if.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let view = bytecode_view(&program, None, false);
+        let lines : Vec<&str> = view.lines().take(5).collect();
+        assert_eq!(lines, vec![
+            "  ip  opcode   operands  jump   location              source",
+            "   0  OP_PUSH  420              tests/if.rorth:1:1    420",
+            "   1  OP_PUSH  420              tests/if.rorth:1:5    420",
+            "   2  OP_EQ                     tests/if.rorth:1:9    =",
+            "   3  OP_IF    6         -> 7   tests/if.rorth:1:11   if",
+        ]);
+        assert!(bytecode_view(&program, None, true).contains("\x1b[33m-> 7 \x1b[0m"));
+    }
+
+    #[test]
+    fn bytecode_view_merges_profile() {
+        let session = BuildSession::new().unwrap();
+        let tokens = lex_source("3 while dup 0 > do 1 - end\n", "");
+        let program = parser(&tokens);
+        let counts = interpret_profiling(&program, &mut Vec::new());
+        fs::write(session.path("test_bytecode_view_merges_profile.profile"), profile_to_string(&counts)).unwrap();
+        let counts = read_profile(&session.path("test_bytecode_view_merges_profile.profile"), program.len()).unwrap();
+        assert_eq!(counts, vec![1, 4, 4, 4, 4, 4, 3, 3, 3]);
+        let view = bytecode_view(&program, Some(&counts), false);
+        assert_eq!(view.lines().nth(7).unwrap(), "   6      3  OP_PUSH   1                   :1:20");
+        assert_eq!(view.lines().nth(9).unwrap(), "   8      3  OP_END    1         -> 1 hot  :1:24");
+        assert!(read_profile("./tests/while.rorth", program.len()).is_err());
+    }
+
+    #[test]
+    fn config_flags_from_defaults_table() {
+        let toml = "[package]\nflags = [\"--no\"]\n\n[defaults]\nflags = [\"--runtime-checks\", \"-o\", \"my out\"]\n";
+        assert_eq!(config_flags(toml), Ok(vec![String::from("--runtime-checks"), String::from("-o"), String::from("my out")]));
+        assert_eq!(config_flags("[package]\nname = \"x\"\n"), Ok(Vec::new()));
+        assert!(config_flags("[defaults]\nflags = \"-v\"\n").is_err());
+        assert!(config_flags("[defaults]\nflags = [\"-v]\n").is_err());
+    }
+
+    #[test]
+    fn parse_emit_specs() {
+        assert_eq!(parse_emit_spec("bytecode"), Ok((EmitKind::Bytecode(BytecodeFormat::Text), None)));
+        assert_eq!(parse_emit_spec("bytecode:json=out.json"),
+            Ok((EmitKind::Bytecode(BytecodeFormat::Json), Some(String::from("out.json")))));
+        assert_eq!(parse_emit_spec("bytecode:binary"), Ok((EmitKind::Bytecode(BytecodeFormat::Binary), None)));
+        assert_eq!(parse_emit_spec("listing=out.lst"), Ok((EmitKind::Listing, Some(String::from("out.lst")))));
+        assert_eq!(parse_emit_spec("source-map"), Ok((EmitKind::SourceMap, None)));
+        assert_eq!(parse_emit_spec("layout"), Ok((EmitKind::Layout, None)));
+        assert_eq!(parse_emit_spec("json-ir"), Ok((EmitKind::JsonIr, None)));
+        assert!(parse_emit_spec("bytecode:xml").is_err());
+        assert!(parse_emit_spec("asm").is_err());
+    }
+
+    #[test]
+    fn emit_bytecode_formats() {
+        let tokens : Vec<Token> = vec![Token::new(String::from("2"), "", 0, 0), Token::new(String::from("dump"), "", 0, 1)];
+        let program = parser(&tokens);
+        assert_eq!(bytecode_to_json(&program),
+            "{\"version\": 3, \"instructions\": [{\"ip\": 0, \"opcode\": \"OP_PUSH\", \"operands\": [2]}, \
+            {\"ip\": 1, \"opcode\": \"OP_DUMP\", \"operands\": []}]}\n");
+        let bytes = bytecode_to_binary(&program);
+        assert_eq!(&bytes[0..8], BYTECODE_MAGIC);
+        assert_eq!(bytes.len(), 8 + 4 + 8 + (1 + 8 + 4 + 8) + (1 + 8 + 4));
+    }
+
+    #[test]
+    fn emit_json_ir() {
+        let tokens = lex_source("var n\nstruct P x 8 end\nn @ P.x + if 1 end\n", "a.rorth");
+        let (tokens, constants) = expand_structs_with_constants(tokens, &[(String::from("N"), 3)]).unwrap();
+        let program = parser(&tokens);
+        let json = ir_to_json(&program, &tokens, &constants);
+        let lines : Vec<&str> = json.lines().collect();
+        assert_eq!(lines[0], "{\"version\": 3,");
+        assert!(lines[1].contains("{\"ip\": 4, \"opcode\": \"OP_IF\", \"operands\": [6], \"jump\": 7, \"span\": {\"file\": \"a.rorth\", \"row\": 3, \"col\": 11}}"));
+        assert_eq!(lines[2], " \"globals\": [{\"name\": \"n\", \"index\": 0, \"address\": 268435456, \
+            \"span\": {\"file\": \"a.rorth\", \"row\": 1, \"col\": 5}}],");
+        assert_eq!(lines[3], " \"constants\": [{\"name\": \"N\", \"value\": 3}, {\"name\": \"P.x\", \"value\": 0}, \
+            {\"name\": \"sizeof(P)\", \"value\": 8}]}");
+    }
+
+    #[test]
+    fn highlight_source() {
+        let html = highlight_html("var n // counter\n1 n ! n @ 0 > if 2 end\nstruct P x 8 end P.x\n\t\"a b\" puts\n",
+            &LexOptions::default().line_comments);
+        assert_eq!(html, "<pre class=\"rorth\">\
+            <span class=\"keyword\">var</span> <span class=\"definition\">n</span> <span class=\"comment\">// counter</span>\n\
+            <span class=\"number\">1</span> <span class=\"name\">n</span> <span class=\"word\">!</span> \
+            <span class=\"name\">n</span> <span class=\"word\">@</span> <span class=\"number\">0</span> \
+            <span class=\"word\">&gt;</span> <span class=\"keyword\">if</span> <span class=\"number\">2</span> \
+            <span class=\"keyword\">end</span>\n\
+            <span class=\"keyword\">struct</span> <span class=\"definition\">P</span> <span class=\"definition\">x</span> \
+            <span class=\"number\">8</span> <span class=\"keyword\">end</span> <span class=\"name\">P.x</span>\n\
+            \t<span class=\"word\">\"a b\"</span> <span class=\"word\">puts</span>\n\
+            </pre>\n");
+    }
+
+    #[test]
+    fn minimize_keeps_blocks_balanced() {
+        let source = "var x\n1 2 + dump\n0 if\n  7 dump\nend\n3 4 * dump\n";
+        let minimized = minimize_source(source, |candidate| candidate.contains("7 dump"));
+        assert_eq!(minimized, "if\n7 dump\nend\n");
+        assert!(blocks_balanced(&["1", "while", "dup", "do", "if", "else", "end", "end"]));
+        assert!(!blocks_balanced(&["1", "while", "dup", "end"]));
+        assert!(!blocks_balanced(&["var", "42"]));
+        assert!(!blocks_balanced(&["struct", "P", "x", "end"]));
+    }
+
+    #[test]
+    fn fuzz_programs_run() {
+        for seed in 0..100 {
+            let options = FuzzOptions { seed, size: 30, depth: 3, ..FuzzOptions::default() };
+            let source = fuzz_program(&options);
+            assert_eq!(source, fuzz_program(&options));
+            let program = parser(&lex_source(&source, "fuzz.rorth"));
+            let mut stdout = Vec::new();
+            let stats = interpret_with_stats(&program, &mut stdout);
+            assert!(stats.instructions_executed > 0);
+        }
+        let mut options = FuzzOptions::default();
+        options.set("--features", "arith,if").unwrap();
+        assert!(!fuzz_program(&options).contains("while"));
+        assert!(options.set("--features", "strings").is_err());
+    }
+
+    #[test]
+    fn init_creates_project() {
+        let session = BuildSession::new().unwrap();
+        let dir = &session.path("test_init_creates_project");
+        init_project(dir).unwrap();
+        assert_eq!(fs::read_to_string(format!("{}/rorth.toml", dir)).unwrap(),
+            "[package]\nname = \"test_init_creates_project\"\nversion = \"0.1.0\"\n");
+        assert!(fs::metadata(format!("{}/tests", dir)).unwrap().is_dir());
+        assert!(fs::read_to_string(format!("{}/.gitignore", dir)).unwrap().contains("*.asm\n"));
+        let program = parser(&lexer(&format!("{}/src/main.rorth", dir)));
+        let mut stdout = Vec::new();
+        interpret(&program, &mut stdout);
+        assert_eq!(stdout, b"42\n");
+        assert!(init_project(dir).is_err());
+    }
+
+    #[test]
+    fn serve_routes_requests() {
+        let request = "POST /run HTTP/1.1\r\nHost: localhost\r\nContent-Length: 11\r\n\r\n1 2 + dump\n";
+        let (method, path, body) = read_http_request(&mut request.as_bytes()).unwrap();
+        assert_eq!((method.as_str(), path.as_str(), body.as_str()), ("POST", "/run", "1 2 + dump\n"));
+        let page = String::from_utf8(handle_http_request("GET", "/", "")).unwrap();
+        assert!(page.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(page.ends_with(PLAYGROUND_PAGE));
+        let missing = String::from_utf8(handle_http_request("GET", "/missing", "")).unwrap();
+        assert!(missing.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let large = "POST /run HTTP/1.1\r\nContent-Length: 1000000000\r\n\r\n";
+        assert_eq!(read_http_request(&mut large.as_bytes()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn serve_limits_programs() {
+        assert_eq!(run_playground("1 2 + dump\n"), "3\n");
+        assert_eq!(run_playground(include_str!("../tests/overflow.rorth")),
+            format!("[ERROR] Stopped with more than {} values on the stack\n", 1 << 20));
+        assert_eq!(run_playground("while 1 do end"), "[ERROR] Stopped after 10000000 instructions\n");
+    }
+
+    #[test]
+    fn parse_flat_json_objects() {
+        assert_eq!(parse_flat_json_object(r#" {"a": "x\"\né😀", "b" : -12, "c":null, "d":true} "#), Ok(vec![
+            (String::from("a"), JsonValue::String(String::from("x\"\né😀"))),
+            (String::from("b"), JsonValue::Number(-12)),
+            (String::from("c"), JsonValue::Null),
+            (String::from("d"), JsonValue::Bool(true)),
+        ]));
+        assert_eq!(parse_flat_json_object("{}"), Ok(vec![]));
+        assert_eq!(parse_flat_json_object(r#"{"a": [1]}"#), Err(String::from("Unsupported value for `a`")));
+        assert_eq!(parse_flat_json_object(r#"{"a": "x"#), Err(String::from("Unterminated string")));
+        assert_eq!(parse_flat_json_object(r#"{"a": 1} 2"#), Err(String::from("Unexpected text after the object")));
+    }
+
+    #[test]
+    fn batch_runs_requests() {
+        let mut input = [
+            r#"{"id": 1, "source": "1 2 + dump"}"#,
+            "",
+            r#"{"id": "loop", "source": "while 1 do 7 dump end", "max_steps": 8}"#,
+            r#"{"id": 3, "source": "1 2 + dump", "stdin": "ignored"}"#,
+            r#"{"id": 4, "source": "1 if"}"#,
+            r#"{"id": 5, "source": "while 1 do 7 dump end", "max_output": 3}"#,
+            r#"{"id": 6}"#,
+            "[]",
+        ].join("\n").into_bytes();
+        // a line that isn't UTF-8 fails alone
+        input.extend(b"\n\xff\n");
+        input.extend(br#"{"id": 7, "source": "9223372036854775807 1 + dump"}"#);
+        let mut output : Vec<u8> = Vec::new();
+        batch(&input[..], &mut output);
+        assert_eq!(String::from_utf8(output).unwrap().lines().collect::<Vec<_>>(), [
+            r#"{"id":1,"exit_code":0,"stdout":"3\u000a","stderr":"","steps":4}"#,
+            r#"{"id":"loop","exit_code":124,"stdout":"7\u000a","stderr":"[ERROR] Stopped after 8 instructions\u000a","steps":8}"#,
+            r#"{"id":3,"exit_code":0,"stdout":"3\u000a","stderr":"","steps":4}"#,
+            r#"{"id":4,"exit_code":1,"stdout":"","stderr":"[ERROR] <source>:1:3: @ip 1: Found `if` without matching `end`\u000a","steps":0}"#,
+            r#"{"id":5,"exit_code":124,"stdout":"7\u000a7","stderr":"[ERROR] Stopped after printing 3 bytes\u000a","steps":11}"#,
+            r#"{"id":6,"error":"Expected a string `source`"}"#,
+            r#"{"id":null,"error":"Expected `{`"}"#,
+            r#"{"id":null,"error":"Expected UTF-8"}"#,
+            r#"{"id":7,"exit_code":0,"stdout":"-9223372036854775808\u000a","stderr":"","steps":4}"#,
+        ]);
+        let mut parsed = HashMap::new();
+        for value in 0..BATCH_MAX_CACHED + 10 {
+            batch_request(&format!(r#"{{"source": "{} dump"}}"#, value), &mut parsed);
+        }
+        assert!(parsed.len() <= BATCH_MAX_CACHED);
+    }
+
+    #[test]
+    fn execute_propagates_exit_status() {
+        let session = BuildSession::new().unwrap();
+        fs::write(session.path("test_execute_propagates_exit_status"), "#!/bin/sh\nexit $1\n").unwrap();
+        fs::set_permissions(session.path("test_execute_propagates_exit_status"), fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(execute(&session.path("test_execute_propagates_exit_status"), &[String::from("3")], None), 3);
+        assert_eq!(execute(&session.path("test_execute_propagates_exit_status"), &[String::from("0")], None), 0);
+        assert_eq!(execute(&session.path("test_execute_propagates_exit_status"), &[String::from("4")], Some("sh -e")), 4);
+    }
+
+    #[test]
+    fn debug_modifies_state() {
+        let source_file = "tests/while.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let mut ip = 0;
+        let mut stack : Vec<i64> = vec![1, 2];
+        debug_modify_state("set 0 5", &program, &mut ip, &mut stack).unwrap();
+        debug_modify_state("push 7", &program, &mut ip, &mut stack).unwrap();
+        assert_eq!(stack, vec![5, 2, 7]);
+        debug_modify_state("pop", &program, &mut ip, &mut stack).unwrap();
+        assert_eq!(stack, vec![5, 2]);
+        debug_modify_state("jump 3", &program, &mut ip, &mut stack).unwrap();
+        assert_eq!(ip, 3);
+        debug_modify_state("jump line 6", &program, &mut ip, &mut stack).unwrap();
+        assert_eq!(program[ip].opcode, Opcode::OP_PUSH);
+        assert_eq!(program[ip].operands, vec![420]);
+        assert!(debug_modify_state("set 9 1", &program, &mut ip, &mut stack).is_err());
+        assert!(debug_modify_state("jump 1000", &program, &mut ip, &mut stack).is_err());
+    }
+
+    #[test]
+    fn record_and_replay_trace() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/while.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let mut stdout = Vec::new();
+        let mut trace = Vec::new();
+        interpret_recording(&program, &mut stdout, &mut trace);
+        fs::write(session.path("test_record_and_replay_trace.bin"), &trace).unwrap();
+        let steps = read_trace(&session.path("test_record_and_replay_trace.bin"), &program).unwrap();
+
+        let mut ip = 0;
+        let mut stack : Vec<i64> = Vec::new();
+        let mut globals : Vec<i64> = vec![0; globals_count(&program)];
+        for step in &steps {
+            step.redo(&mut ip, &mut stack, &mut globals).unwrap();
+        }
+        assert_eq!(ip, program.len());
+        assert_eq!(stack, vec![0]);
+        for step in steps.iter().rev() {
+            step.undo(&mut ip, &mut stack, &mut globals).unwrap();
+        }
+        assert_eq!(ip, 0);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn record_and_replay_trace_with_globals() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/var.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let mut stdout = Vec::new();
+        let mut trace = Vec::new();
+        interpret_recording(&program, &mut stdout, &mut trace);
+        fs::write(session.path("test_record_and_replay_trace_with_globals.bin"), &trace).unwrap();
+        let steps = read_trace(&session.path("test_record_and_replay_trace_with_globals.bin"), &program).unwrap();
+
+        let mut ip = 0;
+        let mut stack : Vec<i64> = Vec::new();
+        let mut globals : Vec<i64> = vec![0; globals_count(&program)];
+        for step in &steps {
+            step.redo(&mut ip, &mut stack, &mut globals).unwrap();
+        }
+        assert_eq!(globals, vec![0, 55]);
+        for step in steps.iter().rev() {
+            step.undo(&mut ip, &mut stack, &mut globals).unwrap();
+        }
+        assert_eq!(globals, vec![0, 0]);
+
+        // a trace only replays on the program it was recorded from
+        let other = parser(&lexer("tests/arithmetic.rorth"));
+        assert_eq!(read_trace(&session.path("test_record_and_replay_trace_with_globals.bin"), &other),
+            Err(format!("Trace {} was recorded from a different program", session.path("test_record_and_replay_trace_with_globals.bin"))));
+        let store = steps.iter().find(|step| step.store.is_some()).unwrap();
+        assert_eq!(store.redo(&mut ip, &mut vec![1, 2, 3], &mut []),
+            Err(format!("@ip {}: Step stores to global {} but program has 0", store.ip, store.store.unwrap().0)));
+        assert_eq!(store.redo(&mut ip, &mut Vec::new(), &mut globals),
+            Err(format!("@ip {}: Step removes {} values but stack has 0", store.ip, store.popped.len())));
+    }
+
+    #[test]
+    fn interpret_arithmetic() {
+        let result = run_program(include_str!("../tests/arithmetic.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("69\n420\n4\n5\n"));
+    }
+
+    #[test]
+    fn interpret_multiple_files() {
+        let source_files = vec![String::from("tests/arithmetic.rorth"), String::from("tests/bitwise.rorth")];
+        let tokens = lex_files(&source_files, &LexOptions::default(), &mut Timings::default());
+        assert_eq!(tokens.first().unwrap().file, "tests/arithmetic.rorth");
+        assert_eq!(tokens.last().unwrap().file, "tests/bitwise.rorth");
+        let program = parser(&tokens);
+        let mut stdout = Vec::new();
+        interpret(&program, &mut stdout);
+        assert_eq!(String::from_utf8(stdout).unwrap(),
+            String::from("69\n420\n4\n5\n8\n4\n3\n0\n6\n-6\n15\n2\n"));
+    }
+
+    #[test]
+    fn interpret_division() {
+        let result = run_program(include_str!("../tests/division.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("3\n1\n1\n3\n3\n1\n1\n1\n"));
+    }
+
+    #[test]
+    fn interpret_math() {
+        let result = run_program(include_str!("../tests/math.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("1024\n-27\n1\n0\n0\n3\n4\n3037000499\n6\n6\n7\n0\n"));
+        // the same message as the trap of compiled programs
+        let result = run_program(include_str!("../tests/negative_sqrt.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stderr).unwrap(),
+            String::from("[ERROR] <source>:6:7: @ip 6: Square root of negative number\n"));
+    }
+
+    #[test]
+    fn interpret_bswap() {
+        let result = run_program(include_str!("../tests/bswap.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("13330\n13330\n2018915346\n4294967295\n578437695752307201\n-72057594037927936\n72057594037927936\n"));
+    }
+
+    #[test]
+    fn interpret_bit_counting() {
+        let result = run_program(include_str!("../tests/bits.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("0\n8\n64\n63\n64\n0\n3\n64\n63\n"));
+    }
+
+    #[test]
+    fn interpret_swap() {
+        let result = run_program(include_str!("../tests/swap.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("1\n2\n7\n8\n-1\n"));
+    }
+
+    #[test]
+    fn interpret_radix() {
+        let result = run_program(include_str!("../tests/radix.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("255\n0xff\n0xffffffffffffffff\n0b101\n0b0\n-5\n"));
+        let mut program = parser(&lex_source("10 dump 2 base! 3 dump\n", ""));
+        set_dump_radix(&mut program, 16);
+        let mut stdout = Vec::new();
+        interpret(&program, &mut stdout);
+        assert_eq!(stdout, b"0xa\n0b11\n");
+    }
+
+    #[test]
+    fn interpret_rot() {
+        let result = run_program(include_str!("../tests/rot.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("1\n3\n2\n4 5 6\n"));
+    }
+
+    #[test]
+    fn interpret_pairs() {
+        let result = run_program(include_str!("../tests/pairs.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("1 2 1 2\n5\n3 4 1 2\n1 2 3 4 1 2\n"));
+    }
+
+    #[test]
+    fn parse_extern_proc() {
+        let program = parser(&lexer("tests/extern.rorth"));
+        assert_eq!(program[3].opcode, Opcode::OP_CALL);
+        assert_eq!(program[3].operands[..2], [3, 1]);
+        assert_eq!(unpack_name(&program[3].operands[2..]), "add3");
+        assert_eq!(unpack_name(&pack_name("a_long_proc_name")), "a_long_proc_name");
+        assert_eq!(parse_source("extern proc dup 1 1\n5 dup dump\n").unwrap_err(),
+            "[ERROR] <source>:1:13: Invalid or redefined extern proc name `dup`");
+    }
+
+    #[test]
+    fn interpret_pick_roll() {
+        let result = run_program(include_str!("../tests/pick_roll.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("1 2 3 1\n1 2 3 3\n2 3 1\n1 2 3\n"));
+    }
+
+    #[test]
+    fn interpret_depth() {
+        let result = run_program(include_str!("../tests/depth.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("0\n2\n7 8 9 3\n"));
+    }
+
+    #[test]
+    fn interpret_not() {
+        let result = run_program(include_str!("../tests/not.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("1\n0\n0\n0\n1\n-1\n-6\n0\n"));
+    }
+
+    #[test]
+    fn interpret_store() {
+        let result = run_program(include_str!("../tests/store.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(), String::from("42\n7\n0\n"));
+    }
+
+    #[test]
+    fn builtin_words_are_not_variable_names() {
+        for word in KEYWORDS.iter().chain(BUILTIN_WORDS) {
+            assert!(parse_source(&format!("var {}", word)).is_err(), "{}", word);
+        }
+        assert_eq!(parse_source("var dup").unwrap_err(),
+            "[ERROR] <source>:1:5: Invalid or redefined variable name `dup` after `var`");
+        // every reserved word means something to the parser
+        for word in BUILTIN_WORDS {
+            let program = parse_source(&format!("1 2 3 10 {}", word)).unwrap();
+            assert!(program.len() != 5 || program[4].opcode != Opcode::OP_PUSH || bool_literal(word).is_some(), "{}", word);
+        }
+    }
+
+    #[test]
+    fn interpret_logic() {
+        let result = run_program(include_str!("../tests/logic.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("1\n0\n0\n1\n0\n1\n1\n1\n1\n"));
+    }
+
+    #[test]
+    fn interpret_negative_numbers() {
+        let result = run_program(include_str!("../tests/negative.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("-3\n-1\n-420\n0\n9223372036854775807\n-9223372036854775808\n-5\n5\n-9223372036854775808\n-9223372036854775808\n"));
+    }
+
+    #[test]
+    fn interpret_stderr() {
+        let result = run_program(include_str!("../tests/stderr.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(), String::from("1\n3\n"));
+        assert_eq!(String::from_utf8(result.stderr).unwrap(), String::from("2\n-4\n"));
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.stats.peak_stack_depth, 1);
+    }
+
+    #[test]
+    fn interpret_structs() {
+        let source_file = "tests/struct.rorth";
+        let tokens = expand_structs(lexer(source_file), &[]);
+        let program = parser(&tokens);
+        let mut stdout = Vec::new();
+        interpret(&program, &mut stdout);
+        assert_eq!(String::from_utf8(stdout).unwrap(),
+            String::from("0\n8\n16\n0\n8\n12\n13\n"));
+    }
+
+    #[test]
+    fn interpret_checking_memory_reports_unwritten_reads() {
+        let source_file = "tests/var.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let mut stdout : Vec<u8> = Vec::new();
+        let warnings = interpret_checking_memory(&program, &mut stdout);
+        assert_eq!(stdout, b"0\n55\n0\n0\n268435456\n8\n");
+        assert_eq!(warnings, vec![
+            "[WARNING] tests/var.rorth:5:9: @ip 1: Read of global 0 before it was written",
+            "[WARNING] tests/var.rorth:10:9: @ip 13: Read of global 1 before it was written",
+        ]);
+    }
+
+    #[test]
+    fn interpret_explaining_steps() {
+        let tokens = lex_source("3 4 + dup 7 = if dump end\n", "");
+        let program = parser(&tokens);
+        let (mut stdout, mut explain) = (Vec::new(), Vec::new());
+        interpret_explaining(&program, &mut stdout, &mut explain);
+        assert_eq!(stdout, b"7\n");
+        assert_eq!(String::from_utf8(explain).unwrap().lines().map(str::trim_end).collect::<Vec<_>>(), vec![
+            "   0  push      push 3                            [3]",
+            "   1  push      push 4                            [3 4]",
+            "   2  add       pop 3 and 4, push 7               [7]",
+            "   3  dup       pop 7, push 7 and 7               [7 7]",
+            "   4  push      push 7                            [7 7 7]",
+            "   5  eq        pop 7 and 7, push 1               [7 1]",
+            "   6  if        pop 1, it is true, go on          [7]",
+            "   7  dump      pop 7                             []",
+            "   8  end       nothing changes                   []",
+        ]);
+    }
+
+    #[test]
+    fn interpret_with_stats_counts_usage() {
+        let source_file = "tests/arithmetic.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let mut stdout : Vec<u8> = Vec::new();
+        let stats = interpret_with_stats(&program, &mut stdout);
+        assert_eq!(stats, RunStats { peak_stack_depth: 2, instructions_executed: program.len() as u64 });
+    }
+
+    #[test]
+    fn pure_runs_check_depth_once() {
+        let program = parser(&lex_source("1 2 + dup * dump 3 - 4", "a.rorth"));
+        let runs = pure_runs(&program);
+        assert_eq!(runs[0], Some(PureRun { end: 5, needs: 0 }));
+        assert_eq!(runs[3], Some(PureRun { end: 5, needs: 1 }));
+        assert_eq!(runs[4], None);
+        assert_eq!(runs[5], None);
+        assert_eq!(runs[6], Some(PureRun { end: 9, needs: 1 }));
+        assert_eq!(runs[8], None);
+    }
+
+    #[test]
+    fn interpret_fast_path_matches_slow_path() {
+        for fixture in ["arithmetic", "bitwise", "comparisons", "increment", "logic", "negative", "nested_while", "not", "pairs", "rot", "rotate"] {
+            let source = std::fs::read_to_string(format!("tests/{}.rorth", fixture)).unwrap();
+            let mut stdout = Vec::new();
+            interpret(&parser(&lex_source(&source, "<source>")), &mut stdout);
+            assert_eq!(stdout, run_program(&source, b"").stdout, "{}", fixture);
+        }
+        // both wrap around
+        let source = "9223372036854775807 1 + dump -9223372036854775808 1 - dump 4611686018427387904 4 * dump\n";
+        let mut stdout = Vec::new();
+        interpret(&parser(&lex_source(source, "<source>")), &mut stdout);
+        assert_eq!(stdout, b"-9223372036854775808\n9223372036854775807\n0\n");
+        assert_eq!(stdout, run_program(source, b"").stdout);
+    }
+
+    #[test]
+    fn max_stack_depth_follows_every_path() {
+        let depth = |source: &str| max_stack_depth(&parser(&lex_source(source, "a.rorth")));
+        assert_eq!(depth("1 2 + 3 * dump"), Ok((2, 1)));
+        assert_eq!(depth("1 if 1 2 + else 3 end 4 2dup 3 .q"), Ok((5, 10)));
+        assert_eq!(depth("1 if 1 2 else 3 end dump"), Err(String::from(
+            "[WARNING] a.rorth:1:21: @ip 7: The stack is 1 or 2 values deep here depending on the path, its depth has no static bound")));
+        assert_eq!(depth("depth .q"), Err(String::from(
+            "[WARNING] a.rorth:1:7: @ip 1: `.q` pops a computed number of values, the stack depth has no static bound")));
+        let nested_while = parser(&lexer("tests/nested_while.rorth"));
+        assert_eq!(max_stack_depth(&nested_while), Err(String::from(
+            "[WARNING] tests/nested_while.rorth:1:4: @ip 1: The stack is 1 or 2 values deep here depending on the path, its depth has no static bound")));
+    }
+
+    #[test]
+    fn basic_blocks_follow_jumps() {
+        let tokens = lex_source("while 1 do 0 if 1 else 2 end end\n", "");
+        let program = parser(&tokens);
+        let edges : Vec<(usize, usize, Vec<usize>)> = basic_blocks(&program).into_iter()
+            .map(|block| (block.start, block.end, block.successors))
+            .collect();
+        assert_eq!(edges, vec![
+            (0, 2, vec![3, 10]), (3, 4, vec![5, 7]), (5, 6, vec![9]), (7, 8, vec![9]), (9, 9, vec![0]),
+        ]);
+        assert_eq!(flow(&program[9]), Flow::Jump(0));
+    }
+
+    #[test]
+    fn analyze_weights_nested_loops() {
+        let tokens = lex_source("1 while dup do while 0 do 2 / end 3 * end dump\n", "");
+        let program = parser(&tokens);
+        let regions = analyze_regions(&program);
+        assert_eq!(regions, vec![
+            Region { start: 0, end: 13, depth: 0, iteration_cost: 4631, total_cost: 4631 },
+            Region { start: 1, end: 12, depth: 1, iteration_cost: 458, total_cost: 4580 },
+            Region { start: 4, end: 9, depth: 2, iteration_cost: 45, total_cost: 4500 },
+        ]);
+    }
+
+    #[test]
+    fn block_labels_name_constructs() {
+        let tokens = lex_source("while 1 do 0 if 1 else 2 end end\n", "");
+        let program = parser(&tokens);
+        let labels = block_labels(&program);
+        let mut names : Vec<(&usize, &String)> = labels.iter().collect();
+        names.sort();
+        assert_eq!(names, vec![
+            (&0, &String::from("while_0")), (&2, &String::from("while_0_do")), (&4, &String::from("if_4")),
+            (&6, &String::from("if_4_else")), (&8, &String::from("if_4_end")), (&9, &String::from("while_0_end")),
+        ]);
+    }
+
+    #[test]
+    fn bss_layout_aligns_regions() {
+        let source_file = "tests/var.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let layout = plan_bss(&program, &CompileOptions::default());
+        let offsets : Vec<(&str, usize)> = layout.iter().map(|region| (region.label.as_str(), region.offset)).collect();
+        assert_eq!(offsets, vec![("global_0", 0), ("global_1", 8), ("out_len", 16), ("out_buf", 32), ("signal_stack", 4128)]);
+        assert_eq!(layout_to_string(&layout[..1]), "address     size  label\n0x10000000      8  global_0\n");
+        let unbuffered = CompileOptions { buffer_output: false, ..CompileOptions::default() };
+        assert_eq!(plan_bss(&program, &unbuffered).len(), 3);
+    }
+
+    #[test]
+    fn asm_renders_for_each_assembler() {
+        let mut asm = Asm::default();
+        asm.section(".text");
+        asm.label("start");
+        asm.ins("push rax");
+        asm.ins("pop rax");
+        asm.ins("push QWORD [rax]");
+        asm.ins("pop rbx");
+        asm.bytes("data_0", b"hi\n");
+        asm.reserve("", 8);
+        assert_eq!(asm.finish(AsmSyntax::Nasm),
+            "section .text\nstart:\n    mov rbx, QWORD [rax]\ndata_0: db \"hi\",10\n    resb 8\n");
+        let mut asm = Asm::default();
+        asm.define("SYS_EXIT", 60);
+        asm.ins("mov BYTE [rcx], al");
+        asm.comment("OP_DUMP");
+        assert_eq!(asm.finish(AsmSyntax::Gas),
+            ".intel_syntax noprefix\n.equ SYS_EXIT, 60\n    mov BYTE PTR [rcx], al\n    # OP_DUMP\n");
+        // nasm needs the scope of a local label referred to from another one
+        let table = || {
+            let mut asm = Asm::default();
+            asm.label("start");
+            asm.label(".addr_0");
+            asm.label("table");
+            asm.quads(&[String::from(".addr_0"), String::from("8")]);
+            asm
+        };
+        assert_eq!(table().finish(AsmSyntax::Nasm), "start:\n.addr_0:\ntable:\n    dq start.addr_0,8\n");
+        assert_eq!(table().finish(AsmSyntax::Gas), ".intel_syntax noprefix\nstart:\n.addr_0:\ntable:\n    .quad .addr_0,8\n");
+    }
+
+    #[test]
+    fn schedule_moves_pops_to_their_push() {
+        let mut asm = Asm::default();
+        asm.label(".addr_0");
+        asm.ins("push rax");
+        asm.label(".addr_1");
+        asm.comment("OP_PUSH");
+        asm.ins("push 3");
+        asm.ins("pop rax");
+        asm.ins("pop rbx");
+        asm.ins("mul rbx");
+        asm.ins("push rax");
+        asm.label(".loop");
+        asm.ins("pop rbx");
+        asm.ins("jmp .loop");
+        asm.schedule();
+        assert_eq!(asm.finish(AsmSyntax::Nasm),
+            ".addr_0:\n    mov rbx, rax\n.addr_1:\n    ;; OP_PUSH\n    mov rax, 3\n    mul rbx\n    push rax\n.loop:\n    pop rbx\n    jmp .loop\n");
+        assert_eq!(register_effects("mul rbx"), Some((vec!["rax", "rbx"], vec!["rax", "rdx"])));
+        assert_eq!(register_effects("mov rax, QWORD [rbx]"), None);
+        assert_eq!(register_effects("cmp rax, rbx"), None);
+    }
+
+    #[test]
+    fn data_section_shares_identical_entries() {
+        let mut data = DataSection::default();
+        assert_eq!(data.add(b"Stack underflow\n"), "data_0");
+        assert_eq!(data.add(b"say \"hi\""), "data_1");
+        assert_eq!(data.add(b"Stack underflow\n"), "data_0");
+        assert_eq!(db_operands(b"Stack underflow\n"), "\"Stack underflow\",10");
+        assert_eq!(db_operands(b"say \"hi\""), "\"say \",34,\"hi\",34");
+    }
+
+    #[test]
+    fn interpret_strict_reports_leftovers() {
+        let source_file = "tests/leftover.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let mut stdout = Vec::new();
+        let errors = interpret_strict(&program, &mut stdout);
+        assert_eq!(stdout, b"3\n");
+        assert_eq!(errors, vec![
+            "[ERROR] tests/leftover.rorth:2:5: @ip 2: Value 3 left on the stack",
+            "[ERROR] tests/leftover.rorth:4:5: @ip 7: Value 1 left on the stack",
+        ]);
+    }
+
+    #[test]
+    fn report_errors_caps_and_summarizes() {
+        let errors: Vec<String> = (0..3).map(|i| format!("[ERROR] e{}", i)).collect();
+        assert_eq!(report_errors(&errors, 2), vec![
+            "[ERROR] e0",
+            "[ERROR] e1",
+            "[ERROR] aborting due to 3 previous errors",
+        ]);
+        assert_eq!(report_errors(&errors[..1], 20), vec!["[ERROR] e0", "[ERROR] aborting due to 1 previous error"]);
+        assert!(report_errors(&[], 20).is_empty());
+    }
+
+    #[test]
+    fn interpret_resumes_from_checkpoint() {
+        let source_file = "tests/countdown.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let mut expected = Vec::new();
+        interpret(&program, &mut expected);
+
+        let mut stdout = Vec::new();
+        let mut state = Checkpoint::new(&program);
+        assert!(!interpret_resumable(&program, &mut state, Some(20), &mut stdout));
+        let bytes = state.to_bytes(&program);
+        let mut resumed = Checkpoint::from_bytes(&bytes, &program).unwrap();
+        assert_eq!(resumed, state);
+        assert!(interpret_resumable(&program, &mut resumed, None, &mut stdout));
+        assert_eq!(stdout, expected);
+
+        let other = parser(&lexer("tests/while.rorth"));
+        assert_eq!(Checkpoint::from_bytes(&bytes, &other),
+            Err(String::from("checkpoint was saved by a different program")));
+    }
+
+    #[test]
+    fn run_program_resumes_from_checkpoint() {
+        let source = include_str!("../tests/countdown.rorth");
+        let expected = run_program(source, b"").stdout;
+        let limits = RunLimits { max_steps: Some(20), ..RunLimits::default() };
+        let (first, checkpoint) = run_program_resumable(source, b"", None, limits);
+        assert_eq!(first.exit_code, LIMIT_EXIT_CODE);
+        let checkpoint = checkpoint.unwrap();
+        let (rest, done) = run_program_resumable(source, b"", Some(&checkpoint), RunLimits::default());
+        assert_eq!((rest.exit_code, done), (0, None));
+        assert_eq!([first.stdout, rest.stdout].concat(), expected);
+        let (result, _) = run_program_resumable("1 dump", b"", Some(&checkpoint), RunLimits::default());
+        assert_eq!(String::from_utf8(result.stderr).unwrap(),
+            "[ERROR] Could not resume: checkpoint was saved by a different program\n");
+    }
+
+    #[test]
+    fn interpret_32_bit_words() {
+        let source_file = "tests/bits32.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let mut stdout = Vec::new();
+        interpret(&program, &mut stdout);
+        assert_eq!(String::from_utf8(stdout).unwrap(),
+            String::from("2147483648\n4294967296\n68719476735\n64\n2147483648\n8\n"));
+        let mut stdout = Vec::new();
+        interpret_32(&program, &mut stdout);
+        assert_eq!(String::from_utf8(stdout).unwrap(),
+            String::from("-2147483648\n1\n15\n32\n-2147483648\n4\n"));
+        for (source, inputs) in [("shl", 2), ("popcount", 1), ("1 rotl", 2)] {
+            let program = parser(&lex_source(source, "a.rorth"));
+            let ip = program.len() - 1;
+            let mut stack : Vec<i64> = program[..ip].iter().map(|ins| ins.operands[0]).collect();
+            let err = try_interpret_single_instruction_32(&program, ip, &mut stack, &mut [], &mut Vec::new(), &mut Vec::new());
+            assert_eq!(err, Err(format!("[ERROR] a.rorth:1:{}: @ip {}: Tried to pop {} values but stack has {}",
+                program[ip].loc.col + 1, ip, inputs, ip)));
+        }
+    }
+
+    #[test]
+    fn pragmas_silence_unused_variables() {
+        let tokens = lex_source("var a\n// rorth: allow(unused)\nvar b var c\na dump\n", "pragmas.rorth");
+        assert_eq!(tokens[2].allow, vec!["unused"]);
+        assert_eq!(unused_variables(&tokens), vec!["[WARNING] pragmas.rorth:3:11: Variable `c` is never used"]);
+    }
+
+    #[test]
+    fn interpret_fused_loops() {
+        for source_file in ["tests/while.rorth", "tests/nested_while.rorth", "tests/var.rorth"] {
+            let tokens = lexer(source_file);
+            let program = parser(&tokens);
+            let (mut expected, mut fused) = (Vec::new(), Vec::new());
+            interpret(&program, &mut expected);
+            interpret_fused(&program, &mut fused);
+            assert_eq!(fused, expected, "{}", source_file);
+        }
+    }
+
+    #[test]
+    fn interpret_globals() {
+        let result = run_program(include_str!("../tests/var.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("0\n55\n0\n0\n268435456\n8\n"));
+    }
+
+    #[test]
+    fn interpret_dumpq() {
+        let result = run_program(include_str!("../tests/dumpq.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("1 2 3\n-4\n\n1 2\n42\n"));
+        let result = run_program("1 2 .q", b"");
+        assert_eq!(String::from_utf8(result.stderr).unwrap(),
+            "[ERROR] <source>:1:5: @ip 2: Tried to print 2 values but stack has 1\n");
+    }
+
+    #[test]
+    fn interpret_comparisons() {
+        let result = run_program(include_str!("../tests/comparisons.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("1\n0\n0\n1\n1\n0\n0\n1\n"));
+    }
+
+    #[test]
+    fn interpret_rotate() {
+        let result = run_program(include_str!("../tests/rotate.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("-9223372036854775808\n3\n-1152921504606846961\n5\n"));
+    }
+
+    #[test]
+    fn interpret_increment() {
+        let result = run_program(include_str!("../tests/increment.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("42\n42\n-9223372036854775808\n9223372036854775807\n3\n2\n1\n0\n"));
+    }
+
+    #[test]
+    fn interpret_managed_strings() {
+        let source_file = "tests/strings.rorth";
+        let program = parser(&lexer(source_file));
+        let mut stdout = Vec::new();
+        let heap = interpret_strings(&program, &mut stdout);
+        assert_eq!(String::from_utf8(stdout).unwrap(),
+            String::from("hello, world\n12\nworld\n// not a comment: 42\n0\n"));
+        assert!(heap.slots.len() <= 2 * GC_THRESHOLD, "{} slots", heap.slots.len());
+        assert_eq!(heap.get(global_address(0)), None);
+    }
+
+    #[test]
+    fn lex_string_literals() {
+        let tokens = lex_source("\"a b\" \"say \\\"hi\\\"\" // \"c\"\n\"x // y\" dup\n", "");
+        let words : Vec<&str> = tokens.iter().map(|tok| tok.tok.as_str()).collect();
+        assert_eq!(words, vec!["\"a b\"", "\"say \\\"hi\\\"\"", "\"x // y\"", "dup"]);
+        assert_eq!(parse_string_literal(&tokens[1].tok), Ok(String::from("say \"hi\"")));
+        assert_eq!(parse_string_literal("\"a\\q\""), Err(String::from("Unknown escape `\\q` in string")));
+        assert_eq!(parse_string_literal("\"a"), Err(String::from("Unterminated string")));
+    }
+
+    #[test]
+    fn lex_configured_comments_and_recognizers() {
+        let mut options = LexOptions::default();
+        options.line_comments.extend([String::from("#"), String::from("\\")]);
+        // character literals like 'a' push their code point
+        options.recognize(|word| word.strip_prefix('\'')?.strip_suffix('\'')
+            .filter(|c| c.chars().count() == 1)
+            .map(|c| vec![(c.chars().next().unwrap() as i64).to_string()]));
+        let source = "'a' 1 + # sum\n\\ a Forth comment\n\"a # b\" dump // done\n";
+        let tokens = lex_source_with(source, "", &options);
+        let words : Vec<(&str, usize, usize)> = tokens.iter()
+            .map(|tok| (tok.tok.as_str(), tok.row, tok.col))
+            .collect();
+        assert_eq!(words, vec![("97", 0, 0), ("1", 0, 4), ("+", 0, 6), ("\"a # b\"", 2, 0), ("dump", 2, 8)]);
+        assert_eq!(split_comment("1 # 2", &LexOptions::default().line_comments), ("1 # 2", ""));
+        assert_ne!(cache_key(&[], &[], &options.line_comments), cache_key(&[], &[], &[]));
+        let result = run_program_with("'a' dump # done", b"", &options, RunLimits::default());
+        assert_eq!(result.stdout, b"97\n");
+    }
+
+    #[test]
+    fn read_bytecode_of_version_1() {
+        // written by the build of version 1 from tests/v1_opcodes.rorth
+        let bytes = fs::read("tests/v1_opcodes.bytecode").unwrap();
+        let program = bytecode_from_binary(&bytes, "tests/v1_opcodes.bytecode").unwrap();
+        let source_program = parser(&lexer("tests/v1_opcodes.rorth"));
+        assert_eq!(bytecode_to_binary(&program), bytecode_to_binary(&source_program));
+        let mut stdout = Vec::new();
+        interpret(&program, &mut stdout);
+        assert_eq!(String::from_utf8(stdout).unwrap(), "4\n0\n2\n4\n14\n3\n20\n1\n0\n");
+        let mut future = bytes.clone();
+        future[8] = 9;
+        assert_eq!(bytecode_from_binary(&future, "").err(), Some(String::from("Unsupported bytecode version 9, expected 3 or older")));
+    }
+
+    #[test]
+    fn verify_ir_reports_structural_problems() {
+        for source_file in ["tests/nested_if.rorth", "tests/nested_while.rorth", "tests/strings.rorth", "tests/extern.rorth"] {
+            let program = parser(&lexer(source_file));
+            assert_eq!(verify_program(&program), Vec::<String>::new(), "{}", source_file);
+            let bytes = bytecode_to_binary(&program);
+            let read = bytecode_from_binary(&bytes, source_file).unwrap();
+            assert_eq!(bytecode_to_binary(&read), bytes, "{}", source_file);
+        }
+        // more operands than a u8 counts
+        let program = parser(&lex_source(&format!("\"{}\" puts\n", "a".repeat(2100)), ""));
+        assert!(program[0].operands.len() > 255);
+        let read = bytecode_from_binary(&bytecode_to_binary(&program), "").unwrap();
+        assert_eq!(read[0].operands, program[0].operands);
+        let mut program = parser(&lex_source("1 if 2 else 3 end\n", ""));
+        program[1].operands[0] = 9;
+        program[2].operands.clear();
+        program.push(Instruction::new(Opcode::OP_END, vec![], 6));
+        program.push(Instruction::new(Opcode::OP_WHILE, vec![], 8));
+        assert_eq!(verify_program(&program), vec![
+            "@ip 2: Malformed operands [] for OP_PUSH",
+            "@ip 1: `if` jumps to 9 instead of its `else` at 3",
+            "@ip 6: `end` is in no block it can belong to",
+            "@ip 7: Instruction is numbered 8",
+            "@ip 7: `while` has no `end`",
+        ]);
+        let bytes = bytecode_to_binary(&program);
+        assert_eq!(bytecode_from_binary(&bytes[..bytes.len() - 1], "a.bytecode").err(),
+            Some(format!("Truncated bytecode at byte {}", bytes.len() - 4)));
+        let session = BuildSession::new().unwrap();
+        fs::write(session.path("not_utf8.bytecode"), b"\xff\xfe1 2 +").unwrap();
+        assert_eq!(verify_file(&session.path("not_utf8.bytecode"), &[], &LexOptions::default()),
+            vec!["Neither binary bytecode nor UTF-8 source"]);
+        assert_eq!(verify_file("tests/v1_opcodes.bytecode", &[], &LexOptions::default()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn lint_reports_style_warnings() {
+        let tokens = lexer("tests/lint.rorth");
+        assert_eq!(lint_tokens(&tokens), vec![
+            "[WARNING] tests/lint.rorth:3:3: `swap swap` does nothing [noop]",
+            "[WARNING] tests/lint.rorth:4:3: `dup <` compares a value with itself, always 0 [self-compare]",
+            "[WARNING] tests/lint.rorth:5:1: `if` condition is the constant 1 [constant-condition]",
+            "[WARNING] tests/lint.rorth:8:1: Magic number 1000, give it a name with `-D` or a `var` [magic-number]",
+            "[WARNING] tests/lint.rorth:8:6: `while` condition is the constant 0 [constant-condition]",
+            "[WARNING] tests/lint.rorth:9:1: `if` condition is the constant 2 [constant-condition]",
+            "[WARNING] tests/lint.rorth:12:11: Blocks nested deeper than 4 levels [deep-nesting]",
+            "[WARNING] tests/lint.rorth:21:1: `end` without a matching `if` or `while` [unbalanced]",
+            "[WARNING] tests/lint.rorth:16:5: Variable `unused` is never used",
+        ]);
+    }
+
+    #[test]
+    fn interpret_bitwise() {
+        let result = run_program(include_str!("../tests/bitwise.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("8\n4\n3\n0\n6\n-6\n15\n2\n"));
+    }
+
+    #[test]
+    fn interpret_ifs() {
+        let result = run_program(include_str!("../tests/if.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("1\n42\n42\n0\n42\n"));
+    }
+
+    #[test]
+    fn interpret_nested_ifs() {
+        let result = run_program(include_str!("../tests/nested_if.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("42\n42\n8\n"));
+    }
+
+    #[test]
+    fn interpret_whiles() {
+        let result = run_program(include_str!("../tests/while.rorth"), b"");
+        assert_eq!(String::from_utf8(result.stdout).unwrap(),
+            String::from("10\n9\n8\n7\n6\n5\n4\n3\n2\n1\n420\n"));
+    }
+
+    #[test]
+    fn compile_comparisons() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/comparisons.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_comparisons"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_comparisons"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, b"1\n0\n0\n1\n1\n0\n0\n1\n");
+    }
+
+    #[test]
+    fn compile_bitwise() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/bitwise.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_bitwise"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_bitwise"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, b"8\n4\n3\n0\n6\n-6\n15\n2\n");
+    }
+
+    #[test]
+    fn compile_rotate() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/rotate.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_rotate"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_rotate"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, b"-9223372036854775808\n3\n-1152921504606846961\n5\n");
+    }
+
+    #[test]
+    fn compile_increment() {
+        let session = BuildSession::new().unwrap();
+        let program = parser(&lexer("tests/increment.rorth"));
+        if !build_in_test(&program, &session.path("test_compile_increment"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_increment"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, b"42\n42\n-9223372036854775808\n9223372036854775807\n3\n2\n1\n0\n");
+    }
+
+    #[test]
+    fn compile_embeds_metadata() {
+        let session = BuildSession::new().unwrap();
+        let program = parser(&lexer("tests/arithmetic.rorth"));
+        let options = CompileOptions { schedule: true, source_hash: Some(0x1234), ..CompileOptions::default() };
+        if !build_in_test(&program, &session.path("test_compile_embeds_metadata"), &options) { return; }
+        let bytes = std::fs::read(session.path("test_compile_embeds_metadata")).unwrap();
+        assert_eq!(read_metadata(&bytes), Ok(format!("version={} {}\ntarget=x86_64-linux\nopt=O2\nsource-hash=0000000000001234\n", NAME, VERSION)));
+        assert_eq!(read_metadata(b"1 2 + dump"), Err(String::from("Not a 64-bit little endian ELF file")));
+    }
+
+    #[test]
+    fn compile_ifs() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/if.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_ifs"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_ifs"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, b"1\n42\n42\n0\n42\n");
+    }
+
+    #[test]
+    fn compile_nested_ifs() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/nested_if.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_nested_ifs"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_nested_ifs"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, b"42\n42\n8\n");
+    }
+
+    #[test]
+    fn compile_math() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/math.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_math"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_math"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, "1024\n-27\n1\n0\n0\n3\n4\n3037000499\n6\n6\n7\n0\n".as_bytes());
+    }
+
+    #[test]
+    fn compile_bswap() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/bswap.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_bswap"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_bswap"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, "13330\n13330\n2018915346\n4294967295\n578437695752307201\n-72057594037927936\n72057594037927936\n".as_bytes());
+    }
+
+    #[test]
+    fn compile_bit_counting() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/bits.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_bit_counting"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_bit_counting"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, "0\n8\n64\n63\n64\n0\n3\n64\n63\n".as_bytes());
+    }
+
+    #[test]
+    fn compile_swap() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/swap.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_swap"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_swap"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, "1\n2\n7\n8\n-1\n".as_bytes());
+    }
+
+    #[test]
+    fn compile_radix() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/radix.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_radix"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_radix"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, "255\n0xff\n0xffffffffffffffff\n0b101\n0b0\n-5\n".as_bytes());
+    }
+
+    #[test]
+    fn compile_rot() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/rot.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_rot"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_rot"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, "1\n3\n2\n4 5 6\n".as_bytes());
+    }
+
+    #[test]
+    fn compile_depth() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/depth.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_depth"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_depth"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, "0\n2\n7 8 9 3\n".as_bytes());
+    }
+
+    #[test]
+    fn compile_scheduled_matches_interpreter() {
+        let session = BuildSession::new().unwrap();
+        let options = CompileOptions { schedule: true, ..CompileOptions::default() };
+        let fixtures = ["tests/arithmetic.rorth", "tests/bitwise.rorth", "tests/nested_while.rorth", "tests/pairs.rorth", "tests/rot.rorth"]
+            .map(|file| (file.to_string(), parser(&lexer(file))));
+        let fuzzed = (0..10).map(|seed| {
+            let source = fuzz_program(&FuzzOptions { seed, size: 30, depth: 3, ..FuzzOptions::default() });
+            (format!("fuzz seed {}", seed), parser(&lex_source(&source, "fuzz.rorth")))
+        });
+        for (name, program) in fixtures.into_iter().chain(fuzzed) {
+            let mut stdout = Vec::new();
+            interpret(&program, &mut stdout);
+            if !build_in_test(&program, &session.path("test_compile_scheduled"), &options) { return; }
+            let exec_output = Command::new(session.path("test_compile_scheduled"))
+                .output()
+                .expect("Expected a 0 return code");
+            assert_eq!(exec_output.stdout, stdout, "{}", name);
+        }
+    }
+
+    #[test]
+    fn compile_division() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/division.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let mut stdout = Vec::new();
+        interpret(&program, &mut stdout);
+        if !build_in_test(&program, &session.path("test_compile_division"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_division"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, stdout);
+        assert_eq!(exec_output.stdout, b"3\n1\n1\n3\n3\n1\n1\n1\n");
+    }
+
+    #[test]
+    fn compile_not() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/not.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_not"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_not"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, b"1\n0\n0\n0\n1\n-1\n-6\n0\n");
+    }
+
+    #[test]
+    fn compile_logic() {
+        let session = BuildSession::new().unwrap();
+        let program = parser(&lexer("tests/logic.rorth"));
+        if !build_in_test(&program, &session.path("test_compile_logic"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_logic"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, b"1\n0\n0\n1\n0\n1\n1\n1\n1\n");
+    }
+
+    #[test]
+    fn compile_negative_numbers() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/negative.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let mut stdout = Vec::new();
+        interpret(&program, &mut stdout);
+        if !build_in_test(&program, &session.path("test_compile_negative_numbers"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_negative_numbers"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, stdout);
+    }
+
+    #[test]
+    fn compile_buffered_and_unbuffered_output() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/countdown.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let mut stdout = Vec::new();
+        interpret(&program, &mut stdout);
+        for buffer_output in [true, false] {
+            let options = CompileOptions { buffer_output, ..CompileOptions::default() };
+            if !build_in_test(&program, &session.path("test_compile_buffered_and_unbuffered_output"), &options) { return; }
+            let exec_output = Command::new(session.path("test_compile_buffered_and_unbuffered_output"))
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .output()
+                .expect("Expected a 0 return code");
+            assert_eq!(exec_output.stdout, stdout);
+        }
+    }
+
+    #[test]
+    fn compile_stderr() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/stderr.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_stderr"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_stderr"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, b"1\n3\n");
+        assert_eq!(exec_output.stderr, b"2\n-4\n");
+    }
+
+    #[test]
+    fn compile_runtime_checks() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/underflow.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let options = CompileOptions { runtime_checks: true, ..CompileOptions::default() };
+        if !build_in_test(&program, &session.path("test_compile_runtime_checks"), &options) { return; }
+        let exec_output = Command::new(session.path("test_compile_runtime_checks"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Could not run executable");
+        assert_eq!(exec_output.status.code(), Some(1));
+        assert_eq!(exec_output.stdout, b"1\n");
+        assert_eq!(exec_output.stderr, b"[ERROR] tests/underflow.rorth:3:1: @ip 3: Stack underflow\n");
+    }
+
+    #[test]
+    fn build_session_is_removed_when_dropped() {
+        let session = BuildSession::new().unwrap();
+        let other = BuildSession::new().unwrap();
+        assert_ne!(session.path("out"), other.path("out"));
+        fs::write(session.path("out"), "").unwrap();
+        let dir = session.dir.clone();
+        drop(session);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn external_backends_are_given_the_program() {
+        let session = BuildSession::new().unwrap();
+        let backend_file = session.path("rorth-backend-toy");
+        // saves the request and writes an executable printing 3
+        fs::write(&backend_file, format!("#!/bin/sh\ncat > {}\nprintf '#!/bin/sh\\necho 3\\n' > {}\nchmod +x {}\n",
+            session.path("request.json"), session.path("toy"), session.path("toy"))).unwrap();
+        fs::set_permissions(&backend_file, fs::Permissions::from_mode(0o755)).unwrap();
+        let dir = std::ffi::OsString::from(session.path(""));
+        assert_eq!(find_backend(None, &dir).unwrap().name(), NATIVE_BACKEND);
+        assert_eq!(find_backend(Some("avr"), &dir).err(), Some(String::from(
+            "Unknown backend `avr`, expected x86_64-linux or an executable `rorth-backend-avr` in PATH")));
+        let backend = find_backend(Some("toy"), &dir).unwrap();
+        let program = parser(&lex_source("1 2 + dump\n", "toy.rorth"));
+        let options = CompileOptions { backend: Some(String::from("toy")), ..CompileOptions::default() };
+        backend.build(&program, &session.path("toy"), &options, &mut Timings::default()).unwrap();
+        let request = fs::read_to_string(session.path("request.json")).unwrap();
+        assert_eq!(request, backend_request(&program, &session.path("toy"), &options));
+        assert!(request.starts_with("{\"abi\": 1, \"backend\": \"toy\", "));
+        assert!(request.contains("{\"ip\": 2, \"opcode\": \"OP_ADD\", \"operands\": [], \"jump\": null, \
+            \"span\": {\"file\": \"toy.rorth\", \"row\": 1, \"col\": 5}}"));
+        assert_eq!(Command::new(session.path("toy")).output().unwrap().stdout, b"3\n");
+    }
+
+    #[test]
+    fn missing_tools_are_named() {
+        let gas = CompileOptions { asm_syntax: AsmSyntax::Gas, ..CompileOptions::default() };
+        let nowhere = std::ffi::OsStr::new("/nonexistent");
+        assert!(missing_tool(&CompileOptions::default(), nowhere).unwrap().contains("`nasm`"));
+        assert!(missing_tool(&gas, nowhere).unwrap().contains("`as`"));
+        assert_eq!(missing_tool(&gas, &env::var_os("PATH").unwrap()), None);
+    }
+
+    #[test]
+    fn compile_data_stack() {
+        let session = BuildSession::new().unwrap();
+        let options = CompileOptions { data_stack: Some(PAGE_SIZE), runtime_checks: true, ..CompileOptions::default() };
+        for (source_file, signal) in [("tests/while.rorth", None), ("tests/overflow.rorth", Some(11))] {
+            let tokens = lexer(source_file);
+            let program = parser(&tokens);
+            if !build_in_test(&program, &session.path("test_compile_data_stack"), &options) { return; }
+            let exec_output = Command::new(session.path("test_compile_data_stack"))
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .output()
+                .expect("Could not run executable");
+            assert_eq!(exec_output.status.signal(), signal, "{}", source_file);
+        }
+    }
+
+    #[test]
+    fn compile_reports_crashes() {
+        let session = BuildSession::new().unwrap();
+        let tokens = lexer("tests/null_address.rorth");
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_reports_crashes"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_reports_crashes"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Could not run executable");
+        assert_eq!(exec_output.status.signal(), Some(11));
+        assert_eq!(exec_output.stdout, b"1\n");
+        assert_eq!(String::from_utf8_lossy(&exec_output.stderr),
+            "[ERROR] tests/null_address.rorth:3:3: @ip 3: Runtime crash in OP_LOAD: SIGSEGV\n");
+    }
+
+    #[test]
+    fn division_is_the_same_in_both_backends() {
+        let session = BuildSession::new().unwrap();
+        for source_file in ["tests/division_by_zero.rorth", "tests/division_overflow.rorth"] {
+            let interpreted = run_program(&fs::read_to_string(source_file).unwrap(), b"");
+            let tokens = lexer(source_file);
+            let program = parser(&tokens);
+            if !build_in_test(&program, &session.path("test_division_is_the_same_in_both_backends"), &CompileOptions::default()) { return; }
+            let exec_output = Command::new(session.path("test_division_is_the_same_in_both_backends"))
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .output()
+                .expect("Could not run executable");
+            assert_eq!(exec_output.status.code(), Some(interpreted.exit_code), "{}", source_file);
+            assert_eq!(exec_output.stdout, interpreted.stdout, "{}", source_file);
+            assert_eq!(String::from_utf8_lossy(&exec_output.stderr).replace(source_file, "<source>"), String::from_utf8_lossy(&interpreted.stderr), "{}", source_file);
+        }
+    }
+
+    // exit code, stdout and stderr declared by the `// expect-exit: N`,
+    // `// expect-stdout: LINE` and `// expect-stderr: LINE` comments at the
+    // top of a fixture, None if it has none
+    fn golden_expectations(source: &str) -> Option<(i32, String, String)> {
+        let mut expected : Option<(i32, String, String)> = None;
+        for line in source.lines().take_while(|line| line.starts_with("//")) {
+            let Some((key, value)) = line.trim_start_matches("//").trim_start().split_once(':') else { continue };
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            let (exit_code, stdout, stderr) = expected.get_or_insert((0, String::new(), String::new()));
+            match key {
+                "expect-exit" => *exit_code = value.parse().unwrap(),
+                "expect-stdout" => *stdout += &format!("{}\n", value),
+                "expect-stderr" => *stderr += &format!("{}\n", value),
+                _ => continue,
+            }
+        }
+        expected.filter(|(exit_code, stdout, stderr)| *exit_code != 0 || !stdout.is_empty() || !stderr.is_empty())
+    }
+
+    #[test]
+    fn golden_fixtures_in_both_backends() {
+        let session = BuildSession::new().unwrap();
+        let mut fixtures : Vec<String> = fs::read_dir("tests").unwrap()
+            .map(|entry| entry.unwrap().path().to_string_lossy().into_owned())
+            .filter(|path| path.ends_with(".rorth"))
+            .collect();
+        fixtures.sort();
+        let mut checked = 0;
+        for source_file in &fixtures {
+            let Some((exit_code, stdout, stderr)) = golden_expectations(&fs::read_to_string(source_file).unwrap()) else { continue };
+            let tokens = lexer(source_file);
+            let program = parser(&tokens);
+            let interpreted = run_instructions(&program, b"", RunLimits::default());
+            assert_eq!(interpreted.exit_code, exit_code, "{}", source_file);
+            assert_eq!(String::from_utf8_lossy(&interpreted.stdout), stdout, "{}", source_file);
+            assert_eq!(String::from_utf8_lossy(&interpreted.stderr), stderr, "{}", source_file);
+            let options = CompileOptions { runtime_checks: true, ..CompileOptions::default() };
+            if !build_in_test(&program, &session.path("test_golden_fixtures"), &options) { return; }
+            let exec_output = Command::new(session.path("test_golden_fixtures"))
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .output()
+                .expect("Could not run executable");
+            assert_eq!(exec_output.status.code(), Some(exit_code), "{}", source_file);
+            assert_eq!(String::from_utf8_lossy(&exec_output.stdout), stdout, "{}", source_file);
+            assert_eq!(String::from_utf8_lossy(&exec_output.stderr), stderr, "{}", source_file);
+            checked += 1;
+        }
+        assert!(checked >= 2);
+    }
+
+    #[test]
+    fn compile_runtime_checks_division() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/division_by_zero.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let options = CompileOptions { runtime_checks: true, ..CompileOptions::default() };
+        if !build_in_test(&program, &session.path("test_compile_runtime_checks_division"), &options) { return; }
+        let exec_output = Command::new(session.path("test_compile_runtime_checks_division"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Could not run executable");
+        assert_eq!(exec_output.status.code(), Some(1));
+        assert_eq!(exec_output.stdout, b"2\n");
+        assert_eq!(exec_output.stderr, b"[ERROR] tests/division_by_zero.rorth:6:9: @ip 8: Division by zero\n");
+    }
+
+    #[test]
+    fn compile_runtime_checks_addresses() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/bad_address.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let options = CompileOptions { runtime_checks: true, ..CompileOptions::default() };
+        if !build_in_test(&program, &session.path("test_compile_runtime_checks_addresses"), &options) { return; }
+        let exec_output = Command::new(session.path("test_compile_runtime_checks_addresses"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Could not run executable");
+        assert_eq!(exec_output.status.code(), Some(1));
+        assert_eq!(exec_output.stdout, b"5\n");
+        assert_eq!(exec_output.stderr, b"[ERROR] tests/bad_address.rorth:5:7: @ip 9: Invalid address\n");
+    }
+
+    #[test]
+    fn compile_links_objects() {
+        if !tools_installed(&CompileOptions::default()) {
+            return;
+        }
+        let session = BuildSession::new().unwrap();
+        fs::write(session.path("test_compile_links_objects_extra.asm"), "section .data\nextra: dq 1\n").unwrap();
+        let assembled = Command::new("nasm")
+            .args(["-felf64", &session.path("test_compile_links_objects_extra.asm")])
+            .status()
+            .unwrap();
+        assert!(assembled.success());
+        let source_file = "tests/arithmetic.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let options = CompileOptions { link_objects: vec![session.path("test_compile_links_objects_extra.o")], ..CompileOptions::default() };
+        if !build_in_test(&program, &session.path("test_compile_links_objects"), &options) { return; }
+        let symbols = Command::new("nm").arg(session.path("test_compile_links_objects")).output().unwrap();
+        assert!(String::from_utf8_lossy(&symbols.stdout).lines().any(|line| line.ends_with(" extra")));
+        let exec_output = Command::new(session.path("test_compile_links_objects")).output().unwrap();
+        assert_eq!(exec_output.stdout, b"69\n420\n4\n5\n");
+    }
+
+    #[test]
+    fn compile_extern_proc() {
+        if !tools_installed(&CompileOptions::default()) {
+            return;
+        }
+        let session = BuildSession::new().unwrap();
+        fs::write(session.path("procs.asm"), "section .text\nglobal add3\nglobal nothing\n\
+            add3:\n    lea rax, [rdi+rsi]\n    add rax, rdx\n    ret\nnothing:\n    ret\n").unwrap();
+        let assembled = Command::new("nasm")
+            .args(["-felf64", &session.path("procs.asm")])
+            .status()
+            .unwrap();
+        assert!(assembled.success());
+        let source_file = "tests/extern.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let options = CompileOptions { link_objects: vec![session.path("procs.o")], runtime_checks: true, ..CompileOptions::default() };
+        if !build_in_test(&program, &session.path("test_compile_extern_proc"), &options) { return; }
+        let exec_output = Command::new(session.path("test_compile_extern_proc")).output().unwrap();
+        assert_eq!(exec_output.stdout, b"6\n39\n");
+    }
+
+    #[test]
+    fn compile_emits_listing() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/arithmetic.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let options = CompileOptions { listing: Some(session.path("test_compile_emits_listing.lst")), ..CompileOptions::default() };
+        if !build_in_test(&program, &session.path("test_compile_emits_listing"), &options) { return; }
+        let listing = fs::read_to_string(session.path("test_compile_emits_listing.lst")).unwrap();
+        let annotations : Vec<&str> = listing.lines().filter(|line| line.starts_with(";;")).collect();
+        assert_eq!(annotations, vec![
+            ";; tests/arithmetic.rorth:1: 34 35 + dump",
+            ";; tests/arithmetic.rorth:2: 430 10 - dump",
+            ";; tests/arithmetic.rorth:3: 2 2 * dump",
+            ";; tests/arithmetic.rorth:4: 10 2 / dump",
+        ]);
+        let first = listing.find(";; tests/arithmetic.rorth:1:").unwrap();
+        assert!(listing[first..].lines().nth(1).unwrap().ends_with("push   0x22"));
+    }
+
+    #[test]
+    fn compile_globals_with_gas() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/var.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        let options = CompileOptions { asm_syntax: AsmSyntax::Gas, runtime_checks: true, ..CompileOptions::default() };
+        if !build_in_test(&program, &session.path("test_compile_globals_with_gas"), &options) { return; }
+        let exec_output = Command::new(session.path("test_compile_globals_with_gas"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, b"0\n55\n0\n0\n268435456\n8\n");
+    }
+
+    #[test]
+    fn compile_globals() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/var.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_globals"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_globals"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, b"0\n55\n0\n0\n268435456\n8\n");
+    }
+
+    #[test]
+    fn compile_dumpq() {
+        let session = BuildSession::new().unwrap();
+        let source_file = "tests/dumpq.rorth";
+        let tokens = lexer(source_file);
+        let program = parser(&tokens);
+        if !build_in_test(&program, &session.path("test_compile_dumpq"), &CompileOptions::default()) { return; }
+        let exec_output = Command::new(session.path("test_compile_dumpq"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .expect("Expected a 0 return code");
+        assert_eq!(exec_output.stdout, b"1 2 3\n-4\n\n1 2\n42\n");
+    }
+}